@@ -15,7 +15,7 @@ extern crate embedded_hal;
 
 pub mod wrapper;
 
-#[cfg(feature = "mock")]
+#[cfg(feature = "std")]
 extern crate std;
 
 #[cfg(feature = "mock")]
@@ -61,8 +61,89 @@ pub trait Transactional {
     /// note that outgoing and incoming must have the same length
     //fn transfer(&mut self, outgoing: &[u8], incoming: &mut [u8]) -> Result<(), Self::Error>;
 
+    /// Write chunked splits the output buffer into `chunk` sized pieces, writing each with
+    /// the prefix returned by `prefix_fn` for the offset of that chunk (ie. for paged writes)
+    /// 
+    /// # Panics
+    /// 
+    /// This panics if `chunk` is zero
+    fn spi_write_chunked<F, P>(&mut self, prefix_fn: F, data: &[u8], chunk: usize) -> Result<(), Self::Error> 
+    where
+        F: Fn(usize) -> P,
+        P: AsRef<[u8]>,
+        Self: Sized,
+    {
+        assert!(chunk != 0, "spi_write_chunked chunk size must be non-zero");
+
+        for (n, c) in data.chunks(chunk).enumerate() {
+            self.spi_write(prefix_fn(n * chunk).as_ref(), c)?;
+        }
+        Ok(())
+    }
+
     /// Exec allows 'Transaction' objects to be chained together into a single transaction
     fn spi_exec(&mut self, transactions: &mut [Transaction]) -> Result<(), Self::Error>;
+
+    /// Write read writes the prefix and output buffers then reads into the input buffer
+    /// This is executed as a single `spi_exec` transaction
+    fn spi_write_read(&mut self, prefix: &[u8], out: &[u8], incoming: &mut [u8]) -> Result<(), Self::Error> {
+        self.spi_exec(&mut [Transaction::Write(prefix), Transaction::Write(out), Transaction::Read(incoming)])
+    }
+
+    /// Begin explicitly frames the start of a group of operations (default no-op)
+    /// This must be matched by a following call to `end`
+    fn begin(&mut self) -> Result<(), Self::Error> {
+        Ok(())
+    }
+
+    /// End explicitly frames the end of a group of operations started with `begin` (default no-op)
+    fn end(&mut self) -> Result<(), Self::Error> {
+        Ok(())
+    }
+
+    /// Modify register reads a single byte register, applies `f`, and writes back the result
+    /// The read and write are framed with `begin` / `end` and both use `addr` as the prefix
+    fn modify_register<F>(&mut self, addr: u8, f: F) -> Result<(), Self::Error> 
+    where
+        F: FnOnce(u8) -> u8,
+        Self: Sized,
+    {
+        let mut v = [0u8; 1];
+
+        self.begin()?;
+        self.spi_read(&[addr], &mut v)?;
+        self.spi_write(&[addr], &[f(v[0])])?;
+        self.end()
+    }
+
+    /// Soft reset writes a reset command sequence to the peripheral
+    /// This is distinct from the pin-based `Reset` trait, for devices that reset via a magic command
+    fn soft_reset(&mut self, cmd: &[u8]) -> Result<(), Self::Error> {
+        self.spi_write(cmd, &[])
+    }
+}
+
+/// Async variant of the `Transactional` trait, for use with async HALs
+#[cfg(feature = "async")]
+#[allow(async_fn_in_trait)]
+pub trait AsyncTransactional {
+    type Error;
+
+    /// Read writes the prefix buffer then reads into the input buffer
+    async fn spi_read(&mut self, prefix: &[u8], data: &mut [u8]) -> Result<(), Self::Error>;
+
+    /// Write writes the prefix buffer then writes the output buffer
+    async fn spi_write(&mut self, prefix: &[u8], data: &[u8]) -> Result<(), Self::Error>;
+
+    /// Exec allows 'Transaction' objects to be chained together into a single transaction
+    async fn spi_exec(&mut self, transactions: &mut [Transaction<'_>]) -> Result<(), Self::Error>;
+}
+
+/// WriteStatus trait for half-duplex peripherals that return status bytes during writes
+pub trait WriteStatus: Transactional {
+    /// Write the prefix and output buffers, reading the status returned by the peripheral
+    /// into the status buffer
+    fn spi_write_status(&mut self, prefix: &[u8], data: &[u8], status: &mut [u8]) -> Result<(), Self::Error>;
 }
 
 /// Transaction enum defines possible SPI transactions
@@ -72,6 +153,8 @@ pub enum Transaction<'a> {
     Write(&'a [u8]),
     // Read from the peripheral into the supplied buffer
     Read(&'a mut [u8]),
+    // Delay for the supplied number of milliseconds between operations
+    Delay(u32),
     // Write the first buffer while reading into the second
     // This behaviour is actually just the same as Read
     //Transfer((&'a [u8], &'a mut [u8]))
@@ -101,6 +184,61 @@ pub trait Ready {
     fn get_ready(&mut self) -> Result<PinState, Self::Error>;
 }
 
+/// MultiLane trait for dual / quad SPI peripherals supporting multiple data lanes
+pub trait MultiLane {
+    type Error;
+
+    /// Set the number of data lanes used for following operations
+    fn set_lanes(&mut self, lanes: Lanes) -> Result<(), Self::Error>;
+}
+
+/// Identify trait for peripherals with a chip identification (WHO_AM_I) register
+/// This provides default `read_id` and `verify_id` implementations over `Transactional::spi_read`
+pub trait Identify<SpiError, PinError>: Transactional<Error = Error<SpiError, PinError>> {
+    /// Prefix written to read the identification register
+    const ID_PREFIX: &'static [u8];
+
+    /// Read the chip identifier
+    fn read_id(&mut self) -> Result<u8, Self::Error> {
+        let mut id = [0u8; 1];
+        self.spi_read(Self::ID_PREFIX, &mut id)?;
+        Ok(id[0])
+    }
+
+    /// Read the chip identifier and check it matches the expected value,
+    /// returning `Error::UnexpectedId` with the received identifier on mismatch
+    fn verify_id(&mut self, expected: u8) -> Result<(), Self::Error> {
+        match self.read_id()? {
+            id if id == expected => Ok(()),
+            id => Err(Error::UnexpectedId(id)),
+        }
+    }
+}
+
+/// BurstRead trait for peripherals supporting multi-register reads with address auto-increment
+/// This provides a default `burst_read` implementation over `Transactional::spi_read`
+pub trait BurstRead: Transactional {
+    /// Bit set in the register address to enable auto-increment
+    const AUTO_INCREMENT: u8 = 0x80;
+
+    /// Read consecutive registers starting at `start` into the provided buffer
+    /// This is executed as a single `spi_read` (without de-asserting CS)
+    fn burst_read(&mut self, start: u8, buf: &mut [u8]) -> Result<(), Self::Error> {
+        self.spi_read(&[Self::AUTO_INCREMENT | start], buf)
+    }
+}
+
+/// PowerMode trait for peripherals with sleep / standby / active mode commands
+/// This provides a default `set_mode` implementation over `Transactional::spi_write`
+pub trait PowerMode: Transactional {
+    /// Fetch the command word used to enter a mode
+    fn mode_command(mode: Mode) -> u8;
+
+    /// Enter the provided power mode
+    fn set_mode(&mut self, mode: Mode) -> Result<(), Self::Error> {
+        self.spi_write(&[Self::mode_command(mode)], &[])
+    }
+}
 
 /// Error type combining SPI and Pin errors for utility
 #[derive(Debug, Clone, PartialEq)]
@@ -108,6 +246,34 @@ pub enum Error<SpiError, PinError> {
     Spi(SpiError),
     Pin(PinError),
     Aborted,
+    UnexpectedId(u8),
+}
+
+impl <SpiError: core::fmt::Debug, PinError: core::fmt::Debug> core::fmt::Display for Error<SpiError, PinError> {
+    fn fmt(&self, f: &mut core::fmt::Formatter) -> core::fmt::Result {
+        match self {
+            Error::Spi(e) => write!(f, "SPI error: {:?}", e),
+            Error::Pin(e) => write!(f, "pin error: {:?}", e),
+            Error::Aborted => write!(f, "transaction aborted"),
+            Error::UnexpectedId(id) => write!(f, "unexpected device id: 0x{:02x}", id),
+        }
+    }
+}
+
+/// Standard error support, returning the wrapped SPI or pin error as the source
+#[cfg(feature = "std")]
+impl <SpiError, PinError> std::error::Error for Error<SpiError, PinError> 
+where
+    SpiError: std::error::Error + 'static,
+    PinError: std::error::Error + 'static,
+{
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::Spi(e) => Some(e),
+            Error::Pin(e) => Some(e),
+            _ => None,
+        }
+    }
 }
 
 /// PinState enum used for busy indication
@@ -116,3 +282,50 @@ pub enum PinState {
     Low,
     High
 }
+
+impl PinState {
+    /// Fetch the opposite pin state
+    pub fn toggle(self) -> PinState {
+        match self {
+            PinState::Low => PinState::High,
+            PinState::High => PinState::Low,
+        }
+    }
+}
+
+impl From<bool> for PinState {
+    fn from(high: bool) -> Self {
+        if high { PinState::High } else { PinState::Low }
+    }
+}
+
+impl From<PinState> for bool {
+    fn from(state: PinState) -> Self {
+        state == PinState::High
+    }
+}
+
+impl core::fmt::Display for PinState {
+    fn fmt(&self, f: &mut core::fmt::Formatter) -> core::fmt::Result {
+        match self {
+            PinState::Low => write!(f, "Low"),
+            PinState::High => write!(f, "High"),
+        }
+    }
+}
+
+/// Lanes enum used for multi-lane (dual / quad) SPI
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Lanes {
+    Single,
+    Dual,
+    Quad,
+}
+
+/// Mode enum used for power mode transitions
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Mode {
+    Sleep,
+    Standby,
+    Active,
+}
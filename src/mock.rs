@@ -1,58 +1,306 @@
 
-use std::{panic, vec};
+use std::{panic, vec, fmt, format};
 use std::vec::Vec;
-use std::sync::{Arc, Mutex};
+use std::boxed::Box;
+use std::string::{String, ToString};
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::collections::{HashMap, VecDeque};
+use std::ops::RangeBounds;
+use std::borrow::Borrow;
 
-use crate::{Transaction, Transactional, Busy, Ready, Reset, PinState, Error};
+use crate::{Transaction, Transactional, WriteStatus, Busy, Ready, Reset, MultiLane, PowerMode, Lanes, Mode, PinState, Error};
 
 use embedded_hal::blocking::spi;
 use embedded_hal::digital::v2;
-use embedded_hal::blocking::delay::DelayMs;
+use embedded_hal::blocking::delay::{DelayMs, DelayUs};
+
+#[cfg(feature = "fuzz")]
+use rand_core::{RngCore, SeedableRng};
+#[cfg(feature = "fuzz")]
+use rand_xorshift::XorShiftRng;
+
+mod dsl;
+pub use dsl::{parse_expectations, format_expectations, ParseError};
+
+mod template;
+pub use template::Template;
+
+mod codegen;
 
 /// Base mock type
-pub struct Mock {
-    inner: Arc<Mutex<Inner>>,
-    count: Id,
+/// This is generic over the SPI word type `W`, defaulting to bytes
+/// 
+/// Handles only implement the `spi::Transfer` and `spi::Write` traits for the mock word type,
+/// so mixing word sizes against the same peripheral is caught at compile time:
+/// 
+/// ```compile_fail
+/// use embedded_hal::blocking::spi::Transfer;
+/// use embedded_spi::mock::Mock;
+/// 
+/// let mut m = Mock::new();
+/// let mut s = m.spi();
+/// 
+/// // `Mock::new` creates a `Mock<u8>`, which does not support `u16` transfers
+/// s.transfer(&mut [0x0102u16]).unwrap();
+/// ```
+/// 
+/// Without expectations (or after they are exhausted) the mock runs permissively,
+/// recording actual transactions, returning default pin states (`false` / `PinState::Low`) and leaving
+/// read buffers unchanged unless configured otherwise (see `strict_polls`, `push_read_data` and `loopback`).
+/// Finalising a mock with no expectations fails if any transactions were recorded.
+/// 
+/// Mocks with expectations are finalised automatically on drop unless already finalised (or
+/// `skip_finalise` is set), so an early return from a test cannot skip the check. Mocks without
+/// expectations are only recording, and are not finalised on drop. Nor is this applied when the
+/// thread is already panicking, to avoid masking the original failure.
+pub struct Mock<W: Word = u8> {
+    inner: Arc<Mutex<Inner<W>>>,
+    handles: Vec<HandleKind>,
+}
+
+/// Kinds of handles minted from a mock, in Id order
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum HandleKind {
+    Spi,
+    Pin,
+    Delay,
+}
+
+/// Handle minted from a mock, see `Mock::duplicate`
+#[derive(Clone, Debug)]
+pub enum Handle<W = u8> {
+    Spi(Spi<W>),
+    Pin(Pin<W>),
+    Delay(Delay<W>),
+}
+
+/// Mock device, bundling an SPI peripheral with its chip select and signal pins
+/// Pins are only present where requested from the `DeviceBuilder`, see `Mock::device`
+#[derive(Clone, Debug)]
+pub struct Device<W = u8> {
+    pub spi: Spi<W>,
+    pub cs: Option<Pin<W>>,
+    pub busy: Option<Pin<W>>,
+    pub ready: Option<Pin<W>>,
+    pub reset: Option<Pin<W>>,
+}
+
+/// Builder for a mock `Device`
+/// Ids are allocated on `build` in a fixed order (spi, cs, busy, ready, reset),
+/// regardless of the order in which pins are requested. Pins are named by role and Id
+/// (eg. `busy2`) for failure messages.
+pub struct DeviceBuilder<'a, W: Word = u8> {
+    mock: &'a mut Mock<W>,
+    cs: bool,
+    busy: bool,
+    ready: bool,
+    reset: bool,
+}
+
+impl <'a, W: Word> DeviceBuilder<'a, W> {
+    /// Add a chip select pin to the device
+    pub fn cs(mut self) -> Self {
+        self.cs = true;
+        self
+    }
+
+    /// Add a busy pin to the device
+    pub fn busy(mut self) -> Self {
+        self.busy = true;
+        self
+    }
+
+    /// Add a ready pin to the device
+    pub fn ready(mut self) -> Self {
+        self.ready = true;
+        self
+    }
+
+    /// Add a reset pin to the device
+    pub fn reset(mut self) -> Self {
+        self.reset = true;
+        self
+    }
+
+    /// Allocate the device peripherals
+    pub fn build(self) -> Device<W> {
+        let m = self.mock;
+        let spi = m.spi();
+
+        let mut role = |used: bool, role: &str| if used { Some(m.pin_named(&format!("{}{}", role, m.handles.len()))) } else { Option::None };
+
+        let cs = role(self.cs, "cs");
+        let busy = role(self.busy, "busy");
+        let ready = role(self.ready, "ready");
+        let reset = role(self.reset, "reset");
+
+        Device{ spi, cs, busy, ready, reset }
+    }
+}
+
+/// Builder for a sequence of expectations, see `Mock::build`
+/// Each method appends the matching `MockTransaction`, with `finish` registering the
+/// sequence on the mock via `Mock::expect`.
+pub struct MockBuilder<'a, W: Word = u8> {
+    mock: &'a mut Mock<W>,
+    txns: Vec<MockTransaction<W>>,
+}
+
+impl <'a, W: Word> MockBuilder<'a, W> {
+    /// Append an arbitrary transaction
+    pub fn push(mut self, t: MockTransaction<W>) -> Self {
+        self.txns.push(t);
+        self
+    }
+
+    pub fn spi_write<A: AsRef<[W]>, B: AsRef<[W]>>(self, spi: &Spi<W>, prefix: A, outgoing: B) -> Self {
+        self.push(MockTransaction::spi_write(spi, prefix, outgoing))
+    }
+
+    pub fn spi_read<A: AsRef<[W]>, B: AsRef<[W]>>(self, spi: &Spi<W>, prefix: A, incoming: B) -> Self {
+        self.push(MockTransaction::spi_read(spi, prefix, incoming))
+    }
+
+    pub fn spi_exec<O: AsRef<[MockExec<W>]>>(self, spi: &Spi<W>, ops: O) -> Self {
+        self.push(MockTransaction::spi_exec(spi, ops))
+    }
+
+    pub fn write<B: AsRef<[W]>>(self, spi: &Spi<W>, outgoing: B) -> Self {
+        self.push(MockTransaction::write(spi, outgoing))
+    }
+
+    pub fn transfer<B: AsRef<[W]>>(self, spi: &Spi<W>, outgoing: B, incoming: B) -> Self {
+        self.push(MockTransaction::transfer(spi, outgoing, incoming))
+    }
+
+    pub fn busy(self, spi: &Spi<W>, value: PinState) -> Self {
+        self.push(MockTransaction::busy(spi, value))
+    }
+
+    pub fn ready(self, spi: &Spi<W>, value: PinState) -> Self {
+        self.push(MockTransaction::ready(spi, value))
+    }
+
+    pub fn reset(self, spi: &Spi<W>, value: PinState) -> Self {
+        self.push(MockTransaction::reset(spi, value))
+    }
+
+    pub fn is_high(self, pin: &Pin<W>, value: bool) -> Self {
+        self.push(MockTransaction::is_high(pin, value))
+    }
+
+    pub fn is_low(self, pin: &Pin<W>, value: bool) -> Self {
+        self.push(MockTransaction::is_low(pin, value))
+    }
+
+    pub fn set_high(self, pin: &Pin<W>) -> Self {
+        self.push(MockTransaction::set_high(pin))
+    }
+
+    pub fn set_low(self, pin: &Pin<W>) -> Self {
+        self.push(MockTransaction::set_low(pin))
+    }
+
+    pub fn delay_ms(self, v: u32) -> Self {
+        self.push(MockTransaction::delay_ms(v))
+    }
+
+    pub fn delay_us(self, v: u32) -> Self {
+        self.push(MockTransaction::delay_us(v))
+    }
+
+    /// Append a named user marker, see `Mock::mark`
+    pub fn user(self, name: &str) -> Self {
+        self.push(MockTransaction::user(name))
+    }
+
+    /// Register the accumulated expectations on the mock
+    pub fn finish(self) {
+        self.mock.expect(self.txns);
+    }
 }
 
+/// Byte-oriented mock type
+pub type ByteMock = Mock<u8>;
+
 pub type Id = u32;
 
+/// Word types supported by the mock
+pub trait Word: Copy + fmt::Debug + PartialEq + Send + 'static {}
+
+impl <T> Word for T where T: Copy + fmt::Debug + PartialEq + Send + 'static {}
+
 /// Mock Transactional SPI implementation
+/// Clones share the Id of the original, so record against the same peripheral
 #[derive(Clone, Debug)]
-pub struct Spi {
+pub struct Spi<W = u8> {
     id: Id,
-    inner: Arc<Mutex<Inner>>,
+    inner: Arc<Mutex<Inner<W>>>,
+}
+
+impl <W> Spi<W> {
+    /// Fetch the Id of this peripheral
+    pub fn id(&self) -> Id {
+        self.id
+    }
 }
 
 /// Mock Pin implementation
+/// Clones share the Id of the original, so record against the same peripheral
 #[derive(Clone, Debug)]
-pub struct Pin {
+pub struct Pin<W = u8> {
     id: Id,
-    inner: Arc<Mutex<Inner>>,
+    inner: Arc<Mutex<Inner<W>>>,
+}
+
+impl <W> Pin<W> {
+    /// Fetch the Id of this peripheral
+    pub fn id(&self) -> Id {
+        self.id
+    }
 }
 
 /// Mock Delay implementation
+/// Clones share the Id of the original, so record against the same peripheral
 #[derive(Clone, Debug)]
-pub struct Delay {
+pub struct Delay<W = u8> {
     id: Id,
-    inner: Arc<Mutex<Inner>>,
+    inner: Arc<Mutex<Inner<W>>>,
+}
+
+impl <W> Delay<W> {
+    /// Fetch the Id of this peripheral
+    pub fn id(&self) -> Id {
+        self.id
+    }
 }
 
 
 /// Mock transaction type for setting and checking expectations
 #[derive(Clone, Debug, PartialEq)]
-pub enum MockTransaction {
+pub enum MockTransaction<W = u8> {
     None,
-    SpiWrite(Id, Vec<u8>, Vec<u8>),
-    SpiRead(Id, Vec<u8>, Vec<u8>),
-    SpiExec(Id, Vec<MockExec>),
+    SpiWrite(Id, Vec<W>, Vec<W>),
+    SpiWriteAny(Id, Vec<W>),
+    SpiWriteStatus(Id, Vec<W>, Vec<W>, Vec<W>),
+    SpiRead(Id, Vec<W>, Vec<W>),
+    SpiReadDummy(Id, Vec<W>, usize, Vec<W>),
+    SpiExec(Id, Vec<MockExec<W>>),
+    SpiDevice(Id, Vec<MockOp<W>>),
+    BeginTransaction(Id),
+    EndTransaction(Id),
+    SetLanes(Id, Lanes),
 
     Busy(Id, PinState),
     Ready(Id, PinState),
     Reset(Id, PinState),
 
-    Write(Id, Vec<u8>),
-    Transfer(Id, Vec<u8>, Vec<u8>),
+    Write(Id, Vec<W>),
+    WriteAny(Id),
+    Transfer(Id, Vec<W>, Vec<W>),
+    TransferTx(Id, Vec<W>),
+    TransferAbort(Id, Vec<W>, Vec<W>, MockError),
+    WriteRead(Id, Vec<W>, Vec<W>),
 
     IsHigh(Id, bool),
     IsLow(Id, bool),
@@ -60,35 +308,276 @@ pub enum MockTransaction {
     SetLow(Id),
 
     DelayMs(u32),
+    DelayUs(u32),
+    DelayMsAtLeast(u32),
+    DelayMsAtMost(u32),
+    DelayMsRange(u32, u32),
+
+    Inject(Id, Option<Box<MockTransaction<W>>>, Error<MockError, MockError>),
+
+    User(String),
+}
+
+/// Mapping from a driver command type to the transactions it should produce,
+/// for use with `Mock::expect_commands`
+pub trait IntoExpectations<W = u8> {
+    /// Build the expected transactions for this command on the provided SPI peripheral
+    fn into_expectations(self, spi: Id) -> Vec<MockTransaction<W>>;
+}
+
+/// Kind of a `MockTransaction`, without the associated data
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum TxnKind {
+    None,
+    SpiWrite,
+    SpiWriteAny,
+    SpiWriteStatus,
+    SpiRead,
+    SpiReadDummy,
+    SpiExec,
+    SpiDevice,
+    BeginTransaction,
+    EndTransaction,
+    SetLanes,
+
+    Busy,
+    Ready,
+    Reset,
+
+    Write,
+    WriteAny,
+    Transfer,
+    TransferTx,
+    TransferAbort,
+    WriteRead,
+
+    IsHigh,
+    IsLow,
+    SetHigh,
+    SetLow,
+
+    DelayMs,
+    DelayUs,
+    DelayMsAtLeast,
+    DelayMsAtMost,
+    DelayMsRange,
+
+    Inject,
+
+    User,
+}
+
+/// Mock error type, injected into the next operation on a peripheral with `MockTransaction::Inject`
+#[derive(Clone, Debug, PartialEq)]
+pub enum MockError {
+    Bus,
+    Gpio,
+    Timeout,
+}
+
+impl fmt::Display for MockError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            MockError::Bus => write!(f, "mock bus error"),
+            MockError::Gpio => write!(f, "mock gpio error"),
+            MockError::Timeout => write!(f, "mock timeout"),
+        }
+    }
+}
+
+impl std::error::Error for MockError {}
+
+/// Options controlling which aspects of transactions are compared, see `Mock::finalise_with`
+/// The default options compare transactions as for `Mock::finalise`
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct MatchOptions {
+    /// Ignore peripheral Ids
+    pub ignore_ids: bool,
+    /// Strip delays from both the expected and actual transactions
+    pub ignore_delays: bool,
+    /// Compare writes by concatenated prefix and data, see `Mock::finalise_concat_prefix`
+    pub concat_prefix: bool,
+    /// Match expected writes with any data, as for `MockTransaction::spi_write_any` and `write_any`
+    pub data_wildcards: bool,
+}
+
+/// Failure of a protocol check, see `Mock::run_checks`
+#[derive(Clone, Debug, PartialEq)]
+pub struct CheckFailure {
+    /// Machine-readable failure code (eg. `alternating`)
+    pub code: &'static str,
+    /// Index of the actual transaction at which the check failed
+    pub index: usize,
+    /// Description of the failure
+    pub message: String,
 }
 
-impl MockTransaction {
+impl fmt::Display for CheckFailure {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.message)
+    }
+}
+
+/// Result of a single protocol check
+pub type CheckResult = Result<(), CheckFailure>;
+
+/// Protocol checks that can be batched with `Mock::run_checks`
+/// Each corresponds to the `Mock::assert_*` method of the same name
+#[derive(Clone, Debug, PartialEq)]
+pub enum Check<W = u8> {
+    /// Peripheral, write prefix, read prefix
+    WriteBeforeRead(Id, Vec<W>, Vec<W>),
+    /// Chip select, from index, to index
+    NoCsReleaseBetween(Id, usize, usize),
+    /// Chip selects a and b
+    NoInterleave(Id, Id),
+    /// Peripheral
+    Alternating(Id),
+    /// Chip select, maximum words
+    MaxBytesPerWindow(Id, usize),
+    /// Write protect pin, peripheral
+    WpDeassertedDuringWrites(Id, Id),
+    /// Chip select, peripheral, setup ms, hold ms
+    CsSetupHold(Id, Id, u32, u32),
+    /// Peripheral, command prefix, status prefix
+    CommandThenPoll(Id, Vec<W>, Vec<W>),
+    /// Peripheral, number of polls
+    BusyPolls(Id, usize),
+    /// Peripheral, minimum retries
+    Retried(Id, usize),
+    /// Peripheral, total words read
+    TotalRead(Id, usize),
+    /// Transaction kind, minimum ms
+    MinDelayBetween(TxnKind, u32),
+}
+
+/// `()`-based error type for simple tests that do not check error payloads
+pub type UnitError = Error<(), ()>;
+
+impl From<Error<MockError, MockError>> for UnitError {
+    fn from(e: Error<MockError, MockError>) -> Self {
+        match e {
+            Error::Spi(_) => Error::Spi(()),
+            Error::Pin(_) => Error::Pin(()),
+            Error::Aborted => Error::Aborted,
+            Error::UnexpectedId(id) => Error::UnexpectedId(id),
+        }
+    }
+}
+
+impl <W: Word> MockTransaction<W> {
 
-    pub fn spi_write<A, B>(spi: &Spi, prefix: A, outgoing: B) -> Self 
+    pub fn spi_write<A, B>(spi: &Spi<W>, prefix: A, outgoing: B) -> Self 
     where 
-        A: AsRef<[u8]>,
-        B: AsRef<[u8]>,
+        A: AsRef<[W]>,
+        B: AsRef<[W]>,
     {
         MockTransaction::SpiWrite(spi.id, prefix.as_ref().to_vec(), outgoing.as_ref().to_vec())
     }
 
-    pub fn spi_read<A, B>(spi: &Spi, prefix: A, incoming: B) -> Self 
+    /// Create a write expectation matching the prefix, with any outgoing data
+    /// 
+    /// This matches any `spi_write` on the peripheral with the same prefix at `finalise`,
+    /// for writes where the payload is computed and uninteresting:
+    /// 
+    /// ```
+    /// use embedded_spi::Transactional;
+    /// use embedded_spi::mock::{Mock, MockTransaction};
+    /// 
+    /// let mut m = Mock::new();
+    /// let mut s = m.spi();
+    /// 
+    /// m.expect([MockTransaction::spi_write_any(&s, [0x80])]);
+    /// 
+    /// s.spi_write(&[0x80], &[0x12, 0x34, 0x56]).unwrap();
+    /// 
+    /// m.finalise();
+    /// ```
+    pub fn spi_write_any<A>(spi: &Spi<W>, prefix: A) -> Self 
+    where 
+        A: AsRef<[W]>,
+    {
+        MockTransaction::SpiWriteAny(spi.id, prefix.as_ref().to_vec())
+    }
+
+    pub fn spi_write_status<A, B, C>(spi: &Spi<W>, prefix: A, outgoing: B, status: C) -> Self 
     where 
-        A: AsRef<[u8]>,
-        B: AsRef<[u8]>,
+        A: AsRef<[W]>,
+        B: AsRef<[W]>,
+        C: AsRef<[W]>,
+    {
+        MockTransaction::SpiWriteStatus(spi.id, prefix.as_ref().to_vec(), outgoing.as_ref().to_vec(), status.as_ref().to_vec())
+    }
+
+    pub fn spi_read<A, B>(spi: &Spi<W>, prefix: A, incoming: B) -> Self 
+    where 
+        A: AsRef<[W]>,
+        B: AsRef<[W]>,
     {
         MockTransaction::SpiRead(spi.id, prefix.as_ref().to_vec(), incoming.as_ref().to_vec())
     }
 
-    pub fn busy(spi: &Spi, value: PinState) -> Self {
+    /// Create a read expectation where the driver clocks `dummy_count` discarded words before the
+    /// incoming data, either as additional prefix words or leading words of the read buffer
+    pub fn spi_read_with_dummy<A, B>(spi: &Spi<W>, prefix: A, dummy_count: usize, incoming: B) -> Self 
+    where 
+        A: AsRef<[W]>,
+        B: AsRef<[W]>,
+    {
+        MockTransaction::SpiReadDummy(spi.id, prefix.as_ref().to_vec(), dummy_count, incoming.as_ref().to_vec())
+    }
+
+    pub fn spi_exec<O>(spi: &Spi<W>, ops: O) -> Self 
+    where 
+        O: AsRef<[MockExec<W>]>,
+    {
+        MockTransaction::SpiExec(spi.id, ops.as_ref().to_vec())
+    }
+
+    /// Create an embedded-hal 1.0 `SpiDevice` transaction expectation
+    pub fn spi_device<O>(spi: &Spi<W>, ops: O) -> Self 
+    where 
+        O: AsRef<[MockOp<W>]>,
+    {
+        MockTransaction::SpiDevice(spi.id, ops.as_ref().to_vec())
+    }
+
+    /// Create a write followed by a read of the same data, for memory-style round trip tests
+    /// 
+    /// Prefixes are expected to be a command followed by an address, see `Mock::assert_readback_matches`
+    pub fn readback<A, B, C>(spi: &Spi<W>, write_prefix: A, read_prefix: B, data: C) -> [Self; 2] 
+    where 
+        A: AsRef<[W]>,
+        B: AsRef<[W]>,
+        C: AsRef<[W]>,
+    {
+        [
+            Self::spi_write(spi, write_prefix, &data),
+            Self::spi_read(spi, read_prefix, &data),
+        ]
+    }
+
+    pub fn begin(spi: &Spi<W>) -> Self {
+        MockTransaction::BeginTransaction(spi.id)
+    }
+
+    pub fn end(spi: &Spi<W>) -> Self {
+        MockTransaction::EndTransaction(spi.id)
+    }
+
+    pub fn set_lanes(spi: &Spi<W>, lanes: Lanes) -> Self {
+        MockTransaction::SetLanes(spi.id, lanes)
+    }
+
+    pub fn busy(spi: &Spi<W>, value: PinState) -> Self {
         MockTransaction::Busy(spi.id, value)
     }
 
-    pub fn ready(spi: &Spi, value: PinState) -> Self {
+    pub fn ready(spi: &Spi<W>, value: PinState) -> Self {
         MockTransaction::Ready(spi.id, value)
     }
 
-    pub fn reset(spi: &Spi, value: PinState) -> Self {
+    pub fn reset(spi: &Spi<W>, value: PinState) -> Self {
         MockTransaction::Reset(spi.id, value)
     }
 
@@ -96,514 +585,6740 @@ impl MockTransaction {
         MockTransaction::DelayMs(v)
     }
 
-    pub fn write<B>(spi: &Spi, outgoing: B) -> Self 
-    where B: AsRef<[u8]>
+    pub fn delay_us(v: u32) -> Self {
+        MockTransaction::DelayUs(v)
+    }
+
+    /// Create a delay expectation matching any `delay_ms` of at least the provided duration
+    /// 
+    /// This allows tests to check a minimum wait without depending on the exact value
+    /// computed by the driver, for example waiting at least 10 ms after reset:
+    /// 
+    /// ```
+    /// use embedded_hal::blocking::delay::DelayMs;
+    /// use embedded_spi::{Reset, PinState};
+    /// use embedded_spi::mock::{Mock, MockTransaction};
+    /// 
+    /// let mut m = Mock::new();
+    /// let mut s = m.spi();
+    /// 
+    /// m.expect([
+    ///     MockTransaction::reset(&s, PinState::Low),
+    ///     MockTransaction::delay_ms_at_least(10),
+    ///     MockTransaction::reset(&s, PinState::High),
+    /// ]);
+    /// 
+    /// s.set_reset(PinState::Low).unwrap();
+    /// s.delay_ms(12);
+    /// s.set_reset(PinState::High).unwrap();
+    /// 
+    /// m.finalise();
+    /// ```
+    pub fn delay_ms_at_least(v: u32) -> Self {
+        MockTransaction::DelayMsAtLeast(v)
+    }
+
+    /// Create a delay expectation matching any `delay_ms` of at most the provided duration
+    pub fn delay_ms_at_most(v: u32) -> Self {
+        MockTransaction::DelayMsAtMost(v)
+    }
+
+    /// Create a delay expectation matching any `delay_ms` within the provided (inclusive) bounds
+    pub fn delay_ms_range(min: u32, max: u32) -> Self {
+        MockTransaction::DelayMsRange(min, max)
+    }
+
+    pub fn write<B>(spi: &Spi<W>, outgoing: B) -> Self 
+    where B: AsRef<[W]>
     {
         MockTransaction::Write(spi.id, outgoing.as_ref().to_vec())
     }
 
-    pub fn transfer<B>(spi: &Spi, outgoing: B, incoming: B) -> Self 
+    /// Create an `spi::Write` expectation matching any outgoing data, see `spi_write_any`
+    pub fn write_any(spi: &Spi<W>) -> Self {
+        MockTransaction::WriteAny(spi.id)
+    }
+
+    pub fn transfer<B>(spi: &Spi<W>, outgoing: B, incoming: B) -> Self 
     where 
-        B: AsRef<[u8]>,
+        B: AsRef<[W]>,
     {
         MockTransaction::Transfer(spi.id, outgoing.as_ref().to_vec(), incoming.as_ref().to_vec())
     }
 
-    pub fn is_high(pin: &Pin, value: bool) -> Self {
+    /// Create a write-then-read expectation, returning the incoming data after the outgoing data is written
+    pub fn write_read<A, B>(spi: &Spi<W>, outgoing: A, incoming: B) -> Self 
+    where 
+        A: AsRef<[W]>,
+        B: AsRef<[W]>,
+    {
+        MockTransaction::WriteRead(spi.id, outgoing.as_ref().to_vec(), incoming.as_ref().to_vec())
+    }
+
+    /// Create a transfer expectation matching only the outgoing data
+    /// The incoming buffer is left unchanged, for drivers that discard the read-back
+    pub fn transfer_tx_only<B>(spi: &Spi<W>, outgoing: B) -> Self 
+    where 
+        B: AsRef<[W]>,
+    {
+        MockTransaction::TransferTx(spi.id, outgoing.as_ref().to_vec())
+    }
+
+    /// Create a transfer expectation that is interrupted with an error
+    /// The partial incoming data is copied into the leading words of the buffer before
+    /// the error is returned, to exercise driver handling of incomplete reads
+    pub fn transfer_abort<A, B>(spi: &Spi<W>, outgoing: A, partial_incoming: B, error: MockError) -> Self 
+    where 
+        A: AsRef<[W]>,
+        B: AsRef<[W]>,
+    {
+        MockTransaction::TransferAbort(spi.id, outgoing.as_ref().to_vec(), partial_incoming.as_ref().to_vec(), error)
+    }
+
+    pub fn is_high(pin: &Pin<W>, value: bool) -> Self {
         MockTransaction::IsHigh(pin.id, value)
     }
 
-    pub fn is_low(pin: &Pin, value: bool) -> Self {
+    pub fn is_low(pin: &Pin<W>, value: bool) -> Self {
         MockTransaction::IsLow(pin.id, value)
     }
 
-    pub fn set_high(pin: &Pin) -> Self {
+    pub fn set_high(pin: &Pin<W>) -> Self {
         MockTransaction::SetHigh(pin.id)
     }
 
-    pub fn set_low(pin: &Pin) -> Self {
+    pub fn set_low(pin: &Pin<W>) -> Self {
         MockTransaction::SetLow(pin.id)
     }
-}
 
-/// MockExec type for composing mock exec transactions
-#[derive(Clone, Debug, PartialEq)]
-pub enum MockExec {
-    SpiWrite(Vec<u8>),
-    SpiRead(Vec<u8>),
-}
+    /// Create a named user marker, see `Mock::mark`
+    pub fn user(name: &str) -> Self {
+        MockTransaction::User(name.to_string())
+    }
 
-impl <'a> From<&Transaction<'a>> for MockExec {
-    fn from(t: &Transaction<'a>) -> Self {
-        match t {
-            Transaction::Read(ref d) => {
-                let mut v = Vec::with_capacity(d.len());
-                v.copy_from_slice(d);
-                MockExec::SpiRead(v)
-            },
-            Transaction::Write(ref d) => {
-                let mut v = Vec::with_capacity(d.len());
-                v.copy_from_slice(d);
-                MockExec::SpiWrite(v)
+    /// Inject an error into the next SPI operation on the provided peripheral
+    pub fn spi_error(spi: &Spi<W>, e: MockError) -> Self {
+        MockTransaction::Inject(spi.id, Option::None, Error::Spi(e))
+    }
+
+    /// Inject an error into the next operation on the provided pin
+    pub fn pin_error(pin: &Pin<W>, e: MockError) -> Self {
+        MockTransaction::Inject(pin.id, Option::None, Error::Pin(e))
+    }
+
+    /// Create an expectation for a call that fails with an error
+    /// 
+    /// When an operation of the same kind is called on the peripheral it returns the error, and the
+    /// attempted call is recorded in place of `call` so its arguments are compared at finalise.
+    /// Read data is not returned by failing operations, so should be left empty in `call`.
+    /// Operations of a different kind are recorded as normal (and so mismatch at finalise).
+    pub fn fail(call: MockTransaction<W>, e: Error<MockError, MockError>) -> Self {
+        let id = call.id().unwrap_or_else(|| panic!("failing call {:?} has no peripheral", call) );
+        MockTransaction::Inject(id, Some(Box::new(call)), e)
+    }
+
+    /// Create an `spi_read` expectation that fails with an error, see `MockTransaction::fail`
+    pub fn spi_read_err<P>(spi: &Spi<W>, prefix: P, e: Error<MockError, MockError>) -> Self
+    where
+        P: AsRef<[W]>,
+    {
+        Self::fail(MockTransaction::SpiRead(spi.id, prefix.as_ref().to_vec(), vec![]), e)
+    }
+
+    /// Create an `spi_write` expectation that fails with an error, see `MockTransaction::fail`
+    pub fn spi_write_err<P, D>(spi: &Spi<W>, prefix: P, data: D, e: Error<MockError, MockError>) -> Self
+    where
+        P: AsRef<[W]>,
+        D: AsRef<[W]>,
+    {
+        Self::fail(MockTransaction::spi_write(spi, prefix, data), e)
+    }
+
+    /// Create an `spi::Write` expectation that fails with an error, see `MockTransaction::fail`
+    pub fn write_err<D>(spi: &Spi<W>, data: D, e: Error<MockError, MockError>) -> Self
+    where
+        D: AsRef<[W]>,
+    {
+        Self::fail(MockTransaction::write(spi, data), e)
+    }
+
+    /// Create an `spi::Transfer` expectation that fails with an error, see `MockTransaction::fail`
+    pub fn transfer_err<D>(spi: &Spi<W>, outgoing: D, e: Error<MockError, MockError>) -> Self
+    where
+        D: AsRef<[W]>,
+    {
+        Self::fail(MockTransaction::Transfer(spi.id, outgoing.as_ref().to_vec(), vec![]), e)
+    }
+
+    /// Fetch the kind of a transaction
+    pub fn kind(&self) -> TxnKind {
+        use MockTransaction::*;
+
+        match self {
+            None => TxnKind::None,
+            SpiWrite(..) => TxnKind::SpiWrite,
+            SpiWriteAny(..) => TxnKind::SpiWriteAny,
+            SpiWriteStatus(..) => TxnKind::SpiWriteStatus,
+            SpiRead(..) => TxnKind::SpiRead,
+            SpiReadDummy(..) => TxnKind::SpiReadDummy,
+            SpiExec(..) => TxnKind::SpiExec,
+            SpiDevice(..) => TxnKind::SpiDevice,
+            BeginTransaction(..) => TxnKind::BeginTransaction,
+            EndTransaction(..) => TxnKind::EndTransaction,
+            SetLanes(..) => TxnKind::SetLanes,
+            Busy(..) => TxnKind::Busy,
+            Ready(..) => TxnKind::Ready,
+            Reset(..) => TxnKind::Reset,
+            Write(..) => TxnKind::Write,
+            WriteAny(..) => TxnKind::WriteAny,
+            Transfer(..) => TxnKind::Transfer,
+            TransferTx(..) => TxnKind::TransferTx,
+            TransferAbort(..) => TxnKind::TransferAbort,
+            WriteRead(..) => TxnKind::WriteRead,
+            IsHigh(..) => TxnKind::IsHigh,
+            IsLow(..) => TxnKind::IsLow,
+            SetHigh(..) => TxnKind::SetHigh,
+            SetLow(..) => TxnKind::SetLow,
+            DelayMs(..) => TxnKind::DelayMs,
+            DelayUs(..) => TxnKind::DelayUs,
+            DelayMsAtLeast(..) => TxnKind::DelayMsAtLeast,
+            DelayMsAtMost(..) => TxnKind::DelayMsAtMost,
+            DelayMsRange(..) => TxnKind::DelayMsRange,
+            Inject(..) => TxnKind::Inject,
+            User(..) => TxnKind::User,
+        }
+    }
+
+    /// Fetch the peripheral Id associated with a transaction (if any)
+    pub fn id(&self) -> Option<Id> {
+        use MockTransaction::*;
+
+        match self {
+            SpiWrite(id, ..) | SpiWriteAny(id, ..) | SpiWriteStatus(id, ..) | SpiRead(id, ..) | SpiReadDummy(id, ..) | SpiExec(id, ..) | SpiDevice(id, ..) => Some(*id),
+            BeginTransaction(id) | EndTransaction(id) | SetLanes(id, ..) => Some(*id),
+            Busy(id, ..) | Ready(id, ..) | Reset(id, ..) => Some(*id),
+            Write(id, ..) | WriteAny(id) | Transfer(id, ..) | TransferTx(id, ..) | TransferAbort(id, ..) | WriteRead(id, ..) => Some(*id),
+            IsHigh(id, ..) | IsLow(id, ..) | SetHigh(id) | SetLow(id) => Some(*id),
+            Inject(id, ..) => Some(*id),
+            None | DelayMs(..) | DelayUs(..) | DelayMsAtLeast(..) | DelayMsAtMost(..) | DelayMsRange(..) | User(..) => Option::None,
+        }
+    }
+
+    /// Fetch a mutable reference to the peripheral Id of a transaction
+    fn id_mut(&mut self) -> Option<&mut Id> {
+        use MockTransaction::*;
+
+        match self {
+            SpiWrite(id, ..) | SpiWriteAny(id, ..) | SpiWriteStatus(id, ..) | SpiRead(id, ..) | SpiReadDummy(id, ..) | SpiExec(id, ..) | SpiDevice(id, ..) => Some(id),
+            BeginTransaction(id) | EndTransaction(id) | SetLanes(id, ..) => Some(id),
+            Busy(id, ..) | Ready(id, ..) | Reset(id, ..) => Some(id),
+            Write(id, ..) | WriteAny(id) | Transfer(id, ..) | TransferTx(id, ..) | TransferAbort(id, ..) | WriteRead(id, ..) => Some(id),
+            IsHigh(id, ..) | IsLow(id, ..) | SetHigh(id) | SetLow(id) => Some(id),
+            Inject(id, ..) => Some(id),
+            None | DelayMs(..) | DelayUs(..) | DelayMsAtLeast(..) | DelayMsAtMost(..) | DelayMsRange(..) | User(..) => Option::None,
+        }
+    }
+
+    /// Check whether a transaction is a delay (or a bounded delay expectation)
+    fn is_delay(&self) -> bool {
+        matches!(self, MockTransaction::DelayMs(_) | MockTransaction::DelayUs(_)
+            | MockTransaction::DelayMsAtLeast(_) | MockTransaction::DelayMsAtMost(_) | MockTransaction::DelayMsRange(..))
+    }
+
+    /// Fetch the duration of a delay transaction (or the delays within an exec) in microseconds
+    fn delay_duration_us(&self) -> Option<u64> {
+        match self {
+            MockTransaction::DelayMs(v) => Some(*v as u64 * 1000),
+            MockTransaction::DelayUs(v) => Some(*v as u64),
+            MockTransaction::SpiExec(_, ops) if ops.iter().any(|o| matches!(o, MockExec::Delay(_)) ) => {
+                Some(ops.iter().map(|o| match o {
+                    MockExec::Delay(ms) => *ms as u64 * 1000,
+                    _ => 0,
+                }).sum())
             },
+            _ => Option::None,
+        }
+    }
+
+    /// Count the words clocked on the bus by a transaction
+    fn clocked_len(&self) -> usize {
+        match self {
+            MockTransaction::SpiWrite(_, p, d) | MockTransaction::SpiWriteStatus(_, p, d, _) | MockTransaction::SpiRead(_, p, d) => p.len() + d.len(),
+            MockTransaction::SpiReadDummy(_, p, n, d) => p.len() + n + d.len(),
+            MockTransaction::SpiExec(_, ops) => ops.iter().map(|o| match o {
+                MockExec::SpiWrite(d) | MockExec::SpiRead(d) => d.len(),
+                MockExec::Delay(_) => 0,
+            }).sum(),
+            MockTransaction::SpiDevice(_, ops) => ops.iter().map(|o| match o {
+                MockOp::Read(d) | MockOp::Write(d) => d.len(),
+                MockOp::Transfer(w, r) => w.len().max(r.len()),
+                MockOp::TransferInPlace(d, _) => d.len(),
+                MockOp::DelayNs(_) => 0,
+            }).sum(),
+            MockTransaction::Write(_, d) | MockTransaction::Transfer(_, d, _) | MockTransaction::TransferTx(_, d) => d.len(),
+            MockTransaction::TransferAbort(_, d, ..) => d.len(),
+            MockTransaction::WriteRead(_, w, r) => w.len() + r.len(),
+            _ => 0,
+        }
+    }
+
+    /// Fetch the outgoing buffers of a transaction
+    fn outgoing_mut(&mut self) -> Vec<&mut Vec<W>> {
+        match self {
+            MockTransaction::SpiWrite(_, prefix, data) | MockTransaction::SpiWriteStatus(_, prefix, data, _) => vec![prefix, data],
+            MockTransaction::SpiRead(_, prefix, _) | MockTransaction::SpiReadDummy(_, prefix, ..) => vec![prefix],
+            MockTransaction::Write(_, data) | MockTransaction::Transfer(_, data, _) | MockTransaction::TransferTx(_, data) => vec![data],
+            MockTransaction::TransferAbort(_, data, ..) | MockTransaction::WriteRead(_, data, _) => vec![data],
+            MockTransaction::SpiExec(_, ops) => ops.iter_mut().filter_map(|o| match o {
+                MockExec::SpiWrite(d) => Some(d),
+                MockExec::SpiRead(_) | MockExec::Delay(_) => Option::None,
+            }).collect(),
+            MockTransaction::SpiDevice(_, ops) => ops.iter_mut().filter_map(|o| match o {
+                MockOp::Write(d) | MockOp::Transfer(d, _) | MockOp::TransferInPlace(d, _) => Some(d),
+                MockOp::Read(_) | MockOp::DelayNs(_) => Option::None,
+            }).collect(),
+            MockTransaction::Inject(_, Some(call), _) => call.outgoing_mut(),
+            _ => Vec::new(),
+        }
+    }
+
+    /// Fetch all data buffers of a transaction, in field order
+    fn buffers(&self) -> Vec<&[W]> {
+        match self {
+            MockTransaction::SpiWrite(_, a, b) | MockTransaction::SpiRead(_, a, b) | MockTransaction::SpiReadDummy(_, a, _, b)
+                | MockTransaction::Transfer(_, a, b) | MockTransaction::TransferAbort(_, a, b, _) | MockTransaction::WriteRead(_, a, b) => vec![a, b],
+            MockTransaction::SpiWriteStatus(_, a, b, c) => vec![a, b, c],
+            MockTransaction::SpiWriteAny(_, a) | MockTransaction::Write(_, a) | MockTransaction::TransferTx(_, a) => vec![a],
+            MockTransaction::SpiExec(_, ops) => ops.iter().filter_map(|o| match o {
+                MockExec::SpiWrite(d) | MockExec::SpiRead(d) => Some(&d[..]),
+                MockExec::Delay(_) => Option::None,
+            }).collect(),
+            MockTransaction::SpiDevice(_, ops) => ops.iter().flat_map(|o| match o {
+                MockOp::Read(d) | MockOp::Write(d) => vec![&d[..]],
+                MockOp::Transfer(a, b) | MockOp::TransferInPlace(a, b) => vec![&a[..], &b[..]],
+                MockOp::DelayNs(_) => vec![],
+            }).collect(),
+            MockTransaction::Inject(_, Some(call), _) => call.buffers(),
+            _ => Vec::new(),
+        }
+    }
+
+    /// Check whether a transaction is a pin operation
+    fn is_pin(&self) -> bool {
+        matches!(self, 
+            MockTransaction::Busy(..) | MockTransaction::Ready(..) | MockTransaction::Reset(..)
+            | MockTransaction::IsHigh(..) | MockTransaction::IsLow(..) | MockTransaction::SetHigh(..) | MockTransaction::SetLow(..)
+        )
+    }
+
+    /// Fetch the pin state driven by a transaction, if any
+    fn pin_level(&self) -> Option<(Id, PinState)> {
+        match self {
+            MockTransaction::SetHigh(p) => Some((*p, PinState::High)),
+            MockTransaction::SetLow(p) => Some((*p, PinState::Low)),
+            MockTransaction::Reset(p, state) => Some((*p, state.clone())),
+            _ => Option::None,
+        }
+    }
+
+    /// Count the words read back by a transaction
+    fn read_len(&self) -> usize {
+        match self {
+            MockTransaction::SpiRead(_, _, d) | MockTransaction::SpiReadDummy(_, _, _, d) => d.len(),
+            MockTransaction::Transfer(_, _, d) | MockTransaction::TransferAbort(_, _, d, _) => d.len(),
+            MockTransaction::SpiExec(_, ops) => ops.iter().map(|o| match o {
+                MockExec::SpiRead(d) => d.len(),
+                MockExec::SpiWrite(_) | MockExec::Delay(_) => 0,
+            }).sum(),
+            MockTransaction::SpiDevice(_, ops) => ops.iter().map(|o| match o {
+                MockOp::Read(d) | MockOp::Transfer(_, d) | MockOp::TransferInPlace(_, d) => d.len(),
+                MockOp::Write(_) | MockOp::DelayNs(_) => 0,
+            }).sum(),
+            _ => 0,
         }
     }
 }
 
-#[derive(Clone, Debug, PartialEq)]
-struct Inner {
-    index: usize,
-    expected: Vec<MockTransaction>,
-    actual: Vec<MockTransaction>,
+/// Byte order for multi-byte register values, see `Mock::set_word_endianness`
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Endianness {
+    Big,
+    Little,
 }
 
-impl Inner {
-    fn finalise(&mut self) {
-        assert_eq!(self.expected, self.actual);
+impl Endianness {
+    /// Encode the low `len` bytes of a value
+    fn encode(self, value: u32, len: usize) -> Vec<u8> {
+        match self {
+            Endianness::Big => value.to_be_bytes()[4 - len..].to_vec(),
+            Endianness::Little => value.to_le_bytes()[..len].to_vec(),
+        }
     }
 }
 
-impl Mock {
-    /// Create a new mock instance
-    pub fn new() -> Self {
-        Self{ inner: Arc::new(Mutex::new(Inner{ index: 0, expected: Vec::new(), actual: Vec::new() })), count: 0 } 
+impl MockTransaction<u8> {
+    /// Create a write of a big endian `u16` value
+    pub fn spi_write_u16_be<A: AsRef<[u8]>>(spi: &Spi, prefix: A, value: u16) -> Self {
+        Self::spi_write(spi, prefix, Endianness::Big.encode(u32::from(value), 2))
     }
 
-    /// Set expectations on the instance
-    pub fn expect<T>(&mut self, transactions: T) 
-    where 
-        T: AsRef<[MockTransaction]> 
-    {
-        let expected: Vec<_> = transactions.as_ref().to_vec();
-        let actual = vec![];
+    /// Create a write of a little endian `u16` value
+    pub fn spi_write_u16_le<A: AsRef<[u8]>>(spi: &Spi, prefix: A, value: u16) -> Self {
+        Self::spi_write(spi, prefix, Endianness::Little.encode(u32::from(value), 2))
+    }
 
-        let i = Inner{
-            index: 0,
-            expected,
-            actual,
-        };
-        
-        *self.inner.lock().unwrap() = i;
+    /// Create a write of a big endian `u32` value
+    pub fn spi_write_u32_be<A: AsRef<[u8]>>(spi: &Spi, prefix: A, value: u32) -> Self {
+        Self::spi_write(spi, prefix, Endianness::Big.encode(value, 4))
     }
 
-    pub fn spi(&mut self) -> Spi {
-        let id = self.count;
-        self.count += 1;
-        Spi{ inner: self.inner.clone(), id }
+    /// Create a write of a little endian `u32` value
+    pub fn spi_write_u32_le<A: AsRef<[u8]>>(spi: &Spi, prefix: A, value: u32) -> Self {
+        Self::spi_write(spi, prefix, Endianness::Little.encode(value, 4))
     }
 
-    pub fn pin(&mut self) -> Pin {
-        let id = self.count;
-        self.count += 1;
-        Pin{ inner: self.inner.clone(), id }
+    /// Create a read returning a big endian `u16` value
+    pub fn spi_read_u16_be<A: AsRef<[u8]>>(spi: &Spi, prefix: A, value: u16) -> Self {
+        Self::spi_read(spi, prefix, Endianness::Big.encode(u32::from(value), 2))
     }
 
-    pub fn delay(&mut self) -> Delay {
-        let id = self.count;
-        self.count += 1;
-        Delay{ inner: self.inner.clone(), id }
+    /// Create a read returning a little endian `u16` value
+    pub fn spi_read_u16_le<A: AsRef<[u8]>>(spi: &Spi, prefix: A, value: u16) -> Self {
+        Self::spi_read(spi, prefix, Endianness::Little.encode(u32::from(value), 2))
     }
 
-    /// Finalise expectations
-    /// This will cause previous expectations to be evaluated
-    pub fn finalise(&self) {
-        let mut i = self.inner.lock().unwrap();
-        i.finalise();
+    /// Create a read returning a big endian `u32` value
+    pub fn spi_read_u32_be<A: AsRef<[u8]>>(spi: &Spi, prefix: A, value: u32) -> Self {
+        Self::spi_read(spi, prefix, Endianness::Big.encode(value, 4))
+    }
+
+    /// Create a read returning a little endian `u32` value
+    pub fn spi_read_u32_le<A: AsRef<[u8]>>(spi: &Spi, prefix: A, value: u32) -> Self {
+        Self::spi_read(spi, prefix, Endianness::Little.encode(value, 4))
     }
 }
 
-impl Transactional for Spi {
-    type Error = Error<(), ()>;
+/// MockExec type for composing mock exec transactions
+#[derive(Clone, Debug, PartialEq)]
+pub enum MockExec<W = u8> {
+    SpiWrite(Vec<W>),
+    SpiRead(Vec<W>),
+    Delay(u32),
+}
 
-    /// Read data from a specified address
-    /// This consumes the provided input data array and returns a reference to this on success
-    fn spi_read(&mut self, prefix: &[u8], data: &mut [u8]) -> Result<(), Self::Error> {
-        let mut i = self.inner.lock().unwrap();
-        let index = i.index;
+impl <W: Word> MockExec<W> {
+    pub fn write<B>(outgoing: B) -> Self 
+    where
+        B: AsRef<[W]>,
+    {
+        MockExec::SpiWrite(outgoing.as_ref().to_vec())
+    }
 
-        // Copy read data from expectation
-        match &i.expected.get(index) {
-            Some(MockTransaction::SpiRead(_id, _outgoing, incoming)) => {
-                data.copy_from_slice(&incoming);
-            },
-            _ => (),
-        };
+    pub fn read<B>(incoming: B) -> Self 
+    where
+        B: AsRef<[W]>,
+    {
+        MockExec::SpiRead(incoming.as_ref().to_vec())
+    }
 
-        // Save actual call
-        i.actual.push(MockTransaction::SpiRead(self.id, prefix.into(), data.into()));
-        
-        // Update expectation index
-        i.index += 1;
+    /// Create a delay (in milliseconds) between exec operations
+    pub fn delay(ms: u32) -> Self {
+        MockExec::Delay(ms)
+    }
+}
 
+/// Operations of an embedded-hal 1.0 `SpiDevice` transaction, see `MockTransaction::spi_device`
+/// Transfers hold the outgoing then incoming data
+#[derive(Clone, Debug, PartialEq)]
+pub enum MockOp<W = u8> {
+    Read(Vec<W>),
+    Write(Vec<W>),
+    Transfer(Vec<W>, Vec<W>),
+    TransferInPlace(Vec<W>, Vec<W>),
+    DelayNs(u32),
+}
+
+impl <'a> From<&Transaction<'a>> for MockExec<u8> {
+    fn from(t: &Transaction<'a>) -> Self {
+        match t {
+            Transaction::Read(ref d) => MockExec::SpiRead(d.to_vec()),
+            Transaction::Write(ref d) => MockExec::SpiWrite(d.to_vec()),
+            Transaction::Delay(ms) => MockExec::Delay(*ms),
+        }
+    }
+}
+
+/// Boxed user callback stored in the mock
+struct Hook<F: ?Sized>(Box<F>);
+
+impl <F: ?Sized> fmt::Debug for Hook<F> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Hook")
+    }
+}
+
+/// Debug formatting for failure messages, printing data buffers in hex
+/// while Ids, lengths and durations remain in decimal
+struct Hex<'a, T: ?Sized>(&'a T);
+
+impl <'a, W: Word> fmt::Debug for Hex<'a, [W]> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{:02X?}", self.0)
+    }
+}
+
+impl <'a, W: Word> fmt::Debug for Hex<'a, MockExec<W>> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.0 {
+            MockExec::SpiWrite(d) => f.debug_tuple("SpiWrite").field(&Hex(&d[..])).finish(),
+            MockExec::SpiRead(d) => f.debug_tuple("SpiRead").field(&Hex(&d[..])).finish(),
+            MockExec::Delay(ms) => f.debug_tuple("Delay").field(ms).finish(),
+        }
+    }
+}
+
+impl <'a, W: Word> fmt::Debug for Hex<'a, MockOp<W>> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.0 {
+            MockOp::Read(d) => f.debug_tuple("Read").field(&Hex(&d[..])).finish(),
+            MockOp::Write(d) => f.debug_tuple("Write").field(&Hex(&d[..])).finish(),
+            MockOp::Transfer(o, i) => f.debug_tuple("Transfer").field(&Hex(&o[..])).field(&Hex(&i[..])).finish(),
+            MockOp::TransferInPlace(o, i) => f.debug_tuple("TransferInPlace").field(&Hex(&o[..])).field(&Hex(&i[..])).finish(),
+            MockOp::DelayNs(ns) => f.debug_tuple("DelayNs").field(ns).finish(),
+        }
+    }
+}
+
+impl <'a, W: Word> fmt::Debug for Hex<'a, MockTransaction<W>> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        use MockTransaction::*;
+
+        let h = |d: &'a Vec<W>| Hex(&d[..]);
+
+        match self.0 {
+            SpiWrite(id, p, d) => f.debug_tuple("SpiWrite").field(id).field(&h(p)).field(&h(d)).finish(),
+            SpiWriteAny(id, p) => f.debug_tuple("SpiWriteAny").field(id).field(&h(p)).finish(),
+            SpiWriteStatus(id, p, d, s) => f.debug_tuple("SpiWriteStatus").field(id).field(&h(p)).field(&h(d)).field(&h(s)).finish(),
+            SpiRead(id, p, d) => f.debug_tuple("SpiRead").field(id).field(&h(p)).field(&h(d)).finish(),
+            SpiReadDummy(id, p, n, d) => f.debug_tuple("SpiReadDummy").field(id).field(&h(p)).field(n).field(&h(d)).finish(),
+            SpiExec(id, ops) => f.debug_tuple("SpiExec").field(id).field(&ops.iter().map(Hex).collect::<Vec<_>>()).finish(),
+            SpiDevice(id, ops) => f.debug_tuple("SpiDevice").field(id).field(&ops.iter().map(Hex).collect::<Vec<_>>()).finish(),
+            Write(id, d) => f.debug_tuple("Write").field(id).field(&h(d)).finish(),
+            Transfer(id, o, i) => f.debug_tuple("Transfer").field(id).field(&h(o)).field(&h(i)).finish(),
+            TransferTx(id, o) => f.debug_tuple("TransferTx").field(id).field(&h(o)).finish(),
+            TransferAbort(id, o, i, e) => f.debug_tuple("TransferAbort").field(id).field(&h(o)).field(&h(i)).field(e).finish(),
+            WriteRead(id, o, i) => f.debug_tuple("WriteRead").field(id).field(&h(o)).field(&h(i)).finish(),
+            Inject(id, Some(t), e) => f.debug_tuple("Inject").field(id).field(&Some(Hex(&**t))).field(e).finish(),
+            t => write!(f, "{:?}", t),
+        }
+    }
+}
+
+/// Callback invoked with each recorded transaction
+type EachHook<W> = Hook<dyn FnMut(&MockTransaction<W>) + Send>;
+
+/// Transform applied to outgoing buffers before recording
+type TxTransform<W> = Hook<dyn Fn(&[W]) -> Vec<W> + Send>;
+
+/// Callback computing read data from the outgoing prefix or data
+type ReadHook<W> = Hook<dyn FnMut(&[W]) -> Vec<W> + Send>;
+
+/// Extractor for the register address of a read or write prefix
+type AddressFn<W> = Hook<dyn Fn(&[W]) -> Vec<W> + Send>;
+
+/// Lock the shared mock state
+/// This tolerates poisoning so a driver panic (caught by a test) does not obscure later checks
+fn lock<W>(inner: &Mutex<Inner<W>>) -> MutexGuard<'_, Inner<W>> {
+    inner.lock().unwrap_or_else(|e| e.into_inner() )
+}
+
+/// Interrupt pin state, see `Mock::mark_interrupt_pin`
+#[derive(Debug)]
+struct Interrupt<W> {
+    level: bool,
+    reads: Vec<MockTransaction<W>>,
+}
+
+#[derive(Debug)]
+struct Inner<W> {
+    index: usize,
+    expected: Vec<MockTransaction<W>>,
+    actual: Vec<MockTransaction<W>>,
+    on_each: Option<EachHook<W>>,
+    tx_transform: Option<TxTransform<W>>,
+    on_read: Option<ReadHook<W>>,
+    strict_polls: bool,
+    strict: bool,
+    finalised: bool,
+    skip_finalise: bool,
+    trailing_delays: bool,
+    concat_prefix: bool,
+    loopback: bool,
+    label: Option<String>,
+    elapsed_us: u64,
+    deadline_us: Option<u64>,
+    read_fifo: VecDeque<W>,
+    errors_fired: bool,
+    pin_states: HashMap<Id, PinState>,
+    interrupts: HashMap<Id, Interrupt<W>>,
+    register_model: bool,
+    registers: Vec<(Id, Vec<W>)>,
+    register_address: Option<AddressFn<W>>,
+    endianness: Endianness,
+    busy_forever: HashMap<Id, usize>,
+    reset_done: bool,
+    reset_registers: Vec<(Vec<W>, W)>,
+    soft_reset: Option<Vec<W>>,
+    operation_cap: Option<usize>,
+    unordered: bool,
+    repeats: Vec<(usize, usize)>,
+    names: Vec<String>,
+    sequence: Vec<u64>,
+    next_sequence: u64,
+    #[cfg(feature = "fuzz")]
+    rng: Option<XorShiftRng>,
+}
+
+impl <W: Word> Inner<W> {
+    fn new() -> Self {
+        Inner{ 
+            index: 0, expected: Vec::new(), actual: Vec::new(), on_each: None, tx_transform: None, on_read: None, strict_polls: false, strict: false, finalised: false, skip_finalise: false, trailing_delays: false, concat_prefix: false, loopback: false, label: None,
+            elapsed_us: 0, deadline_us: None, read_fifo: VecDeque::new(), errors_fired: false, pin_states: HashMap::new(), interrupts: HashMap::new(),
+            register_model: false, registers: Vec::new(), register_address: None, endianness: Endianness::Big, busy_forever: HashMap::new(),
+            reset_done: false, reset_registers: Vec::new(), soft_reset: None, operation_cap: None, unordered: false, repeats: Vec::new(), names: Vec::new(),
+            sequence: Vec::new(), next_sequence: 0,
+            #[cfg(feature = "fuzz")]
+            rng: None,
+        }
+    }
+
+    /// Record an actual transaction and advance the expectation index
+    fn record(&mut self, mut t: MockTransaction<W>) {
+        // Peripheral operations select their expectation before use, delays and markers on record
+        if t.id().is_none() {
+            self.select_where(|e| *e == t || wildcard_matches(e, &t) );
+        }
+
+        // Apply any outgoing data transform
+        if let Some(Hook(f)) = &self.tx_transform {
+            for b in t.outgoing_mut() {
+                *b = f(b);
+            }
+        }
+
+        // Accumulate virtual time
+        if let Some(us) = t.delay_duration_us() {
+            self.elapsed_us += us;
+        }
+
+        // Track the last driven pin state
+        if let Some((id, state)) = t.pin_level() {
+            self.pin_states.insert(id, state);
+        }
+
+        // Track device resets for registers modelled after reset
+        match &t {
+            MockTransaction::Reset(_, PinState::High) => self.reset_done = true,
+            MockTransaction::SpiWrite(_, prefix, data) if data.is_empty() && self.soft_reset.as_ref() == Some(prefix) => self.reset_done = true,
+            _ => (),
+        }
+
+        if let Some(cap) = self.operation_cap {
+            if self.actual.len() >= cap {
+                panic!("{}operation cap exceeded ({} transactions) - possible infinite loop", self.tag(), cap);
+            }
+        }
+
+        self.actual.push(t);
+
+        // Tag with the global sequence number, assigned under the lock
+        self.sequence.push(self.next_sequence);
+        self.next_sequence += 1;
+
+        if let Some(Hook(f)) = &mut self.on_each {
+            f(&self.actual[self.actual.len() - 1]);
+        }
+
+        self.index += 1;
+
+        if let Some(d) = self.deadline_us {
+            if self.elapsed_us > d {
+                panic!("{}deadline of {} us exceeded ({} us elapsed) at index {}", self.tag(), d, self.elapsed_us, self.index - 1);
+            }
+        }
+    }
+
+    /// Clear recorded transactions and per-run state (virtual time, pin states, device resets
+    /// and poll counts) for a new set of expectations, retaining configuration
+    fn clear_run_state(&mut self) {
+        self.index = 0;
+        self.actual = vec![];
+        self.sequence = vec![];
+        self.repeats = vec![];
+        self.unordered = false;
+        self.elapsed_us = 0;
+        self.pin_states.clear();
+        self.reset_done = false;
+        self.busy_forever.values_mut().for_each(|n| *n = 0 );
+        self.interrupts.values_mut().for_each(|int| int.reads.clear() );
+        self.finalised = false;
+    }
+
+    /// Fill a read buffer with no expected data from the read FIFO
+    /// Returns false if no data was available
+    fn fill_words(&mut self, data: &mut [W]) -> bool {
+        if self.read_fifo.is_empty() {
+            return false;
+        }
+
+        let n = data.len().min(self.read_fifo.len());
+        for (d, v) in data.iter_mut().zip(self.read_fifo.drain(..n)) {
+            *d = v;
+        }
+
+        true
+    }
+
+    /// Fill a read buffer using the `on_read` callback, returning false if none is registered
+    fn read_hook(&mut self, outgoing: &[W], data: &mut [W]) -> bool {
+        let v = match &mut self.on_read {
+            Some(Hook(f)) => f(outgoing),
+            Option::None => return false,
+        };
+
+        if v.len() != data.len() {
+            panic!("{}on_read returned {} words but driver provided a {}-word buffer", self.tag(), v.len(), data.len());
+        }
+
+        data.copy_from_slice(&v);
+        true
+    }
+
+    /// Fetch an error to be injected into the current operation of the provided kind
+    /// If the current expectation is an `Inject` for this peripheral (and any failing call is of the
+    /// same kind) this is recorded, with the attempted call in place of the failing call, and the error returned
+    fn inject<F>(&mut self, id: Id, kind: TxnKind, attempt: F) -> Option<Error<MockError, MockError>>
+    where
+        F: FnOnce() -> MockTransaction<W>,
+    {
+        let (call, e) = match self.expected.get(self.index) {
+            Some(MockTransaction::Inject(i, call, e)) if *i == id && !matches!(call, Some(c) if c.kind() != kind) => {
+                (call.as_ref().map(|_| Box::new(attempt()) ), e.clone())
+            },
+            _ => return None,
+        };
+
+        // Untyped errors are returned from the pin side of pin-backed SPI operations
+        let returned = match (&call, kind, &e) {
+            (Option::None, TxnKind::Busy, Error::Spi(v)) | (Option::None, TxnKind::Ready, Error::Spi(v))
+                | (Option::None, TxnKind::Reset, Error::Spi(v)) => Error::Pin(v.clone()),
+            _ => e.clone(),
+        };
+
+        self.record(MockTransaction::Inject(id, call, e));
+        Some(returned)
+    }
+
+    /// Convert an injected error for return from a pin operation
+    fn pin_error(&self, e: Error<MockError, MockError>) -> MockError {
+        match e {
+            Error::Spi(e) | Error::Pin(e) => e,
+            e => panic!("{}injected error {:?} cannot be returned from a pin", self.tag(), e),
+        }
+    }
+
+    /// Select the first remaining expectation for an operation in unordered mode
+    /// This moves a matching expectation (by Id, kind, or a failure for the operation) to the current index
+    fn select(&mut self, id: Id, kind: TxnKind) {
+        self.select_where(|t| t.id() == Some(id) && match t {
+            MockTransaction::Inject(_, Option::None, _) => true,
+            MockTransaction::Inject(_, Some(call), _) => call.kind() == kind,
+            MockTransaction::SpiReadDummy(..) => kind == TxnKind::SpiRead,
+            MockTransaction::SpiWriteAny(..) => kind == TxnKind::SpiWrite,
+            MockTransaction::WriteAny(..) => kind == TxnKind::Write,
+            MockTransaction::TransferTx(..) | MockTransaction::TransferAbort(..) => kind == TxnKind::Transfer,
+            t => t.kind() == kind,
+        });
+    }
+
+    /// Move the first remaining expectation matching `serves` to the current index in unordered mode
+    fn select_where<F: Fn(&MockTransaction<W>) -> bool>(&mut self, serves: F) {
+        if !self.unordered {
+            return;
+        }
+
+        let remaining = match self.expected.get_mut(self.index..) {
+            Some(r) => r,
+            Option::None => return,
+        };
+
+        if let Some(n) = remaining.iter().position(serves) {
+            remaining[..=n].rotate_right(1);
+        }
+    }
+
+    /// Read the level of an interrupt pin, recording to the interrupt stream
+    /// Returns `None` for pins not marked as interrupts
+    fn interrupt_read(&mut self, id: Id, high: bool) -> Option<bool> {
+        let int = self.interrupts.get_mut(&id)?;
+        let v = int.level == high;
+
+        int.reads.push(if high { MockTransaction::IsHigh(id, v) } else { MockTransaction::IsLow(id, v) });
+
+        Some(v)
+    }
+
+    /// Mark a register address as known to the register model
+    fn known_register(&mut self, id: Id, address: &[W]) {
+        if !self.registers.iter().any(|(i, a)| *i == id && a[..] == *address ) {
+            self.registers.push((id, address.to_vec()));
+        }
+    }
+
+    /// Fetch the register address of a prefix for the register model
+    /// This is the prefix following the command word, or the whole prefix where this is a single word
+    fn register_of(&self, prefix: &[W]) -> Vec<W> {
+        match (&self.register_address, prefix) {
+            (Some(Hook(f)), p) => f(p),
+            (Option::None, [_, address @ ..]) if !address.is_empty() => address.to_vec(),
+            (Option::None, p) => p.to_vec(),
+        }
+    }
+
+    /// Check the register addressed by a prefix is known to the register model, if enabled
+    fn check_register(&self, id: Id, prefix: &[W]) {
+        let address = self.register_of(prefix);
+        if self.register_model && !self.registers.iter().any(|(i, a)| *i == id && *a == address ) {
+            panic!("{}read of uninitialised register {:02X?} on peripheral {} at index {}", self.tag(), address, id, self.index);
+        }
+    }
+
+    /// Format the name of the handle associated with a transaction (if any) for failure messages
+    fn name_of(&self, t: &MockTransaction<W>) -> String {
+        match t.id().and_then(|id| self.names.get(id as usize) ) {
+            Some(n) => format!(" on '{}'", n),
+            Option::None => String::new(),
+        }
+    }
+
+    /// Build a check failure at the provided index
+    fn failure(&self, code: &'static str, index: usize, message: String) -> CheckFailure {
+        CheckFailure{ code, index, message: format!("{}{}", self.tag(), message) }
+    }
+
+    /// Fetch the run label prefix for failure messages
+    fn tag(&self) -> String {
+        match &self.label {
+            Some(l) => format!("[{}] ", l),
+            Option::None => String::new(),
+        }
+    }
+
+    /// Check a busy / ready poll has not run past the end of the expectations
+    fn check_poll(&self, kind: &str) {
+        if self.strict_polls && self.index >= self.expected.len() {
+            panic!("{}unexpected {} poll #{} (only {} transactions expected)", self.tag(), kind, self.index, self.expected.len());
+        }
+    }
+
+    /// Check the current expectation is of the provided kind for this peripheral (if strict)
+    fn check_expected(&self, id: Id, kind: TxnKind, call: &str) {
+        let found = self.expected.get(self.index).map(|t| t.id() == Some(id) && t.kind() == kind ).unwrap_or(false);
+        if self.strict && !found {
+            panic!("{}unexpected {}() call at index {}, no expectation set", self.tag(), call, self.index);
+        }
+    }
+
+    /// Check the expectation index is consistent with the recorded transactions
+    /// This can desync if a panic interrupts a partially recorded transaction
+    fn check_index(&self) {
+        if self.index != self.actual.len() {
+            panic!("{}recorded {} actuals but index is {}", self.tag(), self.actual.len(), self.index);
+        }
+    }
+
+    /// Check repeated expectations (by start index and count) occurred the expected number of times
+    fn check_repeats(&self, repeats: &[(usize, usize)], expected: &[MockTransaction<W>], actual: &[MockTransaction<W>]) {
+        for (start, count) in repeats.iter().cloned() {
+            let t = &expected[start];
+            let found = actual.get(start..).unwrap_or(&[]).iter().take_while(|a| *a == t || wildcard_matches(t, a) ).count();
+
+            // Further matches are allowed where the following expectation is also the same transaction
+            if found < count || (found > count && expected.get(start + count) != Some(t)) {
+                panic!("{}expected {} repetitions of {:?} from index {}, found {}", self.tag(), count, t, start, found);
+            }
+        }
+    }
+
+    /// Check begin / end transaction framing is correctly nested
+    fn check_framing(&self) {
+        let mut open = Vec::new();
+
+        for (n, t) in self.actual.iter().enumerate() {
+            match t {
+                MockTransaction::BeginTransaction(id) if open.contains(id) => {
+                    panic!("{}nested begin for peripheral {} at index {}", self.tag(), id, n);
+                },
+                MockTransaction::BeginTransaction(id) => open.push(*id),
+                MockTransaction::EndTransaction(id) if !open.contains(id) => {
+                    panic!("{}end without begin for peripheral {} at index {}", self.tag(), id, n);
+                },
+                MockTransaction::EndTransaction(id) => open.retain(|o| o != id ),
+                _ => (),
+            }
+        }
+
+        if let Some(id) = open.first() {
+            panic!("{}begin without end for peripheral {}", self.tag(), id);
+        }
+    }
+
+    /// Check all injected errors in the expectations were triggered (if enabled)
+    fn check_errors_fired(&self) {
+        if !self.errors_fired {
+            return;
+        }
+
+        let mut fired: Vec<_> = self.actual.iter().filter(|t| t.kind() == TxnKind::Inject ).collect();
+
+        // Failing calls are compared at finalise, so injections match on peripheral, kind and error
+        let key = |t: &MockTransaction<W>| match t {
+            MockTransaction::Inject(id, call, e) => Some((*id, call.as_ref().map(|c| c.kind() ), e.clone())),
+            _ => Option::None,
+        };
+
+        for (n, t) in self.expected.iter().enumerate() {
+            if let MockTransaction::Inject(id, _call, e) = t {
+                match fired.iter().position(|f| key(f) == key(t) ) {
+                    Some(p) => { fired.remove(p); },
+                    Option::None => panic!("{}injected error {} for peripheral {} at index {} was not triggered", self.tag(), match e {
+                        Error::Spi(e) | Error::Pin(e) => format!("{:?}", e),
+                        e => format!("{:?}", e),
+                    }, id, n),
+                }
+            }
+        }
+    }
+
+    /// Check expectations were set if any transactions were recorded
+    fn check_expectations_set<T: fmt::Debug>(&self, expected: &[T], actual: &[T]) {
+        if expected.is_empty() && !actual.is_empty() {
+            panic!("{}no expectations set, but {} transactions were recorded (first: {:?})", self.tag(), actual.len(), actual[0]);
+        }
+    }
+
+    fn finalise(&mut self) {
+        self.finalise_with(MatchOptions::default());
+    }
+
+    /// Compare expected and actual transactions with the provided options
+    /// Repeats and wildcards are applied after normalisation, so that these combine with any option
+    fn finalise_with(&mut self, opts: MatchOptions) {
+        self.check_index();
+        self.check_framing();
+        self.check_errors_fired();
+
+        if self.unordered {
+            self.finalise_unordered();
+            return;
+        }
+
+        let note = match (opts == MatchOptions::default(), self.concat_prefix) {
+            (true, false) => String::new(),
+            (true, true) => " (prefix and data concatenated)".to_string(),
+            (false, _) => format!(" (with {:?})", opts),
+        };
+
+        // Drop unmatched trailing delays if enabled
+        let mut end = self.actual.len();
+        if self.trailing_delays {
+            while end > self.expected.len() && self.actual[end - 1].is_delay() {
+                end -= 1;
+            }
+        }
+
+        let keep = |t: &MockTransaction<W>| !(opts.ignore_delays && t.is_delay());
+        let normalise = |t: &MockTransaction<W>| {
+            let mut t = t.clone();
+            if let (true, Some(id)) = (opts.ignore_ids, t.id_mut()) {
+                *id = 0;
+            }
+            t
+        };
+
+        // Normalise expectations, tracking where repeats start in the normalised sequence
+        let mut expected = Vec::with_capacity(self.expected.len());
+        let mut repeats = Vec::new();
+        for (n, t) in self.expected.iter().enumerate().filter(|(_, t)| keep(t) ) {
+            if let Some((_, count)) = self.repeats.iter().find(|(start, _)| *start == n ) {
+                repeats.push((expected.len(), *count));
+            }
+
+            expected.push(match normalise(t) {
+                MockTransaction::SpiWrite(id, prefix, _) if opts.data_wildcards => MockTransaction::SpiWriteAny(id, prefix),
+                MockTransaction::Write(id, _) if opts.data_wildcards => MockTransaction::WriteAny(id),
+                t => t,
+            });
+        }
+
+        let actual: Vec<_> = self.actual[..end].iter().filter(|t| keep(t) ).map(normalise).collect();
+
+        self.check_repeats(&repeats, &expected, &actual);
+        self.check_expectations_set(&expected, &actual);
+
+        let actual = mask_wildcards(&expected, &actual);
+
+        if opts.concat_prefix || self.concat_prefix {
+            let expected: Vec<_> = expected.iter().map(concat_prefix).collect();
+            let actual: Vec<_> = actual.iter().map(concat_prefix).collect();
+            self.assert_match(&expected, &actual, &note);
+            return;
+        }
+
+        self.assert_match(&expected, &actual, &note);
+    }
+
+    /// Assert expected and actual transactions match, reporting the first difference
+    /// 
+    /// Mismatched transactions are printed in hex along with the differing buffers, and
+    /// missing or unexpected trailing transactions are reported with the expected count.
+    fn assert_match<T>(&self, expected: &[T], actual: &[T], note: &str) 
+    where
+        T: Borrow<MockTransaction<W>> + PartialEq,
+    {
+        let n = match expected.iter().zip(actual.iter()).position(|(e, a)| e != a ) {
+            Some(n) => n,
+            Option::None if expected.len() == actual.len() => return,
+            Option::None => {
+                let n = expected.len().min(actual.len());
+                let (what, t) = match actual.get(n) {
+                    Some(t) => ("first unexpected", t),
+                    Option::None => ("first missing", &expected[n]),
+                };
+                panic!("{}expectation mismatch at index {}{}: expected {} transactions, got {} ({}: {:?}{})", 
+                    self.tag(), n, note, expected.len(), actual.len(), what, Hex(t.borrow()), self.name_of(t.borrow()));
+            },
+        };
+
+        let (e, a) = (expected[n].borrow(), actual[n].borrow());
+
+        let mut diff = String::new();
+        if e.kind() == a.kind() {
+            for (b, (eb, ab)) in e.buffers().iter().zip(a.buffers().iter()).enumerate().filter(|(_, (eb, ab))| eb != ab ) {
+                let w = eb.iter().zip(ab.iter()).position(|(x, y)| x != y ).unwrap_or_else(|| eb.len().min(ab.len()) );
+                diff.push_str(&format!("\n  buffer {}: expected {:02X?}, actual {:02X?} (first difference at word {})", b, eb, ab, w));
+            }
+        }
+
+        panic!("{}expectation mismatch at index {}{}\n  expected: {:?}{}\n  actual:   {:?}{}{}", 
+            self.tag(), n, note, Hex(e), self.name_of(e), Hex(a), self.name_of(a), diff);
+    }
+
+    /// Match actual transactions against the multiset of expectations, ignoring order
+    fn finalise_unordered(&self) {
+        self.check_expectations_set(&self.expected, &self.actual);
+
+        let mut remaining: Vec<_> = self.expected.iter().collect();
+
+        for (n, t) in self.actual.iter().enumerate() {
+            match remaining.iter().position(|e| *e == t || wildcard_matches(e, t) ) {
+                Some(p) => { remaining.remove(p); },
+                Option::None => panic!("{}unexpected transaction {:?}{} at index {} (unordered)", self.tag(), t, self.name_of(t), n),
+            }
+        }
+
+        if let Some(t) = remaining.first() {
+            panic!("{}{} expectations were not consumed (unordered), first: {:?}", self.tag(), remaining.len(), t);
+        }
+    }
+
+    fn finalise_unordered_within_window(&mut self, cs: Id) {
+        self.check_index();
+        self.check_framing();
+        self.check_errors_fired();
+
+        let expected = unordered_windows(&self.expected, cs);
+        let actual = unordered_windows(&self.actual, cs);
+
+        self.check_expectations_set(&expected, &actual);
+
+        self.assert_match(&expected, &actual, " (unordered within windows)");
+    }
+}
+
+/// Normalise writes within chip select windows (`SetLow` to `SetHigh`) for order independent comparison
+/// Within each window, other transactions are kept in order followed by the writes in a canonical order
+fn unordered_windows<W: Word>(t: &[MockTransaction<W>], cs: Id) -> Vec<&MockTransaction<W>> {
+    let mut out = Vec::with_capacity(t.len());
+    let mut window: Option<(Vec<_>, Vec<_>)> = Option::None;
+
+    fn flush<'a, W: Word>(out: &mut Vec<&'a MockTransaction<W>>, (others, mut writes): (Vec<&'a MockTransaction<W>>, Vec<&'a MockTransaction<W>>)) {
+        writes.sort_by_cached_key(|w| format!("{:?}", w) );
+        out.extend(others);
+        out.extend(writes);
+    }
+
+    for v in t.iter() {
+        match (v, &mut window) {
+            (MockTransaction::SetLow(id), Option::None) if *id == cs => {
+                out.push(v);
+                window = Some((vec![], vec![]));
+            },
+            (MockTransaction::SetHigh(id), Some(_)) if *id == cs => {
+                flush(&mut out, window.take().unwrap());
+                out.push(v);
+            },
+            (MockTransaction::SpiWrite(..), Some((_, writes))) 
+                    | (MockTransaction::SpiWriteStatus(..), Some((_, writes))) 
+                    | (MockTransaction::Write(..), Some((_, writes))) 
+                    | (MockTransaction::TransferTx(..), Some((_, writes))) => writes.push(v),
+            (_, Some((others, _))) => others.push(v),
+            (_, Option::None) => out.push(v),
+        }
+    }
+
+    if let Some(w) = window {
+        flush(&mut out, w);
+    }
+
+    out
+}
+
+/// Fold the prefix of a write into its data, so transactions compare independent of the boundary
+fn concat_prefix<W: Word>(t: &MockTransaction<W>) -> MockTransaction<W> {
+    match t {
+        MockTransaction::SpiWrite(id, prefix, data) => {
+            MockTransaction::SpiWrite(*id, vec![], [&prefix[..], &data[..]].concat())
+        },
+        MockTransaction::SpiWriteStatus(id, prefix, data, status) => {
+            MockTransaction::SpiWriteStatus(*id, vec![], [&prefix[..], &data[..]].concat(), status.clone())
+        },
+        _ => t.clone(),
+    }
+}
+
+/// Check whether a wildcard expectation matches an actual transaction
+fn wildcard_matches<W: Word>(expected: &MockTransaction<W>, actual: &MockTransaction<W>) -> bool {
+    match (expected, actual) {
+        (MockTransaction::SpiWriteAny(e, p), MockTransaction::SpiWrite(a, prefix, _)) => e == a && p == prefix,
+        (MockTransaction::WriteAny(e), MockTransaction::Write(a, _)) => e == a,
+        (MockTransaction::DelayMsAtLeast(min), MockTransaction::DelayMs(v)) => v >= min,
+        (MockTransaction::DelayMsAtMost(max), MockTransaction::DelayMs(v)) => v <= max,
+        (MockTransaction::DelayMsRange(min, max), MockTransaction::DelayMs(v)) => v >= min && v <= max,
+        _ => false,
+    }
+}
+
+/// Replace actual transactions matched by wildcard expectations at the same index with the wildcard
+fn mask_wildcards<W: Word>(expected: &[MockTransaction<W>], actual: &[MockTransaction<W>]) -> Vec<MockTransaction<W>> {
+    actual.iter().enumerate().map(|(n, a)| match expected.get(n) {
+        Some(e) if wildcard_matches(e, a) => e.clone(),
+        _ => a.clone(),
+    }).collect()
+}
+
+impl Mock<u8> {
+    /// Create a new (byte-oriented) mock instance
+    /// Mocks for other word types can be created with `Mock::<W>::default()`
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Create a new mock instance that fills `spi_read` buffers with pseudo-random data
+    /// 
+    /// Reads without matching expectations are filled from an RNG seeded with the provided value,
+    /// and the generated data is recorded in the actual transactions, so runs are reproducible
+    /// from the seed. This requires the `fuzz` feature.
+    #[cfg(feature = "fuzz")]
+    pub fn with_read_seed(seed: u64) -> Self {
+        let m = Self::new();
+        lock(&m.inner).rng = Some(XorShiftRng::seed_from_u64(seed));
+        m
+    }
+
+    /// Model a register that reads as zero until the device has been reset
+    /// 
+    /// `spi_read`s of the provided prefix without expected data return `value` once a reset has
+    /// occurred (`set_reset(PinState::High)`, or the command set with `soft_reset_command`),
+    /// allowing tests to verify a driver resets the device before reading (eg.) its identifier.
+    pub fn register_after_reset(&mut self, prefix: &[u8], value: u8) {
+        lock(&self.inner).reset_registers.push((prefix.to_vec(), value));
+    }
+
+    /// Set the command (written as a prefix with no data) treated as a soft reset
+    pub fn soft_reset_command(&mut self, cmd: &[u8]) {
+        lock(&self.inner).soft_reset = Some(cmd.to_vec());
+    }
+
+    /// Set the endianness used by the `spi_write_u16` / `spi_read_u16` (and `u32`) expectation helpers
+    /// This defaults to big endian, with the explicitly suffixed `MockTransaction` helpers unaffected
+    pub fn set_word_endianness(&mut self, e: Endianness) {
+        lock(&self.inner).endianness = e;
+    }
+
+    /// Create a write of a `u16` value in the configured endianness
+    pub fn spi_write_u16<A: AsRef<[u8]>>(&self, spi: &Spi, prefix: A, value: u16) -> MockTransaction {
+        MockTransaction::spi_write(spi, prefix, lock(&self.inner).endianness.encode(u32::from(value), 2))
+    }
+
+    /// Create a write of a `u32` value in the configured endianness
+    pub fn spi_write_u32<A: AsRef<[u8]>>(&self, spi: &Spi, prefix: A, value: u32) -> MockTransaction {
+        MockTransaction::spi_write(spi, prefix, lock(&self.inner).endianness.encode(value, 4))
+    }
+
+    /// Create a read returning a `u16` value in the configured endianness
+    pub fn spi_read_u16<A: AsRef<[u8]>>(&self, spi: &Spi, prefix: A, value: u16) -> MockTransaction {
+        MockTransaction::spi_read(spi, prefix, lock(&self.inner).endianness.encode(u32::from(value), 2))
+    }
+
+    /// Create a read returning a `u32` value in the configured endianness
+    pub fn spi_read_u32<A: AsRef<[u8]>>(&self, spi: &Spi, prefix: A, value: u32) -> MockTransaction {
+        MockTransaction::spi_read(spi, prefix, lock(&self.inner).endianness.encode(value, 4))
+    }
+
+    /// Fetch the power mode of a peripheral in effect at the provided actual transaction index
+    /// 
+    /// Mode transitions are identified as single word `spi_write`s of the `PowerMode::mode_command`
+    /// for the driver type `P`, with no transitions prior to the index returning `None`.
+    pub fn mode_at<P: PowerMode>(&self, id: Id, index: usize) -> Option<Mode> {
+        let i = lock(&self.inner);
+
+        i.actual.iter().take(index).rev().find_map(|t| match t {
+            MockTransaction::SpiWrite(p, prefix, data) if *p == id && prefix.len() == 1 && data.is_empty() => {
+                [Mode::Sleep, Mode::Standby, Mode::Active].iter().cloned().find(|m| P::mode_command(*m) == prefix[0] )
+            },
+            _ => Option::None,
+        })
+    }
+
+    /// Assert the actual transactions match a golden expectation file in the text format
+    /// 
+    /// On mismatch this panics with a unified diff against the golden file, where removed and
+    /// unchanged lines are annotated with their line numbers in the file.
+    pub fn assert_matches_golden(&self, path: &str) {
+        let i = lock(&self.inner);
+
+        let s = std::fs::read_to_string(path)
+            .unwrap_or_else(|e| panic!("{}error reading golden file '{}': {}", i.tag(), path, e) );
+        let golden = dsl::parse_with_lines(&s)
+            .unwrap_or_else(|e| panic!("{}error parsing golden file '{}': {}", i.tag(), path, e) );
+
+        if let Some(d) = dsl::golden_diff(&golden, &i.actual) {
+            panic!("{}actual transactions do not match golden file\n--- {}\n+++ actual\n{}", i.tag(), path, d);
+        }
+    }
+
+    /// Write the actual transactions to a golden file in the text format
+    pub fn bless(&self, path: &str) {
+        let i = lock(&self.inner);
+
+        std::fs::write(path, dsl::format_expectations(&i.actual))
+            .unwrap_or_else(|e| panic!("{}error writing golden file '{}': {}", i.tag(), path, e) );
+    }
+
+    /// Check the actual transactions against a golden file, re-blessing it when `BLESS=1`
+    /// 
+    /// This supports a snapshot workflow: run once with `BLESS=1` set in the environment to
+    /// record the reference trace, then subsequent runs fail on divergence (see `assert_matches_golden`).
+    pub fn check_against(&self, path: &str) {
+        match std::env::var("BLESS") {
+            Ok(v) if v == "1" => self.bless(path),
+            _ => self.assert_matches_golden(path),
+        }
+    }
+
+    /// Emit the actual transactions as Rust source for use as test expectations
+    /// 
+    /// This produces a `vec![...]` of `MockTransaction` constructor calls, with SPI handles
+    /// named `s<id>` and pins `p<id>`, so a permissive run of an existing driver (with no
+    /// expectations set) can be pasted into a test as a starting expectation set.
+    pub fn emit_expectations(&self) -> String {
+        let i = lock(&self.inner);
+
+        codegen::emit_expectations(&i.actual)
+    }
+
+    /// Check whether any write-type transaction on a peripheral started with the provided opcode
+    /// 
+    /// This considers the first outgoing byte of writes (prefix, or data if no prefix is provided),
+    /// transfers and the first operation of exec transactions.
+    pub fn assert_opcode_issued(&self, id: Id, opcode: u8) -> bool {
+        let i = lock(&self.inner);
+
+        i.actual.iter().filter(|t| t.id() == Some(id) ).any(|t| {
+            let first = match t {
+                MockTransaction::SpiWrite(_, prefix, data) | MockTransaction::SpiWriteStatus(_, prefix, data, _) => {
+                    prefix.first().or_else(|| data.first() )
+                },
+                MockTransaction::SpiExec(_, ops) => match ops.first() {
+                    Some(MockExec::SpiWrite(d)) => d.first(),
+                    _ => Option::None,
+                },
+                MockTransaction::Write(_, data) | MockTransaction::Transfer(_, data, _) | MockTransaction::TransferTx(_, data) => data.first(),
+                MockTransaction::TransferAbort(_, data, ..) | MockTransaction::WriteRead(_, data, _) => data.first(),
+                _ => Option::None,
+            };
+            first == Some(&opcode)
+        })
+    }
+}
+
+impl Inner<u8> {
+    /// Fill a read buffer for a register modelled after reset, returning false for other registers
+    /// Modelled registers read as zero until a reset has occurred
+    fn fill_reset_register(&self, prefix: &[u8], data: &mut [u8]) -> bool {
+        let v = match self.reset_registers.iter().find(|(p, _)| p[..] == *prefix ) {
+            Some((_, v)) if self.reset_done => *v,
+            Some(_) => 0x00,
+            Option::None => return false,
+        };
+
+        data.iter_mut().for_each(|d| *d = v );
+        true
+    }
+
+    /// Fill a read buffer with no expected data
+    fn fill_read(&mut self, _data: &mut [u8]) {
+        if self.fill_words(_data) {
+            return;
+        }
+
+        // Buffers already contain the outgoing data
+        if self.loopback {
+            return;
+        }
+
+        #[cfg(feature = "fuzz")]
+        if let Some(rng) = &mut self.rng {
+            rng.fill_bytes(_data);
+        }
+    }
+}
+
+impl <W: Word> Default for Mock<W> {
+    fn default() -> Self {
+        Self{ inner: Arc::new(Mutex::new(Inner::new())), handles: Vec::new() } 
+    }
+}
+
+impl <W: Word> Mock<W> {
+
+    /// Set expectations on the instance
+    /// This clears recorded transactions and per-run state, as for `reset`
+    pub fn expect<T>(&mut self, transactions: T) 
+    where 
+        T: AsRef<[MockTransaction<W>]> 
+    {
+        let mut i = lock(&self.inner);
+
+        i.clear_run_state();
+        i.expected = transactions.as_ref().to_vec();
+    }
+
+    /// Reset the mock for a new test phase, clearing expectations and recorded transactions
+    /// 
+    /// Issued handles remain valid and configuration (labels, hooks, models and modes) is kept,
+    /// while per-run state (virtual time, pin states, device resets and poll counts) is cleared.
+    pub fn reset(&mut self) {
+        let mut i = lock(&self.inner);
+
+        i.clear_run_state();
+        i.expected = vec![];
+    }
+
+    /// Disable automatic finalisation when the mock is dropped
+    /// For tests that intentionally leave expectations unmet or only record transactions
+    pub fn skip_finalise(&mut self) {
+        lock(&self.inner).skip_finalise = true;
+    }
+
+    /// Set expectations on the instance to be matched in any order
+    /// 
+    /// Each operation is served by the first remaining expectation for the same peripheral and
+    /// kind of operation (so read data and pin states are returned as for ordered expectations),
+    /// and `finalise` checks that every expectation was consumed exactly once with no unexpected
+    /// transactions. `finalise_with` also compares unordered (ignoring any match options), while
+    /// `finalise_unordered_within_window` remains positional.
+    pub fn expect_unordered<T>(&mut self, transactions: T) 
+    where 
+        T: AsRef<[MockTransaction<W>]> 
+    {
+        self.expect(transactions);
+        lock(&self.inner).unordered = true;
+    }
+
+    /// Set a single expectation on the instance
+    pub fn expect_one(&mut self, transaction: MockTransaction<W>) {
+        self.expect([transaction]);
+    }
+
+    /// Set an array of expectations on the instance
+    pub fn expect_all<const N: usize>(&mut self, transactions: [MockTransaction<W>; N]) {
+        self.expect(transactions);
+    }
+
+    /// Set expectations from a sequence of driver commands
+    pub fn expect_commands<C, I>(&mut self, spi_id: Id, cmds: I) 
+    where
+        C: IntoExpectations<W>,
+        I: IntoIterator<Item = C>,
+    {
+        let t: Vec<_> = cmds.into_iter().flat_map(|c| c.into_expectations(spi_id) ).collect();
+        self.expect(t);
+    }
+
+    /// Append the expectations of another mock to those of this instance
+    /// 
+    /// This allows reusable expectation fragments (eg. a standard init sequence) to be composed
+    /// into a full test. Ids are local to each mock (allocated in the order handles are created),
+    /// so fragments must create their handles in the same order as this instance for the ids
+    /// to refer to the same peripherals. Actual transactions are not modified.
+    pub fn extend_from(&mut self, other: &Mock<W>) {
+        let (t, r) = {
+            let o = lock(&other.inner);
+            (o.expected.clone(), o.repeats.clone())
+        };
+
+        let mut i = lock(&self.inner);
+        let offset = i.expected.len();
+        i.repeats.extend(r.iter().map(|(start, count)| (start + offset, *count) ));
+        i.expected.extend(t);
+    }
+
+    /// Append an expectation repeated `count` times, for polling loops
+    /// 
+    /// The transaction is expanded in the expectations (so each occurrence is served in order,
+    /// as for individual expectations), and `finalise` fails with the number of repetitions
+    /// found if the driver undershoots or exceeds the count.
+    pub fn expect_repeated(&mut self, transaction: MockTransaction<W>, count: usize) {
+        let mut i = lock(&self.inner);
+
+        let start = i.expected.len();
+        i.repeats.push((start, count));
+        i.expected.extend(core::iter::repeat_n(transaction, count));
+    }
+
+    /// Append a single expectation, retaining existing expectations and actuals
+    /// This allows tests to interleave setting expectations with running driver steps
+    pub fn expect_next(&mut self, transaction: MockTransaction<W>) {
+        let mut i = lock(&self.inner);
+
+        i.expected.push(transaction);
+        i.finalised = false;
+    }
+
+    /// Assert all expectations set so far have been consumed
+    /// Unlike `finalise` this may be called at any point, for example between driver steps
+    pub fn assert_done(&self) {
+        let i = lock(&self.inner);
+
+        if i.index != i.expected.len() {
+            panic!("{}consumed {} of {} expectations (next: {:?})", i.tag(), i.index, i.expected.len(), i.expected.get(i.index));
+        }
+    }
+
+    /// Register a callback to be invoked with each actual transaction as it is recorded
+    /// 
+    /// This is called while the mock is locked, so the callback must not call back into
+    /// the mock or any of its `Spi`, `Pin` or `Delay` instances (this will deadlock).
+    /// Panicking from the callback is fine, and can be used to fail a test early.
+    pub fn on_each<F>(&mut self, f: F) 
+    where
+        F: FnMut(&MockTransaction<W>) + Send + 'static,
+    {
+        lock(&self.inner).on_each = Some(Hook(Box::new(f)));
+    }
+
+    /// Register a transform applied to outgoing data before it is recorded
+    /// 
+    /// This allows escaped or bit-stuffed wire data to be recorded (and so matched against
+    /// expectations) in logical form, or vice versa. Each outgoing buffer (prefixes, write data,
+    /// transfer and exec writes) is transformed independently, while read data is recorded as-is.
+    /// The driver still sees the untransformed data, and the transform is the identity by default.
+    pub fn set_tx_transform<F>(&mut self, f: F)
+    where
+        F: Fn(&[W]) -> Vec<W> + Send + 'static,
+    {
+        lock(&self.inner).tx_transform = Some(Hook(Box::new(f)));
+    }
+
+    /// Register a callback computing the data returned by reads without expected data
+    /// 
+    /// The callback is passed the prefix for `spi_read`, or the outgoing data for transfers and
+    /// `write_read`, and must return exactly as many words as the driver buffer holds.
+    /// This allows stateful devices (such as register files) to be simulated without enumerating
+    /// every value, while reads with matching expectations still return the expected data.
+    /// As with `on_each`, the callback must not call back into the mock.
+    pub fn on_read<F>(&mut self, f: F)
+    where
+        F: FnMut(&[W]) -> Vec<W> + Send + 'static,
+    {
+        lock(&self.inner).on_read = Some(Hook(Box::new(f)));
+    }
+
+    pub fn spi(&mut self) -> Spi<W> {
+        let id = self.handle(HandleKind::Spi, Option::None);
+        Spi{ inner: self.inner.clone(), id }
+    }
+
+    pub fn pin(&mut self) -> Pin<W> {
+        let id = self.handle(HandleKind::Pin, Option::None);
+        Pin{ inner: self.inner.clone(), id }
+    }
+
+    pub fn delay(&mut self) -> Delay<W> {
+        let id = self.handle(HandleKind::Delay, Option::None);
+        Delay{ inner: self.inner.clone(), id }
+    }
+
+    /// Create an SPI peripheral with a human-readable name for failure messages
+    pub fn spi_named(&mut self, name: &str) -> Spi<W> {
+        let id = self.handle(HandleKind::Spi, Some(name));
+        Spi{ inner: self.inner.clone(), id }
+    }
+
+    /// Create a pin with a human-readable name for failure messages
+    pub fn pin_named(&mut self, name: &str) -> Pin<W> {
+        let id = self.handle(HandleKind::Pin, Some(name));
+        Pin{ inner: self.inner.clone(), id }
+    }
+
+    /// Create a delay with a human-readable name for failure messages
+    pub fn delay_named(&mut self, name: &str) -> Delay<W> {
+        let id = self.handle(HandleKind::Delay, Some(name));
+        Delay{ inner: self.inner.clone(), id }
+    }
+
+    /// Fetch the name of a handle, defaulting to the handle kind and Id (eg. `spi0`)
+    pub fn name(&self, id: Id) -> Option<String> {
+        lock(&self.inner).names.get(id as usize).cloned()
+    }
+
+    /// Allocate the Id and name for a new handle
+    fn handle(&mut self, kind: HandleKind, name: Option<&str>) -> Id {
+        let id = self.handles.len() as Id;
+        self.handles.push(kind);
+
+        let name = match (name, kind) {
+            (Some(n), _) => n.to_string(),
+            (Option::None, HandleKind::Spi) => format!("spi{}", id),
+            (Option::None, HandleKind::Pin) => format!("pin{}", id),
+            (Option::None, HandleKind::Delay) => format!("delay{}", id),
+        };
+        lock(&self.inner).names.push(name);
+
+        id
+    }
+
+    /// Create a builder for a device bundling an SPI peripheral with named pins
+    pub fn device(&mut self) -> DeviceBuilder<'_, W> {
+        DeviceBuilder{ mock: self, cs: false, busy: false, ready: false, reset: false }
+    }
+
+    /// Create a builder for a sequence of expectations, registered with `MockBuilder::finish`
+    /// This is equivalent to calling `expect` with the accumulated transactions
+    pub fn build(&mut self) -> MockBuilder<'_, W> {
+        MockBuilder{ mock: self, txns: Vec::new() }
+    }
+
+    /// Duplicate the mock with the same expectations
+    /// 
+    /// This returns a new independent mock with the current expectations and configuration
+    /// (but not the `on_each` callback or transmit transform), along with fresh handles matching those minted from
+    /// this instance (in Id order), so the same expectations apply.
+    pub fn duplicate(&self) -> (Mock<W>, Vec<Handle<W>>) {
+        let i = lock(&self.inner);
+
+        let mut m = Mock::default();
+        {
+            let mut n = lock(&m.inner);
+            n.expected = i.expected.clone();
+            n.strict_polls = i.strict_polls;
+            n.strict = i.strict;
+            n.skip_finalise = i.skip_finalise;
+            n.trailing_delays = i.trailing_delays;
+            n.concat_prefix = i.concat_prefix;
+            n.loopback = i.loopback;
+            n.label = i.label.clone();
+            n.deadline_us = i.deadline_us;
+            n.read_fifo = i.read_fifo.clone();
+            n.errors_fired = i.errors_fired;
+            n.register_model = i.register_model;
+            n.endianness = i.endianness;
+            n.busy_forever = i.busy_forever.keys().map(|id| (*id, 0) ).collect();
+            n.reset_registers = i.reset_registers.clone();
+            n.soft_reset = i.soft_reset.clone();
+            n.operation_cap = i.operation_cap;
+            n.unordered = i.unordered;
+            n.repeats = i.repeats.clone();
+            n.interrupts = i.interrupts.iter().map(|(id, int)| (*id, Interrupt{ level: int.level, reads: vec![] }) ).collect();
+            #[cfg(feature = "fuzz")]
+            {
+                n.rng = i.rng.clone();
+            }
+        }
+
+        let handles = self.handles.iter().map(|k| match k {
+            HandleKind::Spi => Handle::Spi(m.spi()),
+            HandleKind::Pin => Handle::Pin(m.pin()),
+            HandleKind::Delay => Handle::Delay(m.delay()),
+        }).collect();
+
+        lock(&m.inner).names = i.names.clone();
+
+        (m, handles)
+    }
+
+    /// Label the run, for identifying parameterised tests in failure messages
+    pub fn label(&mut self, label: &str) {
+        lock(&self.inner).label = Some(label.to_string());
+    }
+
+    /// Fetch the virtual time elapsed (from recorded delays) since expectations were set
+    pub fn elapsed_us(&self) -> u64 {
+        lock(&self.inner).elapsed_us
+    }
+
+    /// Set a virtual time budget for the run
+    /// 
+    /// When set, the mock panics as soon as the virtual time accumulated from delays exceeds
+    /// the deadline, rather than waiting for the run to complete.
+    pub fn set_deadline_us(&mut self, deadline_us: u64) {
+        lock(&self.inner).deadline_us = Some(deadline_us);
+    }
+
+    /// Enable or disable strict polling
+    /// 
+    /// When enabled, calls to `get_busy` or `get_ready` after all expectations have been
+    /// consumed will panic rather than returning a default `PinState::Low`, so over-polling
+    /// can not silently terminate a driver wait loop.
+    pub fn strict_polls(&mut self, enabled: bool) {
+        lock(&self.inner).strict_polls = enabled;
+    }
+
+    /// Enable or disable strict mode
+    /// 
+    /// When enabled, calls to `get_busy`, `get_ready`, `is_high` or `is_low` panic immediately
+    /// unless the current expectation is the same call on the same peripheral, rather than
+    /// returning a default (`PinState::Low` or `false`).
+    pub fn strict(&mut self, enabled: bool) {
+        lock(&self.inner).strict = enabled;
+    }
+
+    /// Cap the number of recorded transactions, to turn runaway driver loops into fast failures
+    /// Recording beyond the cap panics, with the default of `None` being unlimited
+    pub fn set_operation_cap(&mut self, cap: Option<usize>) {
+        lock(&self.inner).operation_cap = cap;
+    }
+
+    /// Record a named user marker in the actual transactions
+    /// Markers advance the expectation index, so must be matched by `MockTransaction::user`
+    pub fn mark(&self, name: &str) {
+        lock(&self.inner).record(MockTransaction::User(name.to_string()));
+    }
+
+    /// Rewind the last actual transaction, stepping back the expectation index
+    /// This is a no-op if no transactions have been recorded
+    pub fn rewind(&self) {
+        let mut i = lock(&self.inner);
+
+        if let Some(t) = i.actual.pop() {
+            i.sequence.pop();
+            if let Some(us) = t.delay_duration_us() {
+                i.elapsed_us = i.elapsed_us.saturating_sub(us);
+            }
+            if let Some((id, _)) = t.pin_level() {
+                let last = i.actual.iter().rev().find_map(|t| t.pin_level().filter(|(p, _)| *p == id) );
+                match last {
+                    Some((_, state)) => i.pin_states.insert(id, state),
+                    None => i.pin_states.remove(&id),
+                };
+            }
+            i.index = i.index.saturating_sub(1);
+        }
+    }
+
+    /// Check whether all expectations have been reached
+    pub fn is_done(&self) -> bool {
+        let i = lock(&self.inner);
+        i.index >= i.expected.len()
+    }
+
+    /// Fetch the expectations that have not yet been reached
+    pub fn unmet(&self) -> Vec<MockTransaction<W>> {
+        let i = lock(&self.inner);
+        i.expected.get(i.index..).unwrap_or(&[]).to_vec()
+    }
+
+    /// Enable or disable the strict register model
+    /// 
+    /// When enabled, `spi_read`s without matching expected data panic if the register they address
+    /// has not previously been written, read with expected data, or stubbed with `stub_register`
+    /// on the same peripheral. Registers are addressed by the prefix following the initial
+    /// command word (ie. `prefix[1..]`), or by the whole prefix where this is a single word.
+    /// Use `set_register_address` for other address encodings.
+    pub fn strict_register_model(&mut self, enabled: bool) {
+        lock(&self.inner).register_model = enabled;
+    }
+
+    /// Register a function extracting the register address from a read or write prefix
+    /// 
+    /// This replaces the default addressing of the strict register model, for example to mask
+    /// read / write flags from single-word prefixes so reads and writes address the same register.
+    pub fn set_register_address<F>(&mut self, f: F)
+    where
+        F: Fn(&[W]) -> Vec<W> + Send + 'static,
+    {
+        lock(&self.inner).register_address = Some(Hook(Box::new(f)));
+    }
+
+    /// Mark a register (addressed as for the strict register model) as initialised
+    pub fn stub_register(&mut self, id: Id, address: &[W]) {
+        lock(&self.inner).known_register(id, address);
+    }
+
+    /// Report a peripheral as busy indefinitely, to exercise driver timeout paths
+    /// 
+    /// Subsequent `get_busy` calls return `PinState::High` without consuming expectations or
+    /// being recorded in the actual transactions, with polls counted for `assert_busy_polls`.
+    pub fn busy_forever(&mut self, id: Id) {
+        lock(&self.inner).busy_forever.entry(id).or_insert(0);
+    }
+
+    /// Assert the number of busy polls on a peripheral
+    /// This counts both recorded `Busy` transactions and polls while `busy_forever` is set
+    pub fn assert_busy_polls(&self, id: Id, n: usize) {
+        if let Err(f) = self.check_busy_polls(id, n) {
+            panic!("{}", f);
+        }
+    }
+
+    /// Check variant of `assert_busy_polls`, returning a `CheckFailure` rather than panicking
+    pub fn check_busy_polls(&self, id: Id, n: usize) -> Result<(), CheckFailure> {
+        let i = lock(&self.inner);
+
+        let recorded = i.actual.iter().filter(|t| matches!(t, MockTransaction::Busy(p, _) if *p == id) ).count();
+        let polls = recorded + i.busy_forever.get(&id).cloned().unwrap_or(0);
+
+        if polls != n {
+            return Err(i.failure("busy_polls", i.actual.len(), format!("unexpected number of busy polls on peripheral {} ({}, expected {})", id, polls, n)));
+        }
+
+        Ok(())
+    }
+
+    /// Mark a pin as an interrupt (or data ready) line
+    /// 
+    /// Reads of interrupt pins do not consume expectations, and are recorded to a separate
+    /// stream ignored by `finalise`, available via `interrupt_reads`.
+    /// Reads return the level set with `set_interrupt_level`, defaulting to low.
+    pub fn mark_interrupt_pin(&mut self, id: Id) {
+        lock(&self.inner).interrupts.entry(id).or_insert(Interrupt{ level: false, reads: vec![] });
+    }
+
+    /// Set the level returned by reads of an interrupt pin
+    pub fn set_interrupt_level(&mut self, id: Id, high: bool) {
+        let mut i = lock(&self.inner);
+        match i.interrupts.get_mut(&id) {
+            Some(int) => int.level = high,
+            Option::None => panic!("{}pin {} is not marked as an interrupt", i.tag(), id),
+        }
+    }
+
+    /// Fetch the reads of an interrupt pin, in the order they occurred
+    pub fn interrupt_reads(&self, id: Id) -> Vec<MockTransaction<W>> {
+        lock(&self.inner).interrupts.get(&id).map(|int| int.reads.clone() ).unwrap_or_default()
+    }
+
+    /// Fetch the actual transactions recorded so far, for custom assertions
+    pub fn actual(&self) -> Vec<MockTransaction<W>> {
+        lock(&self.inner).actual.clone()
+    }
+
+    /// Fetch the expected transactions
+    pub fn expected(&self) -> Vec<MockTransaction<W>> {
+        lock(&self.inner).expected.clone()
+    }
+
+    /// Fetch the most recent actual transaction
+    pub fn last(&self) -> Option<MockTransaction<W>> {
+        lock(&self.inner).actual.last().cloned()
+    }
+
+    /// Fetch the actual transaction at the provided index
+    pub fn nth(&self, n: usize) -> Option<MockTransaction<W>> {
+        lock(&self.inner).actual.get(n).cloned()
+    }
+
+    /// Fetch the actual transactions tagged with their global sequence numbers
+    /// 
+    /// Transactions from all handles (including clones used from different threads) are
+    /// serialised by the mock lock, with sequence numbers assigned in lock acquisition order.
+    /// Numbers increase monotonically for the lifetime of the mock, so are not reused after
+    /// `rewind` or resetting expectations.
+    pub fn sequenced_actual(&self) -> Vec<(u64, MockTransaction<W>)> {
+        let i = lock(&self.inner);
+        i.sequence.iter().cloned().zip(i.actual.iter().cloned()).collect()
+    }
+
+    /// Push data to the read FIFO
+    /// 
+    /// Reads and transfers without explicit incoming expectations draw from this FIFO
+    /// (as many words as the buffer length, if available), taking precedence over loopback
+    /// and seeded data. The drawn data is recorded in the actual transactions.
+    pub fn push_read_data(&mut self, data: Vec<W>) {
+        lock(&self.inner).read_fifo.extend(data);
+    }
+
+    /// Enable or disable loopback mode
+    /// 
+    /// When enabled, reads and transfers without explicit incoming expectations return the
+    /// outgoing data (as if MISO were tied to MOSI), and this is recorded in the actual
+    /// transactions. This takes precedence over seeded read data.
+    pub fn loopback(&mut self, enabled: bool) {
+        lock(&self.inner).loopback = enabled;
+    }
+
+    /// Require all injected errors to have been triggered at finalise
+    /// 
+    /// When enabled, `finalise` fails with an explicit message if any `Inject` expectation
+    /// was not reached by the driver, rather than (or prior to) the general mismatch.
+    pub fn assert_all_errors_fired(&mut self, enabled: bool) {
+        lock(&self.inner).errors_fired = enabled;
+    }
+
+    /// Allow trailing delays at finalise
+    /// 
+    /// When enabled, delays at the end of the actual transactions with no counterpart
+    /// in the expectations are dropped prior to comparison, so a final settling delay
+    /// does not need to be pinned. Other delays are still compared as normal.
+    pub fn allow_trailing_delays(&mut self, enabled: bool) {
+        lock(&self.inner).trailing_delays = enabled;
+    }
+
+    /// Compare writes by concatenated prefix and data at finalise
+    /// 
+    /// When enabled, `finalise` compares `prefix ++ data` for each `spi_write` and `spi_write_status`,
+    /// so moving words between the prefix and data of a transaction does not cause a mismatch.
+    /// Transactions are still compared individually.
+    pub fn finalise_concat_prefix(&mut self, enabled: bool) {
+        lock(&self.inner).concat_prefix = enabled;
+    }
+
+    /// Split the actual transactions into a stream per peripheral Id
+    /// 
+    /// Transactions without an Id (ie. delays) are duplicated into every stream at the
+    /// point they occurred, so each stream retains the timing of the overall run.
+    pub fn per_peripheral(&self) -> HashMap<Id, Vec<MockTransaction<W>>> {
+        let i = lock(&self.inner);
+
+        let mut streams = HashMap::new();
+        for id in i.actual.iter().filter_map(|t| t.id() ) {
+            streams.entry(id).or_insert_with(Vec::new);
+        }
+
+        for t in i.actual.iter() {
+            match t.id() {
+                Some(id) => streams.get_mut(&id).unwrap().push(t.clone()),
+                None => streams.values_mut().for_each(|s| s.push(t.clone()) ),
+            }
+        }
+
+        streams
+    }
+
+    /// Count the actual transactions by coarse operation type
+    /// 
+    /// Keys are `write` (spi_write / write_status / Write), `read` (spi_read / read_with_dummy),
+    /// `exec`, `transfer`, `pin_set` (SetHigh / SetLow / Reset), `pin_read` (IsHigh / IsLow / Busy / Ready)
+    /// and `delay`. Other transactions are not counted, and absent keys indicate no occurrences.
+    pub fn op_histogram(&self) -> HashMap<&'static str, usize> {
+        use MockTransaction::*;
+
+        let i = lock(&self.inner);
+
+        let mut h = HashMap::new();
+        for t in i.actual.iter() {
+            let op = match t {
+                SpiWrite(..) | SpiWriteStatus(..) | Write(..) => "write",
+                SpiRead(..) | SpiReadDummy(..) => "read",
+                SpiExec(..) => "exec",
+                Transfer(..) | TransferTx(..) | TransferAbort(..) | WriteRead(..) => "transfer",
+                SetHigh(..) | SetLow(..) | Reset(..) => "pin_set",
+                IsHigh(..) | IsLow(..) | Busy(..) | Ready(..) => "pin_read",
+                DelayMs(..) | DelayUs(..) => "delay",
+                _ => continue,
+            };
+            *h.entry(op).or_insert(0) += 1;
+        }
+
+        h
+    }
+
+    /// Fetch the actual transactions, each paired with the chip select asserted at the time
+    /// 
+    /// Chip selects are tracked from the `SetLow` / `SetHigh` transactions on the provided pins
+    /// (as recorded when driving mock handles through a `Wrapper`), with the most recently
+    /// asserted of these reported for each transaction.
+    pub fn actual_with_cs(&self, cs: &[Id]) -> Vec<(MockTransaction<W>, Option<Id>)> {
+        let i = lock(&self.inner);
+
+        let mut asserted: Vec<Id> = vec![];
+
+        i.actual.iter().map(|t| {
+            match t {
+                MockTransaction::SetLow(id) if cs.contains(id) && !asserted.contains(id) => asserted.push(*id),
+                MockTransaction::SetHigh(id) if cs.contains(id) => asserted.retain(|a| a != id ),
+                _ => (),
+            }
+            (t.clone(), asserted.last().cloned())
+        }).collect()
+    }
+
+    /// Fetch the final state of an output pin (or reset line) from the actual transactions
+    /// Returns `None` if the pin was never set
+    pub fn final_pin_state(&self, id: Id) -> Option<PinState> {
+        lock(&self.inner).pin_states.get(&id).cloned()
+    }
+
+    /// Assert that the last read on `read_id` returned the data last written on `write_id`
+    /// 
+    /// Writes and reads are correlated by address, taken as the prefix following the
+    /// initial command word (ie. `prefix[1..]`). Only writes prior to the read are considered.
+    pub fn assert_readback_matches(&self, write_id: Id, read_id: Id) {
+        let i = lock(&self.inner);
+
+        let (n, address, read) = i.actual.iter().enumerate().rev().find_map(|(n, t)| match t {
+            MockTransaction::SpiRead(id, prefix, data) if *id == read_id => Some((n, prefix.get(1..).unwrap_or(&[]), data)),
+            _ => None,
+        }).unwrap_or_else(|| panic!("{}no read found for peripheral {}", i.tag(), read_id) );
+
+        let write = i.actual[..n].iter().rev().find_map(|t| match t {
+            MockTransaction::SpiWrite(id, prefix, data) if *id == write_id && prefix.get(1..).unwrap_or(&[]) == address => Some(data),
+            _ => None,
+        }).unwrap_or_else(|| panic!("{}no write to address {:?} found for peripheral {}", i.tag(), address, write_id) );
+
+        assert_eq!(write, read, "{}readback mismatch at address {:?}", i.tag(), address);
+    }
+
+    /// Assert that the first matching write on a peripheral precedes the first matching read
+    /// 
+    /// Writes and reads are matched by prefix. This passes if no matching read occurred.
+    pub fn assert_write_before_read(&self, id: Id, write_prefix: &[W], read_prefix: &[W]) {
+        if let Err(f) = self.check_write_before_read(id, write_prefix, read_prefix) {
+            panic!("{}", f);
+        }
+    }
+
+    /// Check variant of `assert_write_before_read`, returning a `CheckFailure` rather than panicking
+    pub fn check_write_before_read(&self, id: Id, write_prefix: &[W], read_prefix: &[W]) -> Result<(), CheckFailure> {
+        let i = lock(&self.inner);
+
+        let write = i.actual.iter().position(|t| match t {
+            MockTransaction::SpiWrite(p, prefix, _) => *p == id && &prefix[..] == write_prefix,
+            _ => false,
+        });
+        let read = i.actual.iter().position(|t| match t {
+            MockTransaction::SpiRead(p, prefix, _) => *p == id && &prefix[..] == read_prefix,
+            _ => false,
+        });
+
+        match (write, read) {
+            (_, Option::None) => (),
+            (Option::None, Some(r)) => {
+                return Err(i.failure("write_before_read", r, format!("read {:?} at index {} on peripheral {} without prior write {:?}", read_prefix, r, id, write_prefix)));
+            },
+            (Some(w), Some(r)) if r < w => {
+                return Err(i.failure("write_before_read", r, format!("read {:?} at index {} on peripheral {} before write {:?} at index {}", read_prefix, r, id, write_prefix, w)));
+            },
+            _ => (),
+        }
+
+        Ok(())
+    }
+
+    /// Assert that a chip select pin is not released (set high) between the provided
+    /// actual transaction indices (inclusive)
+    /// 
+    /// This catches separate operations that must occur within a single CS assertion.
+    pub fn assert_no_cs_release_between(&self, cs_id: Id, from_index: usize, to_index: usize) {
+        if let Err(f) = self.check_no_cs_release_between(cs_id, from_index, to_index) {
+            panic!("{}", f);
+        }
+    }
+
+    /// Check variant of `assert_no_cs_release_between`, returning a `CheckFailure` rather than panicking
+    pub fn check_no_cs_release_between(&self, cs_id: Id, from_index: usize, to_index: usize) -> Result<(), CheckFailure> {
+        let i = lock(&self.inner);
+
+        let end = (to_index + 1).min(i.actual.len());
+        let start = from_index.min(end);
+
+        if let Some(n) = i.actual[start..end].iter().position(|t| *t == MockTransaction::SetHigh(cs_id) ) {
+            return Err(i.failure("no_cs_release_between", start + n, format!("chip select {} released at index {} (between {} and {})", cs_id, start + n, from_index, to_index)));
+        }
+
+        Ok(())
+    }
+
+    /// Assert that the chip select windows of two peripherals do not overlap
+    /// 
+    /// Windows are opened by `SetLow` and closed by `SetHigh` (ie. active low chip selects),
+    /// and this fails if either window opens while the other is still open.
+    pub fn assert_no_interleave(&self, cs_a: Id, cs_b: Id) {
+        if let Err(f) = self.check_no_interleave(cs_a, cs_b) {
+            panic!("{}", f);
+        }
+    }
+
+    /// Check variant of `assert_no_interleave`, returning a `CheckFailure` rather than panicking
+    pub fn check_no_interleave(&self, cs_a: Id, cs_b: Id) -> Result<(), CheckFailure> {
+        let i = lock(&self.inner);
+
+        let (mut a, mut b) = (false, false);
+
+        for (n, t) in i.actual.iter().enumerate() {
+            match t {
+                MockTransaction::SetLow(id) if *id == cs_a => a = true,
+                MockTransaction::SetLow(id) if *id == cs_b => b = true,
+                MockTransaction::SetHigh(id) if *id == cs_a => a = false,
+                MockTransaction::SetHigh(id) if *id == cs_b => b = false,
+                _ => continue,
+            }
+
+            if a && b {
+                return Err(i.failure("no_interleave", n, format!("chip select windows for {} and {} interleaved at index {}", cs_a, cs_b, n)));
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Assert that writes and reads on a peripheral strictly alternate (ie. command, then response)
+    /// 
+    /// Exec transactions contribute each of their operations in order, while full-duplex transfers,
+    /// delays and pin operations are ignored.
+    pub fn assert_alternating(&self, id: Id) {
+        if let Err(f) = self.check_alternating(id) {
+            panic!("{}", f);
+        }
+    }
+
+    /// Check variant of `assert_alternating`, returning a `CheckFailure` rather than panicking
+    pub fn check_alternating(&self, id: Id) -> Result<(), CheckFailure> {
+        let i = lock(&self.inner);
+
+        let mut last: Option<bool> = Option::None;
+
+        for (n, t) in i.actual.iter().enumerate().filter(|(_, t)| t.id() == Some(id) ) {
+            let dirs = match t {
+                MockTransaction::SpiWrite(..) | MockTransaction::SpiWriteStatus(..)
+                    | MockTransaction::Write(..) | MockTransaction::TransferTx(..) => vec![true],
+                MockTransaction::SpiRead(..) | MockTransaction::SpiReadDummy(..) => vec![false],
+                MockTransaction::WriteRead(..) => vec![true, false],
+                MockTransaction::SpiExec(_, ops) => ops.iter().filter(|o| !matches!(o, MockExec::Delay(_)) )
+                    .map(|o| matches!(o, MockExec::SpiWrite(_)) ).collect(),
+                _ => continue,
+            };
+
+            for write in dirs {
+                if last == Some(write) {
+                    return Err(i.failure("alternating", n, format!("consecutive {} on peripheral {} at index {}", if write { "writes" } else { "reads" }, id, n)));
+                }
+                last = Some(write);
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Assert that each command write on a peripheral is followed by a status poll before the next command
+    /// 
+    /// Commands are `spi_write`s with a prefix starting with `cmd_prefix`, and polls are `spi_read`s
+    /// with a prefix starting with `status_prefix`. At least one poll must follow each command before
+    /// the next command on the peripheral (or the end of the actual transactions), as required for
+    /// program or erase operations on nonvolatile memories. Other writes (such as write enables)
+    /// may occur between a command and its poll.
+    pub fn assert_command_then_poll(&self, id: Id, cmd_prefix: &[W], status_prefix: &[W]) {
+        if let Err(f) = self.check_command_then_poll(id, cmd_prefix, status_prefix) {
+            panic!("{}", f);
+        }
+    }
+
+    /// Check variant of `assert_command_then_poll`, returning a `CheckFailure` rather than panicking
+    pub fn check_command_then_poll(&self, id: Id, cmd_prefix: &[W], status_prefix: &[W]) -> Result<(), CheckFailure> {
+        let i = lock(&self.inner);
+
+        // Index of the last command awaiting a poll
+        let mut pending: Option<usize> = Option::None;
+
+        for (n, t) in i.actual.iter().enumerate().filter(|(_, t)| t.id() == Some(id) ) {
+            match t {
+                MockTransaction::SpiWrite(_, prefix, _) | MockTransaction::SpiWriteStatus(_, prefix, _, _) if prefix.starts_with(cmd_prefix) => {
+                    if let Some(c) = pending {
+                        return Err(i.failure("command_then_poll", n, format!("write on peripheral {} at index {} before polling status for command at index {}", id, n, c)));
+                    }
+                    pending = Some(n);
+                },
+                MockTransaction::SpiRead(_, prefix, _) if prefix.starts_with(status_prefix) => pending = Option::None,
+                _ => (),
+            }
+        }
+
+        match pending {
+            Some(c) => Err(i.failure("command_then_poll", c, format!("command on peripheral {} at index {} was not followed by a status poll", id, c))),
+            Option::None => Ok(()),
+        }
+    }
+
+    /// Assert that no chip select window clocks more than `max` words
+    /// 
+    /// This sums the words clocked by all SPI transactions between each `SetLow` and `SetHigh`
+    /// on `cs_id`, including prefixes, dummy words and both directions of exec transactions.
+    pub fn assert_max_bytes_per_window(&self, cs_id: Id, max: usize) {
+        if let Err(f) = self.check_max_bytes_per_window(cs_id, max) {
+            panic!("{}", f);
+        }
+    }
+
+    /// Check variant of `assert_max_bytes_per_window`, returning a `CheckFailure` rather than panicking
+    pub fn check_max_bytes_per_window(&self, cs_id: Id, max: usize) -> Result<(), CheckFailure> {
+        let i = lock(&self.inner);
+
+        let mut window: Option<(usize, usize)> = Option::None;
+
+        for (n, t) in i.actual.iter().enumerate() {
+            match (t, &mut window) {
+                (MockTransaction::SetLow(id), _) if *id == cs_id => window = Some((n, 0)),
+                (MockTransaction::SetHigh(id), _) if *id == cs_id => window = Option::None,
+                (_, Some((start, count))) => {
+                    *count += t.clocked_len();
+                    if *count > max {
+                        return Err(i.failure("max_bytes_per_window", n, format!("{} words clocked in chip select {} window from index {} at index {} (maximum {})", count, cs_id, start, n, max)));
+                    }
+                },
+                _ => (),
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Assert that the driver retried a status read at least `at_least` times
+    /// 
+    /// Retries are counted from the final polling run on the peripheral, that is, the consecutive
+    /// `spi_read`s of the same prefix ending with the last read (which is taken as the successful one).
+    /// Delays, pin operations and transactions on other peripherals do not break the run.
+    pub fn assert_retried(&self, id: Id, at_least: usize) {
+        if let Err(f) = self.check_retried(id, at_least) {
+            panic!("{}", f);
+        }
+    }
+
+    /// Check variant of `assert_retried`, returning a `CheckFailure` rather than panicking
+    pub fn check_retried(&self, id: Id, at_least: usize) -> Result<(), CheckFailure> {
+        let i = lock(&self.inner);
+
+        let mut reads = i.actual.iter().enumerate().rev().filter(|(_, t)| t.id() == Some(id) && !t.is_pin() );
+
+        let (n, prefix) = match reads.next() {
+            Some((n, MockTransaction::SpiRead(_, prefix, _))) => (n, prefix),
+            _ => return Err(i.failure("retried", i.actual.len(), format!("no final status read found for peripheral {}", id))),
+        };
+
+        let retries = reads.take_while(|(_, t)| matches!(t, MockTransaction::SpiRead(_, p, _) if p == prefix) ).count();
+
+        if retries < at_least {
+            return Err(i.failure("retried", n, format!("only {} retries on peripheral {} (expected at least {})", retries, id, at_least)));
+        }
+
+        Ok(())
+    }
+
+    /// Assert that a write-protect pin was deasserted for every write on a peripheral
+    /// 
+    /// The write-protect pin is taken as active low (so unprotected when high), with its state at each
+    /// write tracked from prior `SetHigh` / `SetLow` transactions on `wp_id`. Writes are `spi_write`,
+    /// `spi_write_status`, `spi::Write` and transmit-only transfers on `write_spi_id`.
+    pub fn assert_wp_deasserted_during_writes(&self, wp_id: Id, write_spi_id: Id) {
+        if let Err(f) = self.check_wp_deasserted_during_writes(wp_id, write_spi_id) {
+            panic!("{}", f);
+        }
+    }
+
+    /// Check variant of `assert_wp_deasserted_during_writes`, returning a `CheckFailure` rather than panicking
+    pub fn check_wp_deasserted_during_writes(&self, wp_id: Id, write_spi_id: Id) -> Result<(), CheckFailure> {
+        let i = lock(&self.inner);
+
+        let mut state = Option::None;
+
+        for (n, t) in i.actual.iter().enumerate() {
+            match t {
+                MockTransaction::SetHigh(id) | MockTransaction::SetLow(id) if *id == wp_id => state = t.pin_level().map(|(_, s)| s ),
+                MockTransaction::SpiWrite(id, ..) | MockTransaction::SpiWriteStatus(id, ..)
+                    | MockTransaction::Write(id, ..) | MockTransaction::TransferTx(id, ..) if *id == write_spi_id && state != Some(PinState::High) => {
+                    return Err(i.failure("wp_deasserted_during_writes", n, format!("write on peripheral {} at index {} with write protect {} {}", write_spi_id, n, wp_id,
+                        if state.is_none() { "not driven" } else { "asserted" })));
+                },
+                _ => (),
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Assert the total number of words read back from a peripheral
+    /// 
+    /// This sums reads, transfers and exec reads in the actual transactions, regardless of chunking.
+    pub fn assert_total_read(&self, id: Id, n: usize) {
+        if let Err(f) = self.check_total_read(id, n) {
+            panic!("{}", f);
+        }
+    }
+
+    /// Check variant of `assert_total_read`, returning a `CheckFailure` rather than panicking
+    pub fn check_total_read(&self, id: Id, n: usize) -> Result<(), CheckFailure> {
+        let i = lock(&self.inner);
+
+        let total: usize = i.actual.iter().filter(|t| t.id() == Some(id) ).map(|t| t.read_len() ).sum();
+
+        if total != n {
+            return Err(i.failure("total_read", i.actual.len(), format!("unexpected total read length for peripheral {} ({} words, expected {})", id, total, n)));
+        }
+
+        Ok(())
+    }
+
+    /// Assert the virtual time (in ms) between two named markers is within the provided range
+    /// 
+    /// This uses the first `start` marker and the first following `end` marker,
+    /// failing if either is not found.
+    pub fn assert_elapsed_between<R>(&self, start: &str, end: &str, range: R) 
+    where
+        R: RangeBounds<u32> + fmt::Debug,
+    {
+        let i = lock(&self.inner);
+
+        let mut elapsed_us = 0u64;
+        let mut from = Option::None;
+
+        for t in i.actual.iter() {
+            if let Some(us) = t.delay_duration_us() {
+                elapsed_us += us;
+            }
+
+            match t {
+                MockTransaction::User(name) if from.is_none() && name == start => from = Some(elapsed_us),
+                MockTransaction::User(name) if name == end => if let Some(f) = from {
+                    let ms = (elapsed_us - f) / 1000;
+                    if !range.contains(&(ms.min(u32::MAX as u64) as u32)) {
+                        panic!("{}{} ms elapsed between markers '{}' and '{}' (expected {:?})", i.tag(), ms, start, end, range);
+                    }
+                    return;
+                },
+                _ => (),
+            }
+        }
+
+        match from {
+            Option::None => panic!("{}marker '{}' not found", i.tag(), start),
+            Some(_) => panic!("{}marker '{}' not found after '{}'", i.tag(), end, start),
+        }
+    }
+
+    /// Assert that consecutive transactions of the provided kind are separated by at least `min_ms`
+    /// of recorded delay in the actual transactions
+    pub fn assert_min_delay_between(&self, kind: TxnKind, min_ms: u32) {
+        if let Err(f) = self.check_min_delay_between(kind, min_ms) {
+            panic!("{}", f);
+        }
+    }
+
+    /// Check variant of `assert_min_delay_between`, returning a `CheckFailure` rather than panicking
+    pub fn check_min_delay_between(&self, kind: TxnKind, min_ms: u32) -> Result<(), CheckFailure> {
+        let i = lock(&self.inner);
+
+        let mut last = Option::None;
+        let mut elapsed_us = 0u64;
+
+        for (n, t) in i.actual.iter().enumerate() {
+            if let Some(us) = t.delay_duration_us() {
+                elapsed_us += us;
+            }
+
+            if t.kind() != kind {
+                continue;
+            }
+
+            if let Some(l) = last {
+                if elapsed_us < min_ms as u64 * 1000 {
+                    return Err(i.failure("min_delay_between", n, format!("only {} ms between {:?} transactions at index {} and {} (minimum {} ms)",
+                        elapsed_us as f32 / 1000.0, kind, l, n, min_ms)));
+                }
+            }
+
+            last = Some(n);
+            elapsed_us = 0;
+        }
+
+        Ok(())
+    }
+
+    /// Assert minimum chip select setup and hold times, in recorded delay
+    /// 
+    /// Within each chip select window (`SetLow` to `SetHigh` on `cs_id`), this checks there is at least
+    /// `setup_ms` of delay before the first data transaction on `spi_id`, and at least `hold_ms`
+    /// of delay after the last data transaction before the chip select is released.
+    /// Windows without data transactions are ignored.
+    pub fn assert_cs_setup_hold(&self, cs_id: Id, spi_id: Id, setup_ms: u32, hold_ms: u32) {
+        if let Err(f) = self.check_cs_setup_hold(cs_id, spi_id, setup_ms, hold_ms) {
+            panic!("{}", f);
+        }
+    }
+
+    /// Check variant of `assert_cs_setup_hold`, returning a `CheckFailure` rather than panicking
+    pub fn check_cs_setup_hold(&self, cs_id: Id, spi_id: Id, setup_ms: u32, hold_ms: u32) -> Result<(), CheckFailure> {
+        let i = lock(&self.inner);
+
+        // Open window start, delay since opening (or the last data transaction), and whether data was seen
+        let mut window: Option<(usize, u64, bool)> = Option::None;
+
+        for (n, t) in i.actual.iter().enumerate() {
+            match (t, &mut window) {
+                (MockTransaction::SetLow(id), _) if *id == cs_id => window = Some((n, 0, false)),
+                (MockTransaction::SetHigh(id), Some((start, us, data))) if *id == cs_id => {
+                    if *data && *us < hold_ms as u64 * 1000 {
+                        return Err(i.failure("cs_hold", n, format!("only {} ms hold before chip select {} released at index {} (window from {}, minimum {} ms)",
+                            *us as f32 / 1000.0, cs_id, n, start, hold_ms)));
+                    }
+                    window = Option::None;
+                },
+                (_, Some((start, us, data))) => {
+                    if t.is_delay() {
+                        *us += t.delay_duration_us().unwrap_or(0);
+                        continue;
+                    }
+
+                    let is_data = matches!(t, 
+                        MockTransaction::SpiWrite(..) | MockTransaction::SpiWriteStatus(..) | MockTransaction::SpiRead(..)
+                        | MockTransaction::SpiReadDummy(..) | MockTransaction::SpiExec(..) | MockTransaction::Write(..)
+                        | MockTransaction::Transfer(..) | MockTransaction::TransferTx(..) | MockTransaction::TransferAbort(..)
+                        | MockTransaction::WriteRead(..)
+                    );
+                    if !is_data || t.id() != Some(spi_id) {
+                        continue;
+                    }
+
+                    if !*data && *us < setup_ms as u64 * 1000 {
+                        return Err(i.failure("cs_setup", n, format!("only {} ms setup after chip select {} asserted at index {} (first data at {}, minimum {} ms)",
+                            *us as f32 / 1000.0, cs_id, start, n, setup_ms)));
+                    }
+
+                    *data = true;
+                    *us = 0;
+                },
+                _ => (),
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Run a batch of protocol checks, returning a result for each (in order) rather than panicking
+    /// 
+    /// This covers each assertion with a `check_*` variant (see `Check`). Readback, marker timing,
+    /// golden file and completion assertions compare against external state, so are not batched.
+    pub fn run_checks(&self, checks: &[Check<W>]) -> Vec<CheckResult> {
+        checks.iter().map(|c| match c {
+            Check::WriteBeforeRead(id, w, r) => self.check_write_before_read(*id, w, r),
+            Check::NoCsReleaseBetween(cs, from, to) => self.check_no_cs_release_between(*cs, *from, *to),
+            Check::NoInterleave(a, b) => self.check_no_interleave(*a, *b),
+            Check::Alternating(id) => self.check_alternating(*id),
+            Check::MaxBytesPerWindow(cs, max) => self.check_max_bytes_per_window(*cs, *max),
+            Check::WpDeassertedDuringWrites(wp, id) => self.check_wp_deasserted_during_writes(*wp, *id),
+            Check::CsSetupHold(cs, id, setup, hold) => self.check_cs_setup_hold(*cs, *id, *setup, *hold),
+            Check::CommandThenPoll(id, cmd, status) => self.check_command_then_poll(*id, cmd, status),
+            Check::BusyPolls(id, n) => self.check_busy_polls(*id, *n),
+            Check::Retried(id, at_least) => self.check_retried(*id, *at_least),
+            Check::TotalRead(id, n) => self.check_total_read(*id, *n),
+            Check::MinDelayBetween(kind, min_ms) => self.check_min_delay_between(*kind, *min_ms),
+        }).collect()
+    }
+
+    /// Finalise expectations
+    /// This will cause previous expectations to be evaluated
+    pub fn finalise(&self) {
+        let mut i = lock(&self.inner);
+        i.finalised = true;
+        i.finalise();
+    }
+
+    /// Finalise expectations, ignoring the order of writes within chip select windows
+    /// 
+    /// Within each window (`SetLow` to `SetHigh` of the provided chip select), the set of write
+    /// transactions is compared rather than their exact order, while other transactions must
+    /// still match in order. Transactions outside windows are compared as normal.
+    pub fn finalise_unordered_within_window(&self, cs_id: Id) {
+        let mut i = lock(&self.inner);
+        i.finalised = true;
+        i.finalise_unordered_within_window(cs_id);
+    }
+
+    /// Finalise expectations, ignoring any delays
+    /// This strips delays from both the expected and actual transactions prior to comparison,
+    /// for use where the bus / pin protocol matters but the timing is checked elsewhere,
+    /// and is equivalent to `finalise_with` with `MatchOptions::ignore_delays` set
+    pub fn finalise_ignoring_delays(&self) {
+        let mut i = lock(&self.inner);
+        i.finalised = true;
+        i.finalise_with(MatchOptions{ ignore_delays: true, ..MatchOptions::default() });
+    }
+
+    /// Finalise expectations with the provided match options
+    /// 
+    /// Options may be combined to relax the comparison, with `MatchOptions::default()` comparing
+    /// exactly as `finalise` does. Mock-level comparison settings (such as `allow_trailing_delays`
+    /// and `finalise_concat_prefix`), repeats and wildcard expectations are applied as for `finalise`.
+    pub fn finalise_with(&self, opts: MatchOptions) {
+        let mut i = lock(&self.inner);
+        i.finalised = true;
+        i.finalise_with(opts);
+    }
+}
+
+impl <W: Word> Drop for Mock<W> {
+    /// Finalise the mock if expectations are set and this has not already occurred
+    fn drop(&mut self) {
+        if std::thread::panicking() {
+            return;
+        }
+
+        let mut i = lock(&self.inner);
+        if !i.finalised && !i.skip_finalise && !i.expected.is_empty() {
+            i.finalised = true;
+            i.finalise();
+        }
+    }
+}
+
+impl Transactional for Spi<u8> {
+    type Error = Error<MockError, MockError>;
+
+    /// Read data from a specified address
+    /// This consumes the provided input data array and returns a reference to this on success
+    fn spi_read(&mut self, prefix: &[u8], data: &mut [u8]) -> Result<(), Self::Error> {
+        let mut i = lock(&self.inner);
+
+        i.select(self.id, TxnKind::SpiRead);
+        if let Some(e) = i.inject(self.id, TxnKind::SpiRead, || MockTransaction::SpiRead(self.id, prefix.to_vec(), vec![])) {
+            return Err(e);
+        }
+
+        let index = i.index;
+
+        // Split dummy words (additional prefix or leading data) for expected dummy reads
+        if let Some(MockTransaction::SpiReadDummy(id, p, _dummy, incoming)) = i.expected.get(index) {
+            if *id == self.id && prefix.starts_with(p) && data.len() >= incoming.len() {
+                let skip = data.len() - incoming.len();
+                let dummy = prefix.len() - p.len() + skip;
+                let p = p.clone();
+                data[skip..].copy_from_slice(incoming);
+
+                i.record(MockTransaction::SpiReadDummy(self.id, p, dummy, data[skip..].into()));
+
+                return Ok(());
+            }
+        }
+
+        // Copy read data from expectation
+        match &i.expected.get(index) {
+            Some(MockTransaction::SpiRead(id, _outgoing, incoming)) if *id == self.id && incoming.len() != data.len() => {
+                panic!("{}expectation mismatch at index {}: spi_read expected {} words but driver provided a {}-word buffer",
+                    i.tag(), index, incoming.len(), data.len());
+            },
+            Some(MockTransaction::SpiRead(_id, _outgoing, incoming)) if incoming.len() == data.len() => {
+                data.copy_from_slice(&incoming);
+
+                // Data returned by the device initialises the register
+                let address = i.register_of(prefix);
+                i.known_register(self.id, &address);
+            },
+            _ if i.fill_reset_register(prefix, data) => (),
+            _ => if !i.read_hook(prefix, data) {
+                i.check_register(self.id, prefix);
+                i.fill_read(data);
+            },
+        };
+
+        // Save actual call
+        i.record(MockTransaction::SpiRead(self.id, prefix.into(), data.into()));
+
+        Ok(())
+    }
+
+    /// Write data to a specified register address
+    fn spi_write(&mut self, prefix: &[u8], data: &[u8]) -> Result<(), Self::Error> {
+        let mut i = lock(&self.inner);
+
+        i.select(self.id, TxnKind::SpiWrite);
+        if let Some(e) = i.inject(self.id, TxnKind::SpiWrite, || MockTransaction::SpiWrite(self.id, prefix.to_vec(), data.to_vec())) {
+            return Err(e);
+        }
+
+        let address = i.register_of(prefix);
+        i.known_register(self.id, &address);
+
+        // Save actual call
+        i.record(MockTransaction::SpiWrite(self.id, prefix.into(), data.into()));
+
+        Ok(())
+    }
+
+    /// Execute the provided transactions
+    fn spi_exec(&mut self, transactions: &mut [Transaction]) -> Result<(), Self::Error> {
+        let mut i = lock(&self.inner);
+
+        i.select(self.id, TxnKind::SpiExec);
+        if let Some(e) = i.inject(self.id, TxnKind::SpiExec, || MockTransaction::SpiExec(self.id, transactions.iter().map(|t| match t {
+            Transaction::Read(_) => MockExec::SpiRead(vec![]),
+            t => MockExec::from(t),
+        }).collect())) {
+            return Err(e);
+        }
+
+        let index = i.index;
+
+        // Load expected reads
+        let e = match i.expected.get(index) {
+            Some(MockTransaction::SpiExec(id, e)) if *id == self.id => e.clone(),
+            _ => vec![],
+        };
+
+        for (n, t) in transactions.iter_mut().enumerate() {
+            match (t, e.get(n)) {
+                (Transaction::Read(ref v), Some(MockExec::SpiRead(d))) if v.len() != d.len() => {
+                    panic!("{}expectation mismatch at index {}: spi_exec read {} expected {} words but driver provided a {}-word buffer",
+                        i.tag(), index, n, d.len(), v.len());
+                },
+                (Transaction::Read(ref mut v), Some(MockExec::SpiRead(d))) => v.copy_from_slice(&d),
+                (Transaction::Read(ref mut v), _) => i.fill_read(v),
+                _ => ()
+            }
+        }
+
+        // Capture actual calls (including read data)
+        let t: Vec<MockExec> = transactions.iter().map(|ref v| MockExec::from(*v) ).collect();
+        
+        // Save actual calls
+        i.record(MockTransaction::SpiExec(self.id, t));
+
+        Ok(())
+    }
+
+    /// Begin a framed transaction
+    fn begin(&mut self) -> Result<(), Self::Error> {
+        let mut i = lock(&self.inner);
+
+        i.select(self.id, TxnKind::BeginTransaction);
+        if let Some(e) = i.inject(self.id, TxnKind::BeginTransaction, || MockTransaction::BeginTransaction(self.id)) {
+            return Err(e);
+        }
+
+        // Save actual call
+        i.record(MockTransaction::BeginTransaction(self.id));
+
+        Ok(())
+    }
+
+    /// End a framed transaction
+    fn end(&mut self) -> Result<(), Self::Error> {
+        let mut i = lock(&self.inner);
+
+        i.select(self.id, TxnKind::EndTransaction);
+        if let Some(e) = i.inject(self.id, TxnKind::EndTransaction, || MockTransaction::EndTransaction(self.id)) {
+            return Err(e);
+        }
+
+        // Save actual call
+        i.record(MockTransaction::EndTransaction(self.id));
+
+        Ok(())
+    }
+}
+
+impl WriteStatus for Spi<u8> {
+    /// Write data to a specified register address, returning status from the expectation
+    fn spi_write_status(&mut self, prefix: &[u8], data: &[u8], status: &mut [u8]) -> Result<(), Self::Error> {
+        let mut i = lock(&self.inner);
+
+        i.select(self.id, TxnKind::SpiWriteStatus);
+        if let Some(e) = i.inject(self.id, TxnKind::SpiWriteStatus, || MockTransaction::SpiWriteStatus(self.id, prefix.to_vec(), data.to_vec(), vec![])) {
+            return Err(e);
+        }
+
+        let index = i.index;
+
+        // Copy status from expectation
+        match &i.expected.get(index) {
+            Some(MockTransaction::SpiWriteStatus(id, _prefix, _outgoing, incoming)) if *id == self.id && incoming.len() == status.len() => {
+                status.copy_from_slice(incoming);
+            },
+            _ => (),
+        };
+
+        // Save actual call
+        i.record(MockTransaction::SpiWriteStatus(self.id, prefix.into(), data.into(), status.into()));
+
+        Ok(())
+    }
+}
+
+impl <W: Word> Busy for Spi<W> {
+    type Error = Error<MockError, MockError>;
+    /// Check peripheral busy status
+    fn get_busy(&mut self) -> Result<PinState, Self::Error> {
+        let mut i = lock(&self.inner);
+
+        i.select(self.id, TxnKind::Busy);
+        if let Some(e) = i.inject(self.id, TxnKind::Busy, || MockTransaction::Busy(self.id, PinState::Low)) {
+            return Err(e);
+        }
+
+        // Report busy indefinitely, counting polls
+        if let Some(n) = i.busy_forever.get_mut(&self.id) {
+            *n += 1;
+            return Ok(PinState::High);
+        }
+
+        i.check_expected(self.id, TxnKind::Busy, "get_busy");
+
+        let index = i.index;
+
+        i.check_poll("busy");
+
+        let state = match &i.expected.get(index) {
+            Some(MockTransaction::Busy(_id, state)) => state.clone(),
+            _ => PinState::Low,
+        };
+
+        i.record(MockTransaction::Busy(self.id, state.clone()));
+
+        Ok(state)
+    }
+}
+
+impl <W: Word> Ready for Spi<W> {
+    type Error = Error<MockError, MockError>;
+    /// Check peripheral ready status
+    fn get_ready(&mut self) -> Result<PinState, Self::Error> {
+        let mut i = lock(&self.inner);
+
+        i.select(self.id, TxnKind::Ready);
+        if let Some(e) = i.inject(self.id, TxnKind::Ready, || MockTransaction::Ready(self.id, PinState::Low)) {
+            return Err(e);
+        }
+
+        i.check_expected(self.id, TxnKind::Ready, "get_ready");
+
+        let index = i.index;
+
+        i.check_poll("ready");
+
+        let state = match &i.expected.get(index) {
+            Some(MockTransaction::Ready(_id, state)) => state.clone(),
+            _ => PinState::Low,
+        };
+
+        i.record(MockTransaction::Ready(self.id, state.clone()));
+
+        Ok(state)
+    }
+}
+
+impl <W: Word> Reset for Spi<W> {
+    type Error = Error<MockError, MockError>;
+    /// Check peripheral ready status
+    fn set_reset(&mut self, state: PinState) -> Result<(), Self::Error> {
+        let mut i = lock(&self.inner);
+
+        i.select(self.id, TxnKind::Reset);
+        if let Some(e) = i.inject(self.id, TxnKind::Reset, || MockTransaction::Reset(self.id, state.clone())) {
+            return Err(e);
+        }
+
+        i.record(MockTransaction::Reset(self.id, state));
+
+        Ok(())
+    }
+}
+
+impl <W: Word> MultiLane for Spi<W> {
+    type Error = Error<MockError, MockError>;
+
+    /// Set the number of data lanes
+    fn set_lanes(&mut self, lanes: Lanes) -> Result<(), Self::Error> {
+        let mut i = lock(&self.inner);
+
+        i.select(self.id, TxnKind::SetLanes);
+        if let Some(e) = i.inject(self.id, TxnKind::SetLanes, || MockTransaction::SetLanes(self.id, lanes)) {
+            return Err(e);
+        }
+
+        i.record(MockTransaction::SetLanes(self.id, lanes));
+
+        Ok(())
+    }
+}
+
+impl <W: Word> DelayMs<u32> for Spi<W> {
+    fn delay_ms(&mut self, t: u32) {
+        let mut i = lock(&self.inner);
+
+        // Save actual call
+        i.record(MockTransaction::DelayMs(t));
+    }
+}
+
+impl <W: Word> DelayUs<u32> for Spi<W> {
+    fn delay_us(&mut self, t: u32) {
+        let mut i = lock(&self.inner);
+
+        // Save actual call
+        i.record(MockTransaction::DelayUs(t));
+    }
+}
+
+
+impl <W: Word> Spi<W> {
+    /// Mock spi::Transfer implementation, generic over word types
+    fn transfer_words(&mut self, data: &mut [W]) -> Result<(), Error<MockError, MockError>> {
+        let mut i = lock(&self.inner);
+
+        i.select(self.id, TxnKind::Transfer);
+        if let Some(e) = i.inject(self.id, TxnKind::Transfer, || MockTransaction::Transfer(self.id, data.to_vec(), vec![])) {
+            return Err(e);
+        }
+
+        let index = i.index;
+
+        let incoming: Vec<_> = data.into();
+
+        // Copy partial read data and interrupt the transfer
+        match i.expected.get(index).cloned() {
+            Some(MockTransaction::TransferAbort(id, _outgoing, partial, e)) if id == self.id => {
+                let n = partial.len().min(data.len());
+                data[..n].copy_from_slice(&partial[..n]);
+
+                i.record(MockTransaction::TransferAbort(self.id, incoming, partial[..n].to_vec(), e.clone()));
+
+                return Err(Error::Spi(e));
+            },
+            _ => (),
+        }
+
+        // Transfers are full-duplex, so expectations must clock the same number of words each way
+        if let Some(MockTransaction::Transfer(id, outgoing, incoming)) = i.expected.get(index) {
+            if *id == self.id && outgoing.len() != incoming.len() {
+                panic!("{}transfer expectation at index {} has {} outgoing but {} incoming words", i.tag(), index, outgoing.len(), incoming.len());
+            }
+        }
+
+        // Copy read data from expectation
+        match &i.expected.get(index) {
+            Some(MockTransaction::Transfer(id, _outgoing, incoming)) if *id == self.id && incoming.len() == data.len() => {
+                data.copy_from_slice(incoming);
+            },
+            Some(MockTransaction::TransferTx(id, _outgoing)) if *id == self.id => {
+                // Only outgoing data is compared, so record without read-back
+                i.record(MockTransaction::TransferTx(self.id, incoming));
+                return Ok(())
+            },
+            _ => if !i.read_hook(&incoming, data) {
+                i.fill_words(data);
+            },
+        };
+                       
+        // Save actual call
+        i.record(MockTransaction::Transfer(self.id, incoming, data.into()));
+
+        Ok(())
+    }
+
+    /// Write the outgoing data then read into the incoming buffer, within a single transaction
+    /// embedded-hal 0.2 provides no `WriteRead` trait for this pattern, so it is implemented
+    /// directly on the mock peripheral for drivers that write a command then read the response.
+    pub fn write_read(&mut self, outgoing: &[W], incoming: &mut [W]) -> Result<(), Error<MockError, MockError>> {
+        let mut i = lock(&self.inner);
+
+        i.select(self.id, TxnKind::WriteRead);
+        if let Some(e) = i.inject(self.id, TxnKind::WriteRead, || MockTransaction::WriteRead(self.id, outgoing.to_vec(), vec![])) {
+            return Err(e);
+        }
+
+        let index = i.index;
+
+        // Copy read data from expectation
+        match &i.expected.get(index) {
+            Some(MockTransaction::WriteRead(id, _outgoing, e)) if *id == self.id && e.len() != incoming.len() => {
+                panic!("{}expectation mismatch at index {}: write_read expected {} words but driver provided a {}-word buffer",
+                    i.tag(), index, e.len(), incoming.len());
+            },
+            Some(MockTransaction::WriteRead(_id, _outgoing, e)) => incoming.copy_from_slice(e),
+            _ => if !i.read_hook(outgoing, incoming) {
+                i.fill_words(incoming);
+            },
+        };
+
+        // Save actual call
+        i.record(MockTransaction::WriteRead(self.id, outgoing.into(), incoming.into()));
+
+        Ok(())
+    }
+
+    /// Mock spi::Write implementation, generic over word types
+    fn write_words(&mut self, data: &[W]) -> Result<(), Error<MockError, MockError>> {
+        let mut i = lock(&self.inner);
+
+        i.select(self.id, TxnKind::Write);
+        if let Some(e) = i.inject(self.id, TxnKind::Write, || MockTransaction::Write(self.id, data.to_vec())) {
+            return Err(e);
+        }
+
+        
+        // Save actual call
+        i.record(MockTransaction::Write(self.id, data.into()));
+
+        Ok(())
+    }
+}
+
+// embedded-hal provides blanket `spi::Transfer` and `spi::Write` implementations, so these
+// must be implemented for concrete word types rather than generically over `Word`
+macro_rules! impl_spi_words {
+    ($($w:ty),*) => {$(
+        impl spi::Transfer<$w> for Spi<$w> {
+            type Error = Error<MockError, MockError>;
+
+            fn transfer<'w>(&mut self, data: &'w mut [$w]) -> Result<&'w [$w], Self::Error> {
+                self.transfer_words(data)?;
+                Ok(data)
+            }
+        }
+
+        impl spi::Write<$w> for Spi<$w> {
+            type Error = Error<MockError, MockError>;
+
+            fn write(&mut self, data: &[$w]) -> Result<(), Self::Error> {
+                self.write_words(data)
+            }
+        }
+    )*}
+}
+
+impl_spi_words!(u8, u16, u32);
+
+impl <W: Word> v2::InputPin for Pin<W> {
+    type Error = MockError;
+
+    fn is_high(&self) -> Result<bool, Self::Error> {
+        let mut i = lock(&self.inner);
+
+        i.select(self.id, TxnKind::IsHigh);
+        if let Some(e) = i.inject(self.id, TxnKind::IsHigh, || MockTransaction::IsHigh(self.id, false)) {
+            return Err(i.pin_error(e));
+        }
+
+        if let Some(v) = i.interrupt_read(self.id, true) {
+            return Ok(v);
+        }
+
+        i.check_expected(self.id, TxnKind::IsHigh, "is_high");
+
+        let index = i.index;
+
+        // Fetch expectation if found
+        let v = match &i.expected.get(index) {
+            Some(MockTransaction::IsHigh(_id, v)) => *v,
+            _ => false,
+        };
+
+        // Save actual call
+        i.record(MockTransaction::IsHigh(self.id, v));
+
+        Ok(v)
+    }
+
+    fn is_low(&self) -> Result<bool, Self::Error> {
+        let mut i = lock(&self.inner);
+
+        i.select(self.id, TxnKind::IsLow);
+        if let Some(e) = i.inject(self.id, TxnKind::IsLow, || MockTransaction::IsLow(self.id, false)) {
+            return Err(i.pin_error(e));
+        }
+
+        if let Some(v) = i.interrupt_read(self.id, false) {
+            return Ok(v);
+        }
+
+        i.check_expected(self.id, TxnKind::IsLow, "is_low");
+
+        let index = i.index;
+
+        // Fetch expectation if found
+        let v = match &i.expected.get(index) {
+            Some(MockTransaction::IsLow(_id, v)) => *v,
+            _ => false,
+        };
+
+        // Save actual call
+        i.record(MockTransaction::IsLow(self.id, v));
+
+        Ok(v)
+    }
+}
+
+
+impl <W: Word> v2::OutputPin for Pin<W> {
+    type Error = MockError;
+
+    fn set_high(&mut self) -> Result<(), Self::Error> {
+        let mut i = lock(&self.inner);
+
+        i.select(self.id, TxnKind::SetHigh);
+        if let Some(e) = i.inject(self.id, TxnKind::SetHigh, || MockTransaction::SetHigh(self.id)) {
+            return Err(i.pin_error(e));
+        }
+
+        // Save actual call
+        i.record(MockTransaction::SetHigh(self.id));
+
+        Ok(())
+    }
+
+    fn set_low(&mut self) -> Result<(), Self::Error> {
+        let mut i = lock(&self.inner);
+
+        i.select(self.id, TxnKind::SetLow);
+        if let Some(e) = i.inject(self.id, TxnKind::SetLow, || MockTransaction::SetLow(self.id)) {
+            return Err(i.pin_error(e));
+        }
+
+        // Save actual call
+        i.record(MockTransaction::SetLow(self.id));
+
+        Ok(())
+    }
+}
+
+/// Toggling records whichever of `SetHigh` / `SetLow` flips the last driven state,
+/// with pins not yet driven assumed to be low
+impl <W: Word> v2::ToggleableOutputPin for Pin<W> {
+    type Error = MockError;
+
+    fn toggle(&mut self) -> Result<(), Self::Error> {
+        let mut i = lock(&self.inner);
+
+        let state = i.pin_states.get(&self.id).cloned().unwrap_or(PinState::Low).toggle();
+
+        let call = match state {
+            PinState::High => MockTransaction::SetHigh(self.id),
+            PinState::Low => MockTransaction::SetLow(self.id),
+        };
+
+        i.select(self.id, call.kind());
+        if let Some(e) = i.inject(self.id, call.kind(), || call.clone()) {
+            return Err(i.pin_error(e));
+        }
+
+        // Save actual call
+        i.record(call);
+
+        Ok(())
+    }
+}
+
+impl <W: Word> DelayMs<u32> for Delay<W> {
+    fn delay_ms(&mut self, t: u32) {
+        let mut i = lock(&self.inner);
+
+        // Save actual call
+        i.record(MockTransaction::DelayMs(t));
+    }
+}
+
+impl <W: Word> DelayUs<u32> for Delay<W> {
+    fn delay_us(&mut self, t: u32) {
+        let mut i = lock(&self.inner);
+
+        // Save actual call
+        i.record(MockTransaction::DelayUs(t));
+    }
+}
+
+/// Record an async delay of `ns` nanoseconds, rounded up to whole microseconds
+#[cfg(feature = "async")]
+fn delay_ns<W: Word>(inner: &Mutex<Inner<W>>, ns: u32) {
+    lock(inner).record(MockTransaction::DelayUs(ns.div_ceil(1000)));
+}
+
+/// Async transactions resolve immediately, sharing the blocking expectation handling
+/// The mock is locked only within each call, so is never held across await points
+#[cfg(feature = "async")]
+impl crate::AsyncTransactional for Spi<u8> {
+    type Error = Error<MockError, MockError>;
+
+    async fn spi_read(&mut self, prefix: &[u8], data: &mut [u8]) -> Result<(), Self::Error> {
+        Transactional::spi_read(self, prefix, data)
+    }
+
+    async fn spi_write(&mut self, prefix: &[u8], data: &[u8]) -> Result<(), Self::Error> {
+        Transactional::spi_write(self, prefix, data)
+    }
+
+    async fn spi_exec(&mut self, transactions: &mut [Transaction<'_>]) -> Result<(), Self::Error> {
+        Transactional::spi_exec(self, transactions)
+    }
+}
+
+/// Async delays resolve immediately, recording the requested durations
+#[cfg(feature = "async")]
+impl <W: Word> embedded_hal_async::delay::DelayNs for Spi<W> {
+    async fn delay_ns(&mut self, ns: u32) {
+        delay_ns(&self.inner, ns);
+    }
+
+    async fn delay_us(&mut self, us: u32) {
+        lock(&self.inner).record(MockTransaction::DelayUs(us));
+    }
+
+    async fn delay_ms(&mut self, ms: u32) {
+        lock(&self.inner).record(MockTransaction::DelayMs(ms));
+    }
+}
+
+/// Async delays resolve immediately, recording the requested durations
+#[cfg(feature = "async")]
+impl <W: Word> embedded_hal_async::delay::DelayNs for Delay<W> {
+    async fn delay_ns(&mut self, ns: u32) {
+        delay_ns(&self.inner, ns);
+    }
+
+    async fn delay_us(&mut self, us: u32) {
+        lock(&self.inner).record(MockTransaction::DelayUs(us));
+    }
+
+    async fn delay_ms(&mut self, ms: u32) {
+        lock(&self.inner).record(MockTransaction::DelayMs(ms));
+    }
+}
+
+#[cfg(feature = "hal1")]
+impl embedded_hal_1::spi::Error for Error<MockError, MockError> {
+    fn kind(&self) -> embedded_hal_1::spi::ErrorKind {
+        embedded_hal_1::spi::ErrorKind::Other
+    }
+}
+
+#[cfg(feature = "hal1")]
+impl <W: Word> embedded_hal_1::spi::ErrorType for Spi<W> {
+    type Error = Error<MockError, MockError>;
+}
+
+#[cfg(feature = "hal1")]
+impl <W: Word> Spi<W> {
+    /// Execute embedded-hal 1.0 operations, recording them as a `SpiDevice` transaction
+    fn device_ops(&mut self, operations: &mut [embedded_hal_1::spi::Operation<'_, W>]) -> Result<(), Error<MockError, MockError>> {
+        use embedded_hal_1::spi::Operation;
+
+        let mut i = lock(&self.inner);
+
+        i.select(self.id, TxnKind::SpiDevice);
+        if let Some(e) = i.inject(self.id, TxnKind::SpiDevice, || MockTransaction::SpiDevice(self.id, operations.iter().map(|o| match o {
+            Operation::Read(_) => MockOp::Read(vec![]),
+            Operation::Write(d) => MockOp::Write(d.to_vec()),
+            Operation::Transfer(_, w) => MockOp::Transfer(w.to_vec(), vec![]),
+            Operation::TransferInPlace(d) => MockOp::TransferInPlace(d.to_vec(), vec![]),
+            Operation::DelayNs(ns) => MockOp::DelayNs(*ns),
+        }).collect())) {
+            return Err(e);
+        }
+
+        // Load expected operations
+        let e = match i.expected.get(i.index) {
+            Some(MockTransaction::SpiDevice(id, e)) if *id == self.id => e.clone(),
+            _ => vec![],
+        };
+
+        let mut ops = Vec::with_capacity(operations.len());
+
+        for (n, o) in operations.iter_mut().enumerate() {
+            let op = match (o, e.get(n)) {
+                (Operation::Read(d), Some(MockOp::Read(v))) if d.len() == v.len() => {
+                    d.copy_from_slice(v);
+                    MockOp::Read(d.to_vec())
+                },
+                (Operation::Read(d), _) => {
+                    i.fill_words(d);
+                    MockOp::Read(d.to_vec())
+                },
+                (Operation::Write(d), _) => MockOp::Write(d.to_vec()),
+                (Operation::Transfer(r, w), Some(MockOp::Transfer(_, v))) if r.len() == v.len() => {
+                    r.copy_from_slice(v);
+                    MockOp::Transfer(w.to_vec(), r.to_vec())
+                },
+                (Operation::Transfer(r, w), _) => {
+                    i.fill_words(r);
+                    MockOp::Transfer(w.to_vec(), r.to_vec())
+                },
+                (Operation::TransferInPlace(d), expected) => {
+                    let outgoing = d.to_vec();
+                    match expected {
+                        Some(MockOp::TransferInPlace(_, v)) if d.len() == v.len() => d.copy_from_slice(v),
+                        _ => { i.fill_words(d); },
+                    }
+                    MockOp::TransferInPlace(outgoing, d.to_vec())
+                },
+                (Operation::DelayNs(ns), _) => MockOp::DelayNs(*ns),
+            };
+            ops.push(op);
+        }
+
+        i.record(MockTransaction::SpiDevice(self.id, ops));
+
+        Ok(())
+    }
+}
+
+/// embedded-hal 1.0 `SpiDevice`, recording each transaction as `MockTransaction::SpiDevice`
+#[cfg(feature = "hal1")]
+impl <W: Word> embedded_hal_1::spi::SpiDevice<W> for Spi<W> {
+    fn transaction(&mut self, operations: &mut [embedded_hal_1::spi::Operation<'_, W>]) -> Result<(), Self::Error> {
+        self.device_ops(operations)
+    }
+}
+
+/// embedded-hal 1.0 `SpiBus`, recording each call as a single operation `MockTransaction::SpiDevice`
+#[cfg(feature = "hal1")]
+impl <W: Word> embedded_hal_1::spi::SpiBus<W> for Spi<W> {
+    fn read(&mut self, words: &mut [W]) -> Result<(), Self::Error> {
+        self.device_ops(&mut [embedded_hal_1::spi::Operation::Read(words)])
+    }
+
+    fn write(&mut self, words: &[W]) -> Result<(), Self::Error> {
+        self.device_ops(&mut [embedded_hal_1::spi::Operation::Write(words)])
+    }
+
+    fn transfer(&mut self, read: &mut [W], write: &[W]) -> Result<(), Self::Error> {
+        self.device_ops(&mut [embedded_hal_1::spi::Operation::Transfer(read, write)])
+    }
+
+    fn transfer_in_place(&mut self, words: &mut [W]) -> Result<(), Self::Error> {
+        self.device_ops(&mut [embedded_hal_1::spi::Operation::TransferInPlace(words)])
+    }
+
+    fn flush(&mut self) -> Result<(), Self::Error> {
         Ok(())
     }
+}
+
+#[cfg(test)]
+mod test {
+    use std::*;
+    use std::{vec, panic};
+
+    use super::*;
+
+    #[test]
+    fn test_transactional_read() {
+        let mut m = Mock::new();
+        let mut s = m.spi();
+
+        let prefix = vec![0xFF];
+        let data = vec![0xAA, 0xBB];
+
+        m.expect(vec![MockTransaction::spi_read(&s, prefix.clone(), data.clone())]);
+
+        let mut d = [0u8; 2];
+        s.spi_read(&prefix, &mut d).expect("read failure");
+
+        m.finalise();
+        assert_eq!(&data, &d);
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_transactional_read_expect_write() {
+        let mut m = Mock::new();
+        let mut s = m.spi();
+
+        let prefix = vec![0xFF];
+        let data = vec![0xAA, 0xBB];
+
+        m.expect(vec![MockTransaction::spi_write(&s, prefix.clone(), data.clone())]);
+
+        let mut d = [0u8; 2];
+        s.spi_read(&prefix, &mut d).expect("read failure");
+
+        m.finalise();
+        assert_eq!(&data, &d);
+    }
+
+    #[test]
+    fn test_transactional_write() {
+        let mut m = Mock::new();
+        let mut s = m.spi();
+
+        let prefix = vec![0xFF];
+        let data = vec![0xAA, 0xBB];
+
+        m.expect(vec![MockTransaction::spi_write(&s, prefix.clone(), data.clone())]);
+
+        s.spi_write(&prefix, &data).expect("write failure");
+
+        m.finalise();
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_transactional_write_expect_read() {
+        let mut m = Mock::new();
+        let mut s = m.spi();
+
+        let prefix = vec![0xFF];
+        let data = vec![0xAA, 0xBB];
+
+        m.expect(vec![MockTransaction::spi_read(&s, prefix.clone(), data.clone())]);
+
+        s.spi_write(&prefix, &data).expect("write failure");
+
+        m.finalise();
+    }
+
+    #[test]
+    fn test_transfer_tx_only() {
+        use embedded_hal::blocking::spi::Transfer;
+
+        let mut m = Mock::new();
+        let mut s = m.spi();
+
+        m.expect(vec![
+            MockTransaction::transfer_tx_only(&s, [0xAA, 0xBB]),
+        ]);
+
+        let mut d = [0xAA, 0xBB];
+        let _ = s.transfer(&mut d).expect("transfer failure");
+
+        m.finalise();
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_transfer_tx_only_mismatch() {
+        use embedded_hal::blocking::spi::Transfer;
+
+        let mut m = Mock::new();
+        let mut s = m.spi();
+
+        m.expect(vec![
+            MockTransaction::transfer_tx_only(&s, [0xAA, 0xBB]),
+        ]);
+
+        s.transfer(&mut [0xAA, 0xCC]).expect("transfer failure");
+
+        m.finalise();
+    }
+
+    #[test]
+    fn test_transfer_abort() {
+        use embedded_hal::blocking::spi::Transfer;
+
+        let mut m = Mock::new();
+        let mut s = m.spi();
+
+        m.expect(vec![
+            MockTransaction::transfer_abort(&s, [0x01, 0x02, 0x03, 0x04], [0x11, 0x22], MockError::Bus),
+        ]);
+
+        let mut d = [0x01, 0x02, 0x03, 0x04];
+        assert_eq!(s.transfer(&mut d), Err(Error::Spi(MockError::Bus)));
+        assert_eq!(d, [0x11, 0x22, 0x03, 0x04]);
+
+        m.assert_total_read(s.id, 2);
+        m.finalise();
+    }
+
+    #[test]
+    fn test_transfer_abort_other_peripheral() {
+        use embedded_hal::blocking::spi::Transfer;
+
+        let mut m = Mock::new();
+        let mut s1 = m.spi();
+        let s2 = m.spi();
+        m.skip_finalise();
+
+        m.expect(vec![
+            MockTransaction::transfer_abort(&s2, [0x01, 0x02], [0x11], MockError::Bus),
+        ]);
+
+        // Aborts are only applied to the matching peripheral
+        let mut d = [0x01, 0x02];
+        assert_eq!(s1.transfer(&mut d), Ok(&[0x01, 0x02][..]));
+    }
+
+    #[test]
+    fn test_op_histogram() {
+        use embedded_hal::digital::v2::OutputPin;
+
+        let mut m = Mock::new();
+        let mut s = m.spi();
+        let mut cs = m.pin();
+
+        let mut d = [0u8; 1];
+        cs.set_low().unwrap();
+        s.spi_write(&[0x01], &[0xAA]).unwrap();
+        s.spi_write(&[0x02], &[0xBB]).unwrap();
+        s.delay_ms(1);
+        s.spi_write(&[0x03], &[0xCC]).unwrap();
+        s.spi_read(&[0x04], &mut d).unwrap();
+        cs.set_high().unwrap();
+        m.mark("done");
+
+        let h = m.op_histogram();
+        assert_eq!(h.get("write"), Some(&3));
+        assert_eq!(h.get("read"), Some(&1));
+        assert_eq!(h.get("pin_set"), Some(&2));
+        assert_eq!(h.get("delay"), Some(&1));
+        assert_eq!(h.get("transfer"), Option::None);
+        assert_eq!(h.len(), 4);
+    }
+
+    #[test]
+    fn test_concat_prefix() {
+        let mut m = Mock::new();
+        let mut s = m.spi();
+
+        m.finalise_concat_prefix(true);
+        m.expect(vec![
+            MockTransaction::spi_write(&s, [0x01], [0xAA, 0xBB]),
+            MockTransaction::spi_write(&s, [], [0x02, 0xCC]),
+        ]);
+
+        s.spi_write(&[0x01, 0xAA], &[0xBB]).unwrap();
+        s.spi_write(&[0x02], &[0xCC]).unwrap();
+
+        m.finalise();
+    }
+
+    #[test]
+    #[should_panic(expected = "expectation mismatch at index 1 (prefix and data concatenated)")]
+    fn test_concat_prefix_mismatch() {
+        let mut m = Mock::new();
+        let mut s = m.spi();
+
+        m.finalise_concat_prefix(true);
+        m.expect(vec![
+            MockTransaction::spi_write(&s, [0x01], [0xAA, 0xBB]),
+            MockTransaction::spi_write(&s, [0x02], [0xCC]),
+        ]);
+
+        s.spi_write(&[0x01, 0xAA], &[0xBB]).unwrap();
+        s.spi_write(&[0x02, 0xCC], &[0xCC]).unwrap();
+
+        m.finalise();
+    }
+
+    #[test]
+    fn test_last() {
+        use embedded_hal::digital::v2::OutputPin;
+
+        let mut m = Mock::new();
+        let mut s = m.spi();
+        let mut cs = m.pin();
+
+        assert_eq!(m.last(), Option::None);
+
+        cs.set_low().unwrap();
+        s.spi_write(&[0x10], &[0x01]).unwrap();
+        cs.set_high().unwrap();
+
+        assert_eq!(m.last(), Some(MockTransaction::set_high(&cs)));
+        assert_eq!(m.nth(1), Some(MockTransaction::spi_write(&s, [0x10], [0x01])));
+        assert_eq!(m.nth(3), Option::None);
+    }
+
+    #[test]
+    #[should_panic(expected = "no expectations set, but 2 transactions were recorded (first: SpiWrite(0, [1], [170]))")]
+    fn test_no_expectations() {
+        let mut m = Mock::new();
+        let mut s = m.spi();
+
+        // Runs permissively without expectations
+        let mut d = [0xFF; 2];
+        s.spi_write(&[0x01], &[0xAA]).unwrap();
+        s.spi_read(&[0x02], &mut d).unwrap();
+        assert_eq!(d, [0xFF; 2]);
+
+        m.finalise();
+    }
+
+    #[test]
+    fn test_no_expectations_empty() {
+        let m = Mock::new();
+        m.finalise();
+    }
+
+    #[test]
+    fn test_max_bytes_per_window() {
+        use embedded_hal::digital::v2::OutputPin;
+
+        let mut m = Mock::new();
+        let mut s = m.spi();
+        let mut cs = m.pin();
+
+        let mut d = [0u8; 2];
+        for _ in 0..2 {
+            cs.set_low().unwrap();
+            s.spi_write(&[0x01], &[0xAA]).unwrap();
+            s.spi_read(&[0x02], &mut d).unwrap();
+            cs.set_high().unwrap();
+        }
+
+        // Writes outside windows are not counted
+        s.spi_write(&[0x03], &[0xBB; 8]).unwrap();
+
+        m.assert_max_bytes_per_window(cs.id, 5);
+    }
+
+    #[test]
+    #[should_panic(expected = "6 words clocked in chip select 1 window from index 0 at index 2 (maximum 5)")]
+    fn test_max_bytes_per_window_exceeded() {
+        use embedded_hal::digital::v2::OutputPin;
+
+        let mut m = Mock::new();
+        let mut s = m.spi();
+        let mut cs = m.pin();
+
+        cs.set_low().unwrap();
+        s.spi_write(&[0x01], &[0xAA, 0xBB]).unwrap();
+        s.spi_write(&[0x02], &[0xCC, 0xDD]).unwrap();
+        cs.set_high().unwrap();
+
+        m.assert_max_bytes_per_window(cs.id, 5);
+    }
+
+    #[test]
+    fn test_interrupt_pin() {
+        use embedded_hal::digital::v2::InputPin;
+
+        let mut m = Mock::new();
+        let mut s = m.spi();
+        let drdy = m.pin();
+
+        m.mark_interrupt_pin(drdy.id);
+        m.expect(vec![
+            MockTransaction::spi_read(&s, [0x01], [0xAA]),
+        ]);
+
+        let mut d = [0u8; 1];
+        while !drdy.is_high().unwrap() {
+            m.set_interrupt_level(drdy.id, true);
+        }
+        assert!(!drdy.is_low().unwrap());
+        s.spi_read(&[0x01], &mut d).unwrap();
+
+        m.finalise();
+        assert_eq!(m.interrupt_reads(drdy.id), vec![
+            MockTransaction::is_high(&drdy, false),
+            MockTransaction::is_high(&drdy, true),
+            MockTransaction::is_low(&drdy, false),
+        ]);
+    }
+
+    #[test]
+    fn test_retried() {
+        let mut m = Mock::new();
+        let mut s = m.spi();
+
+        m.expect(vec![
+            MockTransaction::spi_write(&s, [0x01], [0xAA]),
+            MockTransaction::spi_read(&s, [0x05], [0x01]),
+            MockTransaction::delay_ms(1),
+            MockTransaction::spi_read(&s, [0x05], [0x01]),
+            MockTransaction::delay_ms(1),
+            MockTransaction::spi_read(&s, [0x05], [0x00]),
+        ]);
+
+        s.spi_write(&[0x01], &[0xAA]).unwrap();
+
+        // Poll status until not busy
+        let mut d = [0u8; 1];
+        loop {
+            s.spi_read(&[0x05], &mut d).unwrap();
+            if d[0] == 0 {
+                break;
+            }
+            s.delay_ms(1);
+        }
+
+        m.finalise();
+        m.assert_retried(s.id, 2);
+    }
+
+    #[test]
+    #[should_panic(expected = "only 1 retries on peripheral 0 (expected at least 2)")]
+    fn test_retried_too_few() {
+        let mut m = Mock::new();
+        let mut s = m.spi();
+
+        let mut d = [0u8; 1];
+        s.spi_read(&[0x04], &mut d).unwrap();
+        s.spi_read(&[0x05], &mut d).unwrap();
+        s.spi_read(&[0x05], &mut d).unwrap();
+
+        m.assert_retried(s.id, 2);
+    }
+
+    #[test]
+    fn test_extend_from() {
+        use embedded_hal::digital::v2::OutputPin;
+
+        // Reusable fragments, with handles created in the same order as the test mock
+        let init = || {
+            let mut m = Mock::new();
+            m.skip_finalise();
+            let (s, cs) = (m.spi(), m.pin());
+            m.expect(vec![
+                MockTransaction::set_low(&cs),
+                MockTransaction::spi_write(&s, [0x01], [0x00]),
+                MockTransaction::set_high(&cs),
+            ]);
+            m
+        };
+        let measure = || {
+            let mut m = Mock::new();
+            m.skip_finalise();
+            let s = m.spi();
+            m.expect(vec![
+                MockTransaction::spi_write(&s, [0x02], [0x01]),
+            ]);
+            m
+        };
+
+        let mut m = Mock::new();
+        let mut s = m.spi();
+        let mut cs = m.pin();
+
+        m.extend_from(&init());
+        m.extend_from(&measure());
+
+        cs.set_low().unwrap();
+        s.spi_write(&[0x01], &[0x00]).unwrap();
+        cs.set_high().unwrap();
+        s.spi_write(&[0x02], &[0x01]).unwrap();
+
+        m.finalise();
+    }
+
+    #[test]
+    fn test_register_model() {
+        let mut m = Mock::new();
+        m.skip_finalise();
+        let mut s = m.spi();
+
+        m.strict_register_model(true);
+        m.stub_register(s.id, &[0x30]);
+        m.expect(vec![
+            MockTransaction::spi_write(&s, [0x02, 0x10], [0x01]),
+            MockTransaction::spi_read(&s, [0x03, 0x20], [0xAA]),
+        ]);
+
+        let mut d = [0u8; 1];
+        s.spi_write(&[0x02, 0x10], &[0x01]).unwrap();
+        s.spi_read(&[0x03, 0x20], &mut d).unwrap();
+
+        // Written, read from the device and stubbed registers are known
+        s.spi_read(&[0x03, 0x10], &mut d).unwrap();
+        s.spi_read(&[0x03, 0x20], &mut d).unwrap();
+        s.spi_read(&[0x03, 0x30], &mut d).unwrap();
+
+        assert_eq!(m.last(), Some(MockTransaction::spi_read(&s, [0x03, 0x30], [0xAA])));
+    }
+
+    #[test]
+    #[should_panic(expected = "read of uninitialised register [11] on peripheral 0 at index 1")]
+    fn test_register_model_uninitialised() {
+        let mut m = Mock::new();
+        let mut s = m.spi();
+
+        m.strict_register_model(true);
+
+        let mut d = [0u8; 1];
+        s.spi_write(&[0x02, 0x10], &[0x01]).unwrap();
+        s.spi_read(&[0x03, 0x11], &mut d).unwrap();
+    }
+
+    #[test]
+    #[should_panic(expected = "read of uninitialised register [20] on peripheral 0 at index 1")]
+    fn test_register_model_single_word() {
+        let mut m = Mock::new();
+        let mut s = m.spi();
+
+        m.strict_register_model(true);
+
+        let mut d = [0u8; 1];
+        s.spi_write(&[0x10], &[0x01]).unwrap();
+        s.spi_read(&[0x20], &mut d).unwrap();
+    }
+
+    #[test]
+    fn test_register_address() {
+        let mut m = Mock::new();
+        m.skip_finalise();
+        let mut s = m.spi();
+
+        m.strict_register_model(true);
+        m.set_register_address(|p: &[u8]| p.iter().map(|b| b & 0x7F ).collect() );
+
+        // Reads set the top bit of the address
+        let mut d = [0u8; 1];
+        s.spi_write(&[0x10], &[0x01]).unwrap();
+        s.spi_read(&[0x90], &mut d).unwrap();
+    }
+
+    #[test]
+    fn test_word_endianness() {
+        let mut m = Mock::new();
+        let s = m.spi();
+
+        assert_eq!(MockTransaction::spi_write_u16_be(&s, [0x10], 0x0102), MockTransaction::spi_write(&s, [0x10], [0x01, 0x02]));
+        assert_eq!(MockTransaction::spi_read_u32_le(&s, [0x10], 0x01020304), MockTransaction::spi_read(&s, [0x10], [0x04, 0x03, 0x02, 0x01]));
+
+        assert_eq!(m.spi_write_u16(&s, [0x10], 0x0102), MockTransaction::spi_write(&s, [0x10], [0x01, 0x02]));
+
+        m.set_word_endianness(Endianness::Little);
+        assert_eq!(m.spi_write_u16(&s, [0x10], 0x0102), MockTransaction::spi_write(&s, [0x10], [0x02, 0x01]));
+        assert_eq!(m.spi_read_u32(&s, [0x10], 0x01020304), MockTransaction::spi_read(&s, [0x10], [0x04, 0x03, 0x02, 0x01]));
+    }
+
+    #[test]
+    fn test_busy_forever() {
+        let mut m = Mock::new();
+        let mut s = m.spi();
+
+        m.busy_forever(s.id);
+        m.expect(vec![
+            MockTransaction::spi_write(&s, [0x01], []),
+            MockTransaction::delay_ms(1),
+            MockTransaction::delay_ms(1),
+            MockTransaction::delay_ms(1),
+        ]);
+
+        // Bounded wait for the busy signal to clear
+        let wait = |s: &mut Spi| -> Result<(), UnitError> {
+            s.spi_write(&[0x01], &[])?;
+            for _ in 0..3 {
+                s.delay_ms(1);
+                if s.get_busy()? == PinState::Low {
+                    return Ok(())
+                }
+            }
+            Err(Error::Aborted)
+        };
+
+        assert_eq!(wait(&mut s), Err(Error::Aborted));
+
+        m.finalise();
+        m.assert_busy_polls(s.id, 3);
+    }
+
+    #[test]
+    fn test_finalise_with() {
+        let mut m = Mock::new();
+        let _p = m.pin();
+        let mut s = m.spi();
+
+        m.expect(vec![
+            MockTransaction::SpiWrite(0, vec![0x01], vec![0xAA, 0xBB]),
+            MockTransaction::SpiWrite(0, vec![0x02], vec![]),
+        ]);
+
+        s.spi_write(&[0x01, 0xAA], &[0xBB]).unwrap();
+        s.delay_ms(1);
+        s.spi_write(&[0x02], &[]).unwrap();
+
+        m.finalise_with(MatchOptions{ ignore_ids: true, ignore_delays: true, concat_prefix: true, data_wildcards: false });
+    }
+
+    #[test]
+    fn test_finalise_with_default() {
+        use embedded_hal::blocking::delay::DelayMs;
+
+        let mut m = Mock::new();
+        let mut s = m.spi();
+        let mut d = m.delay();
+
+        m.expect(vec![
+            MockTransaction::spi_write_any(&s, [0x80]),
+            MockTransaction::delay_ms_at_least(5),
+        ]);
+
+        s.spi_write(&[0x80], &[0x01, 0x02]).unwrap();
+        d.delay_ms(10);
+
+        m.finalise_with(MatchOptions::default());
+    }
+
+    #[test]
+    fn test_finalise_with_data_wildcards() {
+        use embedded_hal::blocking::spi::Write;
+
+        let mut m = Mock::new();
+        let mut s = m.spi();
+
+        m.expect(vec![
+            MockTransaction::spi_write(&s, [0x80], [0x00]),
+            MockTransaction::write(&s, [0x00]),
+        ]);
+
+        s.spi_write(&[0x80], &[0x01, 0x02]).unwrap();
+        s.write(&[0xAA]).unwrap();
+
+        m.finalise_with(MatchOptions{ data_wildcards: true, ..MatchOptions::default() });
+    }
+
+    #[test]
+    #[should_panic(expected = "expectation mismatch at index 0 (with MatchOptions { ignore_ids: false, ignore_delays: true, concat_prefix: false, data_wildcards: false })")]
+    fn test_finalise_with_mismatch() {
+        let mut m = Mock::new();
+        let _p = m.pin();
+        let mut s = m.spi();
+
+        m.expect(vec![
+            MockTransaction::SpiWrite(0, vec![0x01], vec![0xAA]),
+        ]);
+
+        s.delay_ms(1);
+        s.spi_write(&[0x01], &[0xAA]).unwrap();
+
+        m.finalise_with(MatchOptions{ ignore_delays: true, ..MatchOptions::default() });
+    }
+
+    #[test]
+    #[should_panic(expected = "operation cap exceeded (100 transactions) - possible infinite loop")]
+    fn test_operation_cap() {
+        let mut m = Mock::new();
+        let mut s = m.spi();
+
+        m.set_operation_cap(Some(100));
+
+        // Driver waiting on a status bit that never clears
+        let mut d = [0u8; 1];
+        loop {
+            s.spi_read(&[0x05], &mut d).unwrap();
+            if d[0] & 0x01 != 0 {
+                break;
+            }
+        }
+    }
+
+    #[test]
+    fn test_modify_register() {
+        let mut m = Mock::new();
+        let mut s = m.spi();
+
+        m.expect(vec![
+            MockTransaction::begin(&s),
+            MockTransaction::spi_read(&s, [0x20], [0b1010_0101]),
+            MockTransaction::spi_write(&s, [0x20], [0b1010_1100]),
+            MockTransaction::end(&s),
+        ]);
+
+        // Clear the low bits and set a mode field
+        s.modify_register(0x20, |v| (v & !0x0F) | 0x0C).unwrap();
+
+        m.finalise();
+    }
+
+    #[test]
+    fn test_wp_deasserted() {
+        use embedded_hal::digital::v2::OutputPin;
+
+        let mut m = Mock::new();
+        let mut s = m.spi();
+        let mut wp = m.pin();
+
+        let mut d = [0u8; 1];
+        s.spi_read(&[0x05], &mut d).unwrap();
+        wp.set_high().unwrap();
+        s.spi_write(&[0x02, 0x00], &[0xAA]).unwrap();
+        wp.set_low().unwrap();
+        s.spi_read(&[0x03, 0x00], &mut d).unwrap();
+
+        m.assert_wp_deasserted_during_writes(wp.id, s.id);
+    }
+
+    #[test]
+    #[should_panic(expected = "write on peripheral 0 at index 3 with write protect 1 asserted")]
+    fn test_wp_asserted() {
+        use embedded_hal::digital::v2::OutputPin;
+
+        let mut m = Mock::new();
+        let mut s = m.spi();
+        let mut wp = m.pin();
+
+        wp.set_high().unwrap();
+        s.spi_write(&[0x02, 0x00], &[0xAA]).unwrap();
+        wp.set_low().unwrap();
+        s.spi_write(&[0x02, 0x01], &[0xBB]).unwrap();
+
+        m.assert_wp_deasserted_during_writes(wp.id, s.id);
+    }
+
+    #[test]
+    fn test_sequenced_actual() {
+        let mut m = Mock::new();
+        let s = m.spi();
+
+        let threads: Vec<_> = (0..2u8).map(|n| {
+            let mut s = s.clone();
+            std::thread::spawn(move || {
+                for _ in 0..10 {
+                    s.spi_write(&[n], &[]).unwrap();
+                }
+            })
+        }).collect();
+        for t in threads {
+            t.join().unwrap();
+        }
+
+        let seq = m.sequenced_actual();
+        assert_eq!(seq.len(), 20);
+        assert!(seq.iter().enumerate().all(|(n, (i, _))| *i == n as u64 ));
+        for n in 0..2u8 {
+            assert_eq!(seq.iter().filter(|(_, t)| *t == MockTransaction::spi_write(&s, [n], []) ).count(), 10);
+        }
+
+        m.rewind();
+        s.clone().spi_write(&[0x02], &[]).unwrap();
+        assert_eq!(m.sequenced_actual().last(), Some(&(20, MockTransaction::spi_write(&s, [0x02], []))));
+    }
+
+    #[test]
+    fn test_command_then_poll() {
+        let mut m = Mock::new();
+        let mut s = m.spi();
+
+        s.spi_write(&[0x02, 0x00], &[0xAA]).unwrap();
+        s.spi_read(&[0x05], &mut [0u8; 1]).unwrap();
+        s.spi_read(&[0x05], &mut [0u8; 1]).unwrap();
+        s.spi_write(&[0x06], &[]).unwrap();
+        s.spi_write(&[0x02, 0x01], &[0xBB]).unwrap();
+        s.spi_read(&[0x05], &mut [0u8; 1]).unwrap();
+
+        m.assert_command_then_poll(s.id, &[0x02], &[0x05]);
+
+        s.spi_write(&[0x02, 0x02], &[0xCC]).unwrap();
+        assert_eq!(m.check_command_then_poll(s.id, &[0x02], &[0x05]).map_err(|f| f.index ), Err(6));
+
+        s.spi_write(&[0x06], &[]).unwrap();
+        assert_eq!(m.check_command_then_poll(s.id, &[0x02], &[0x05]).map_err(|f| f.index ), Err(6));
+
+        s.spi_read(&[0x05], &mut [0u8; 1]).unwrap();
+        assert_eq!(m.check_command_then_poll(s.id, &[0x02], &[0x05]), Ok(()));
+    }
+
+    #[test]
+    #[should_panic(expected = "write on peripheral 0 at index 1 before polling status for command at index 0")]
+    fn test_command_then_poll_missing() {
+        let mut m = Mock::new();
+        let mut s = m.spi();
+
+        s.spi_write(&[0x20, 0x00], &[]).unwrap();
+        s.spi_write(&[0x20, 0x01], &[]).unwrap();
+
+        m.assert_command_then_poll(s.id, &[0x20], &[0x05]);
+    }
+
+    #[test]
+    fn test_run_checks() {
+        use embedded_hal::digital::v2::OutputPin;
+
+        let mut m = Mock::new();
+        let mut s = m.spi();
+        let mut cs = m.pin();
+
+        m.label("ci");
+
+        cs.set_low().unwrap();
+        s.spi_write(&[0x01], &[0xAA, 0xBB]).unwrap();
+        s.spi_write(&[0x02], &[]).unwrap();
+        cs.set_high().unwrap();
+
+        let r = m.run_checks(&[
+            Check::NoInterleave(cs.id, 2),
+            Check::Alternating(s.id),
+            Check::MaxBytesPerWindow(cs.id, 3),
+        ]);
+
+        assert_eq!(r, vec![
+            Ok(()),
+            Err(CheckFailure{ code: "alternating", index: 2, message: "[ci] consecutive writes on peripheral 0 at index 2".to_string() }),
+            Err(CheckFailure{ code: "max_bytes_per_window", index: 2, message: "[ci] 4 words clocked in chip select 1 window from index 0 at index 2 (maximum 3)".to_string() }),
+        ]);
+        assert_eq!(m.check_alternating(cs.id), Ok(()));
+
+        let r = m.run_checks(&[
+            Check::BusyPolls(s.id, 0),
+            Check::Retried(s.id, 1),
+            Check::TotalRead(s.id, 0),
+            Check::MinDelayBetween(TxnKind::SpiWrite, 1),
+        ]);
+
+        assert_eq!(r, vec![
+            Ok(()),
+            Err(CheckFailure{ code: "retried", index: 4, message: "[ci] no final status read found for peripheral 0".to_string() }),
+            Ok(()),
+            Err(CheckFailure{ code: "min_delay_between", index: 2, message: "[ci] only 0 ms between SpiWrite transactions at index 1 and 2 (minimum 1 ms)".to_string() }),
+        ]);
+    }
+
+    #[test]
+    fn test_write_chunked() {
+        let mut m = Mock::new();
+        let mut s = m.spi();
+
+        let data: Vec<u8> = (0..300).map(|v| v as u8 ).collect();
+        let prefix = |offset: usize| [0x02, (offset >> 8) as u8, offset as u8];
+
+        m.expect(data.chunks(64).enumerate().map(|(n, c)| {
+            MockTransaction::spi_write(&s, prefix(n * 64), c)
+        }).collect::<Vec<_>>());
+
+        s.spi_write_chunked(prefix, &data, 64).expect("write failure");
+
+        m.finalise();
+        assert_eq!(m.per_peripheral()[&s.id].len(), 5);
+    }
+
+    #[test]
+    #[should_panic(expected = "spi_write_chunked chunk size must be non-zero")]
+    fn test_write_chunked_zero() {
+        let mut m = Mock::new();
+        let mut s = m.spi();
+
+        s.spi_write_chunked(|_| [0x02], &[0xAA], 0).unwrap();
+    }
+
+    #[test]
+    fn test_soft_reset() {
+        let mut m = Mock::new();
+        let mut s = m.spi();
+
+        m.expect(vec![
+            MockTransaction::spi_write(&s, [0x66], []),
+            MockTransaction::spi_write(&s, [0x99], []),
+        ]);
+
+        s.soft_reset(&[0x66]).expect("reset failure");
+        s.soft_reset(&[0x99]).expect("reset failure");
+
+        m.finalise();
+    }
+
+    #[test]
+    fn test_write_status_other_peripheral() {
+        let mut m = Mock::new();
+        let mut s1 = m.spi();
+        let s2 = m.spi();
+        m.skip_finalise();
+
+        m.expect(vec![
+            MockTransaction::spi_write_status(&s2, [0x02], [0xAA, 0xBB], [0x80]),
+        ]);
+
+        // Status is only returned to the matching peripheral
+        let mut status = [0u8; 1];
+        s1.spi_write_status(&[0x02], &[0xAA, 0xBB], &mut status).expect("write failure");
+        assert_eq!(status, [0x00]);
+    }
+
+    #[test]
+    fn test_transfer_other_peripheral() {
+        use embedded_hal::blocking::spi::Transfer;
+
+        let mut m = Mock::new();
+        let mut s1 = m.spi();
+        let s2 = m.spi();
+        m.skip_finalise();
+
+        m.expect(vec![
+            MockTransaction::transfer(&s2, [0xAA], [0x55]),
+            MockTransaction::transfer_tx_only(&s2, [0xBB]),
+        ]);
+
+        // Read-back is only returned to the matching peripheral
+        let mut d = [0xAA];
+        s1.transfer(&mut d).expect("transfer failure");
+        assert_eq!(d, [0xAA]);
+
+        // Transmit-only expectations for other peripherals record a full transfer
+        s1.transfer(&mut [0xBB]).expect("transfer failure");
+        assert_eq!(m.last(), Some(MockTransaction::Transfer(s1.id, vec![0xBB], vec![0xBB])));
+    }
+
+    #[test]
+    #[should_panic(expected = "expectation mismatch at index 0")]
+    fn test_transfer_word_count_other_peripheral() {
+        use embedded_hal::blocking::spi::Transfer;
+
+        let mut m = Mock::new();
+        let mut s1 = m.spi();
+        let s2 = m.spi();
+
+        m.expect(vec![
+            MockTransaction::transfer(&s2, vec![0xAA, 0xBB], vec![0x55]),
+        ]);
+
+        // Malformed expectations for other peripherals are reported as a mismatch
+        s1.transfer(&mut [0xAA, 0xBB]).expect("transfer failure");
+
+        m.finalise();
+    }
+
+    #[test]
+    fn test_write_status() {
+        let mut m = Mock::new();
+        let mut s = m.spi();
+
+        m.expect(vec![
+            MockTransaction::spi_write_status(&s, [0x02], [0xAA, 0xBB], [0x80]),
+        ]);
+
+        let mut status = [0u8; 1];
+        s.spi_write_status(&[0x02], &[0xAA, 0xBB], &mut status).expect("write failure");
+
+        m.finalise();
+        assert_eq!(status, [0x80]);
+    }
+
+    #[test]
+    fn test_transactional_exec() {
+        let mut m = Mock::new();
+        let mut s = m.spi();
+
+        m.expect(vec![
+            MockTransaction::spi_exec(&s, [MockExec::write([0x01, 0x02]), MockExec::read([0xAA, 0xBB])]),
+        ]);
+
+        let mut d = [0u8; 2];
+        s.spi_exec(&mut [Transaction::Write(&[0x01, 0x02]), Transaction::Read(&mut d)]).expect("exec failure");
+
+        m.finalise();
+        assert_eq!(d, [0xAA, 0xBB]);
+    }
+
+    #[test]
+    #[should_panic(expected = "expectation mismatch at index 0")]
+    fn test_transactional_read_length_mismatch() {
+        let mut m = Mock::new();
+        let mut s = m.spi();
+
+        m.expect(vec![MockTransaction::spi_read(&s, [0xFF], [0xAA, 0xBB, 0xCC])]);
+
+        // Mismatched lengths are reported on copy rather than overrunning the buffer
+        let mut d = [0u8; 2];
+        s.spi_read(&[0xFF], &mut d).expect("read failure");
+
+        m.finalise();
+    }
+
+    #[test]
+    #[should_panic(expected = "spi_read expected 4 words but driver provided a 2-word buffer")]
+    fn test_spi_read_buffer_length_mismatch() {
+        let mut m = Mock::new();
+        let mut s = m.spi();
+
+        m.expect(vec![MockTransaction::spi_read(&s, [0xFF], [0xAA, 0xBB, 0xCC, 0xDD])]);
+
+        let mut d = [0u8; 2];
+        let _ = s.spi_read(&[0xFF], &mut d);
+    }
+
+    #[test]
+    #[should_panic(expected = "spi_exec read 1 expected 1 words but driver provided a 3-word buffer")]
+    fn test_spi_exec_buffer_length_mismatch() {
+        let mut m = Mock::new();
+        let mut s = m.spi();
+
+        m.expect(vec![MockTransaction::spi_exec(&s, [MockExec::SpiWrite(vec![0x01]), MockExec::SpiRead(vec![0x02])])]);
+
+        let mut d = [0u8; 3];
+        let _ = s.spi_exec(&mut [Transaction::Write(&[0x01]), Transaction::Read(&mut d)]);
+    }
+
+    #[test]
+    fn test_read_fifo() {
+        use embedded_hal::blocking::spi::Transfer;
+
+        let mut m = Mock::new();
+        let mut s = m.spi();
+        m.push_read_data(vec![0x01, 0x02, 0x03]);
+        m.push_read_data(vec![0x04, 0x05]);
+
+        let mut a = [0u8; 2];
+        s.spi_read(&[0x80], &mut a).unwrap();
+        assert_eq!(a, [0x01, 0x02]);
+
+        assert_eq!(s.transfer(&mut [0u8; 3]).unwrap(), &[0x03, 0x04, 0x05]);
+
+        // Empty FIFOs leave buffers unchanged
+        let mut b = [0xAA];
+        s.spi_read(&[0x80], &mut b).unwrap();
+        assert_eq!(b, [0xAA]);
+
+        assert_eq!(m.per_peripheral()[&s.id], vec![
+            MockTransaction::spi_read(&s, [0x80], [0x01, 0x02]),
+            MockTransaction::transfer(&s, [0x00, 0x00, 0x00], [0x03, 0x04, 0x05]),
+            MockTransaction::spi_read(&s, [0x80], [0xAA]),
+        ]);
+    }
+
+    #[test]
+    fn test_loopback() {
+        use embedded_hal::blocking::spi::Transfer;
+
+        let mut m = Mock::new();
+        m.skip_finalise();
+        let mut s = m.spi();
+        m.loopback(true);
+
+        m.expect(vec![
+            MockTransaction::transfer(&s, [0x01, 0x02], [0xAA, 0xBB]),
+        ]);
+
+        // Explicit expectations still apply
+        assert_eq!(s.transfer(&mut [0x01, 0x02]).unwrap(), &[0xAA, 0xBB]);
+
+        // Otherwise outgoing data is echoed
+        assert_eq!(s.transfer(&mut [0x03, 0x04]).unwrap(), &[0x03, 0x04]);
+
+        let mut d = [0x05, 0x06];
+        s.spi_exec(&mut [Transaction::Write(&[0x10]), Transaction::Read(&mut d)]).unwrap();
+        assert_eq!(d, [0x05, 0x06]);
+
+        assert_eq!(m.per_peripheral()[&s.id][1..], [
+            MockTransaction::transfer(&s, [0x03, 0x04], [0x03, 0x04]),
+            MockTransaction::spi_exec(&s, [MockExec::write([0x10]), MockExec::read([0x05, 0x06])]),
+        ]);
+    }
+
+    #[test]
+    #[cfg(feature = "fuzz")]
+    fn test_loopback_seed() {
+        let mut m = Mock::with_read_seed(0x1234);
+        let mut s = m.spi();
+        m.loopback(true);
+
+        let mut d = [0x05, 0x06];
+        s.spi_read(&[0x01], &mut d).unwrap();
+        assert_eq!(d, [0x05, 0x06]);
+    }
+
+    #[test]
+    fn test_read_with_dummy_other_peripheral() {
+        let mut m = Mock::new();
+        let mut s1 = m.spi();
+        let s2 = m.spi();
+        m.skip_finalise();
+
+        m.expect(vec![
+            MockTransaction::spi_read_with_dummy(&s2, [0x0B, 0x10], 1, [0xAA, 0xBB]),
+        ]);
+
+        // Dummy read data is only returned to the matching peripheral
+        let mut d = [0u8; 3];
+        s1.spi_read(&[0x0B, 0x10], &mut d).unwrap();
+        assert_eq!(d, [0x00, 0x00, 0x00]);
+    }
+
+    #[test]
+    fn test_read_with_dummy() {
+        let mut m = Mock::new();
+        let mut s = m.spi();
+
+        m.expect(vec![
+            MockTransaction::spi_read_with_dummy(&s, [0x0B, 0x10], 1, [0xAA, 0xBB]),
+            MockTransaction::spi_read_with_dummy(&s, [0x0B, 0x10], 1, [0xAA, 0xBB]),
+        ]);
+
+        // Dummy clocked as part of the read buffer
+        let mut d = [0u8; 3];
+        s.spi_read(&[0x0B, 0x10], &mut d).unwrap();
+        assert_eq!(d[1..], [0xAA, 0xBB]);
+
+        // Dummy clocked as part of the prefix
+        let mut d = [0u8; 2];
+        s.spi_read(&[0x0B, 0x10, 0x00], &mut d).unwrap();
+        assert_eq!(d, [0xAA, 0xBB]);
+
+        m.finalise();
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_read_with_dummy_count() {
+        let mut m = Mock::new();
+        let mut s = m.spi();
+
+        m.expect(vec![
+            MockTransaction::spi_read_with_dummy(&s, [0x0B, 0x10], 1, [0xAA, 0xBB]),
+        ]);
+
+        let mut d = [0u8; 4];
+        s.spi_read(&[0x0B, 0x10], &mut d).unwrap();
+
+        m.finalise();
+    }
+
+    #[test]
+    fn test_capacity_ignored() {
+        let mut m = Mock::new();
+        let s = m.spi();
+
+        let mut v = Vec::with_capacity(16);
+        v.extend_from_slice(&[0xAA, 0xBB]);
+
+        assert_eq!(MockTransaction::SpiWrite(s.id, vec![0x01], v.clone()), MockTransaction::spi_write(&s, vec![0x01], vec![0xAA, 0xBB]));
+        assert_eq!(MockExec::SpiRead(v.clone()), MockExec::read(vec![0xAA, 0xBB]));
+        assert_eq!(MockExec::from(&Transaction::Write(&v)), MockExec::write([0xAA, 0xBB]));
+    }
+
+    #[test]
+    fn test_operation_errors() {
+        use embedded_hal::blocking::spi::{Write, Transfer};
+
+        let mut m = Mock::new();
+        let mut s = m.spi();
+
+        m.expect(vec![
+            MockTransaction::write_err(&s, [0xAA, 0xBB], Error::Aborted),
+            MockTransaction::spi_read_err(&s, [0x02], Error::Spi(MockError::Timeout)),
+            MockTransaction::transfer_err(&s, [0x00], Error::Spi(MockError::Bus)),
+            MockTransaction::spi_write(&s, [0x01], []),
+        ]);
+
+        assert_eq!(s.write(&[0xAA, 0xBB]), Err(Error::Aborted));
+        assert_eq!(s.spi_read(&[0x02], &mut [0u8; 2]), Err(Error::Spi(MockError::Timeout)));
+        assert!(s.transfer(&mut [0x00]).is_err());
+
+        // Operations not matching the failing kind succeed
+        s.spi_write(&[0x01], &[]).unwrap();
+
+        m.finalise();
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_operation_error_kind_mismatch() {
+        let mut m = Mock::new();
+        let mut s = m.spi();
+
+        m.expect(vec![
+            MockTransaction::spi_write_err(&s, [0x01], [], Error::Aborted),
+        ]);
+
+        s.spi_read(&[0x02], &mut [0u8; 2]).unwrap();
+
+        m.finalise();
+    }
+
+    #[test]
+    #[should_panic(expected = "expectation mismatch at index 0")]
+    fn test_operation_error_call_mismatch() {
+        use embedded_hal::blocking::spi::Write;
+
+        let mut m = Mock::new();
+        let mut s = m.spi();
+
+        m.expect(vec![
+            MockTransaction::write_err(&s, [0xAA, 0xBB], Error::Aborted),
+        ]);
+
+        // The error is returned, but the attempted call is compared at finalise
+        assert_eq!(s.write(&[0xAA, 0xCC]), Err(Error::Aborted));
+
+        m.finalise();
+    }
+
+    #[test]
+    fn test_expect_unordered() {
+        use embedded_hal::digital::v2::{InputPin, OutputPin};
+
+        let mut m = Mock::new();
+        let mut s = m.spi();
+        let mut p = m.pin();
+
+        m.expect_unordered(vec![
+            MockTransaction::spi_read(&s, [0x05], [0x80]),
+            MockTransaction::is_high(&p, true),
+            MockTransaction::reset(&s, PinState::Low),
+            MockTransaction::reset(&s, PinState::High),
+            MockTransaction::set_low(&p),
+        ]);
+
+        p.set_low().unwrap();
+        s.set_reset(PinState::Low).unwrap();
+        s.set_reset(PinState::High).unwrap();
+        assert_eq!(p.is_high(), Ok(true));
+
+        let mut d = [0u8; 1];
+        s.spi_read(&[0x05], &mut d).unwrap();
+        assert_eq!(d, [0x80]);
+
+        m.finalise();
+    }
+
+    #[test]
+    fn test_expect_unordered_toggle() {
+        use embedded_hal::digital::v2::{InputPin, OutputPin, ToggleableOutputPin};
+
+        let mut m = Mock::new();
+        let mut p = m.pin();
+        let q = m.pin();
+
+        m.expect_unordered(vec![
+            MockTransaction::is_high(&q, true),
+            MockTransaction::set_high(&p),
+            MockTransaction::set_low(&p),
+        ]);
+
+        // Toggles are served by the matching pin expectation, leaving the read in place
+        p.toggle().unwrap();
+        assert_eq!(q.is_high(), Ok(true));
+        p.set_low().unwrap();
+
+        m.finalise();
+    }
+
+    #[test]
+    fn test_expect_unordered_delay() {
+        use embedded_hal::blocking::delay::DelayMs;
+
+        let mut m = Mock::new();
+        let mut s = m.spi();
+        let mut d = m.delay();
+
+        m.expect_unordered(vec![
+            MockTransaction::busy(&s, PinState::High),
+            MockTransaction::user("ready"),
+            MockTransaction::delay_ms_at_least(5),
+        ]);
+
+        // Delays and markers are served by their own expectations, leaving the read in place
+        d.delay_ms(10);
+        m.mark("ready");
+        assert_eq!(s.get_busy(), Ok(PinState::High));
+
+        m.finalise();
+    }
+
+    #[test]
+    #[should_panic(expected = "1 expectations were not consumed (unordered)")]
+    fn test_expect_unordered_unconsumed() {
+        let mut m = Mock::new();
+        let mut s = m.spi();
+
+        m.expect_unordered(vec![
+            MockTransaction::spi_write(&s, [0x01], []),
+            MockTransaction::spi_write(&s, [0x02], []),
+        ]);
+
+        s.spi_write(&[0x02], &[]).unwrap();
+
+        m.finalise();
+    }
+
+    #[test]
+    #[should_panic(expected = "unexpected transaction SpiWrite(0, [3], []) on 'spi0' at index 1 (unordered)")]
+    fn test_expect_unordered_unexpected() {
+        let mut m = Mock::new();
+        let mut s = m.spi();
+
+        m.expect_unordered(vec![
+            MockTransaction::spi_write(&s, [0x01], []),
+            MockTransaction::spi_write(&s, [0x02], []),
+        ]);
+
+        s.spi_write(&[0x02], &[]).unwrap();
+        s.spi_write(&[0x03], &[]).unwrap();
+
+        m.finalise();
+    }
+
+    #[test]
+    fn test_expect_repeated() {
+        let mut m = Mock::new();
+        let mut s = m.spi();
+
+        m.expect([MockTransaction::spi_write(&s, [0x20], [])]);
+        m.expect_repeated(MockTransaction::busy(&s, PinState::High), 3);
+        m.expect_repeated(MockTransaction::busy(&s, PinState::Low), 1);
+
+        s.spi_write(&[0x20], &[]).unwrap();
+        while s.get_busy().unwrap() == PinState::High {}
+
+        m.finalise();
+    }
+
+    #[test]
+    fn test_expect_repeated_wildcard() {
+        let mut m = Mock::new();
+        let mut s = m.spi();
+
+        m.expect_repeated(MockTransaction::spi_write_any(&s, [0x80]), 2);
+
+        s.spi_write(&[0x80], &[0x01]).unwrap();
+        s.spi_write(&[0x80], &[0x02, 0x03]).unwrap();
+
+        m.finalise();
+    }
+
+    #[test]
+    fn test_expect_next() {
+        let mut m = Mock::new();
+        let mut s = m.spi();
+
+        m.expect_next(MockTransaction::spi_write(&s, [0x20], []));
+        s.spi_write(&[0x20], &[]).unwrap();
+        m.assert_done();
+
+        // Poll until ready, setting each expectation as the driver steps
+        for n in 0..4 {
+            let state = if n < 3 { PinState::High } else { PinState::Low };
+            m.expect_next(MockTransaction::busy(&s, state.clone()));
+
+            assert_eq!(s.get_busy().unwrap(), state);
+            m.assert_done();
+        }
+
+        m.finalise();
+    }
+
+    #[test]
+    #[should_panic(expected = "expectation mismatch at index 1")]
+    fn test_expect_next_after_finalise() {
+        let mut m = Mock::new();
+        let mut s = m.spi();
+
+        m.expect_next(MockTransaction::spi_write(&s, [0x20], []));
+        s.spi_write(&[0x20], &[]).unwrap();
+        m.finalise();
+
+        // Expectations queued after finalising are checked on drop
+        m.expect_next(MockTransaction::busy(&s, PinState::Low));
+    }
+
+    #[test]
+    #[should_panic(expected = "consumed 1 of 2 expectations")]
+    fn test_assert_done_pending() {
+        let mut m = Mock::new();
+        let mut s = m.spi();
+
+        m.expect_next(MockTransaction::spi_write(&s, [0x20], []));
+        m.expect_next(MockTransaction::busy(&s, PinState::Low));
+        s.spi_write(&[0x20], &[]).unwrap();
+
+        m.assert_done();
+    }
+
+    #[test]
+    #[should_panic(expected = "expected 4 repetitions of Busy(0, High) from index 0, found 3")]
+    fn test_expect_repeated_undershoot() {
+        let mut m = Mock::new();
+        let mut s = m.spi();
+
+        m.expect_repeated(MockTransaction::busy(&s, PinState::High), 4);
+
+        for _ in 0..3 {
+            s.get_busy().unwrap();
+        }
+
+        m.finalise();
+    }
+
+    #[test]
+    fn test_write_any() {
+        use embedded_hal::blocking::spi::Write;
+
+        let mut m = Mock::new();
+        let mut s = m.spi();
+
+        m.expect(vec![
+            MockTransaction::spi_write_any(&s, [0x80]),
+            MockTransaction::write_any(&s),
+            MockTransaction::spi_write(&s, [0x81], [0x01]),
+        ]);
+
+        s.spi_write(&[0x80], &[0xDE, 0xAD, 0xBE, 0xEF]).unwrap();
+        s.write(&[0x01, 0x02]).unwrap();
+        s.spi_write(&[0x81], &[0x01]).unwrap();
+
+        m.finalise();
+    }
+
+    #[test]
+    #[should_panic(expected = "expectation mismatch at index 0")]
+    fn test_write_any_prefix_mismatch() {
+        let mut m = Mock::new();
+        let mut s = m.spi();
+
+        m.expect(vec![
+            MockTransaction::spi_write_any(&s, [0x80]),
+        ]);
+
+        s.spi_write(&[0x81], &[0xDE, 0xAD]).unwrap();
+
+        m.finalise();
+    }
+
+    #[test]
+    #[should_panic(expected = "expectation mismatch at index 1
+  expected: SpiWrite(0, [01], [AA, BB]) on 'spi0'
+  actual:   SpiWrite(0, [01], [AA, BC]) on 'spi0'
+  buffer 1: expected [AA, BB], actual [AA, BC] (first difference at word 1)")]
+    fn test_mismatch_diagnostics() {
+        let mut m = Mock::new();
+        let mut s = m.spi();
+
+        m.expect(vec![
+            MockTransaction::spi_write(&s, [0x00], []),
+            MockTransaction::spi_write(&s, [0x01], [0xAA, 0xBB]),
+        ]);
+
+        s.spi_write(&[0x00], &[]).unwrap();
+        s.spi_write(&[0x01], &[0xAA, 0xBC]).unwrap();
+
+        m.finalise();
+    }
+
+    #[test]
+    #[should_panic(expected = "expected: SetHigh(1) on 'cs'\n  actual:   Write(0, [AA]) on 'radio'")]
+    fn test_named_handles() {
+        use embedded_hal::blocking::spi::Write;
+
+        let mut m = Mock::new();
+        let mut s = m.spi_named("radio");
+        let cs = m.pin_named("cs");
+        let d = m.delay();
+
+        assert_eq!(m.name(s.id), Some("radio".to_string()));
+        assert_eq!(m.name(d.id), Some("delay2".to_string()));
+        assert_eq!(m.duplicate().0.name(cs.id), Some("cs".to_string()));
+
+        m.expect(vec![MockTransaction::set_high(&cs)]);
+
+        s.write(&[0xAA]).unwrap();
+
+        m.finalise();
+    }
+
+    #[test]
+    #[should_panic(expected = "expectation mismatch at index 1: expected 2 transactions, got 1 (first missing: SpiWrite(0, [01], [AA]) on 'spi0')")]
+    fn test_mismatch_missing() {
+        let mut m = Mock::new();
+        let mut s = m.spi();
+
+        m.expect(vec![
+            MockTransaction::spi_write(&s, [0x00], []),
+            MockTransaction::spi_write(&s, [0x01], [0xAA]),
+        ]);
+
+        s.spi_write(&[0x00], &[]).unwrap();
+
+        m.finalise();
+    }
+
+    #[test]
+    #[should_panic(expected = "expectation mismatch at index 1: expected 1 transactions, got 2 (first unexpected: DelayMs(10))")]
+    fn test_mismatch_unexpected() {
+        let mut m = Mock::new();
+        let mut s = m.spi();
+
+        m.expect(vec![
+            MockTransaction::spi_write(&s, [0x00], []),
+        ]);
+
+        s.spi_write(&[0x00], &[]).unwrap();
+        s.delay_ms(10);
+
+        m.finalise();
+    }
+
+    #[test]
+    #[should_panic(expected = "expected: SpiExec(0, [SpiWrite([10]), Delay(12)]) on 'spi0'
+  actual:   SpiExec(0, [SpiWrite([10]), Delay(15)]) on 'spi0'")]
+    fn test_mismatch_decimal_fields() {
+        let mut m = Mock::new();
+        let mut s = m.spi();
+
+        m.expect(vec![
+            MockTransaction::spi_exec(&s, vec![MockExec::write([0x10]), MockExec::Delay(12)]),
+        ]);
+
+        s.spi_exec(&mut [Transaction::Write(&[0x10]), Transaction::Delay(15)]).unwrap();
+
+        m.finalise();
+    }
+
+    #[test]
+    #[cfg(feature = "hal1")]
+    fn test_spi_device_other_peripheral() {
+        use embedded_hal_1::spi::{SpiDevice, Operation};
+
+        let mut m = Mock::new();
+        let mut s1 = m.spi();
+        let s2 = m.spi();
+        m.skip_finalise();
+
+        m.expect(vec![
+            MockTransaction::spi_device(&s2, [MockOp::Read(vec![0xAA, 0xBB])]),
+        ]);
+
+        // Read data is only returned to the matching peripheral
+        let mut r = [0u8; 2];
+        s1.transaction(&mut [Operation::Read(&mut r)]).unwrap();
+        assert_eq!(r, [0x00, 0x00]);
+    }
+
+    #[test]
+    #[cfg(feature = "hal1")]
+    fn test_spi_device() {
+        use embedded_hal_1::spi::{SpiDevice, Operation};
+
+        let mut m = Mock::new();
+        let mut s = m.spi();
+
+        m.expect(vec![
+            MockTransaction::spi_device(&s, [
+                MockOp::Write(vec![0x01]),
+                MockOp::Read(vec![0xAA, 0xBB]),
+                MockOp::Transfer(vec![0x02], vec![0xCC]),
+                MockOp::TransferInPlace(vec![0x03, 0x04], vec![0xDD, 0xEE]),
+                MockOp::DelayNs(500),
+            ]),
+        ]);
+
+        let (mut r, mut t, mut p) = ([0u8; 2], [0u8; 1], [0x03, 0x04]);
+        s.transaction(&mut [
+            Operation::Write(&[0x01]),
+            Operation::Read(&mut r),
+            Operation::Transfer(&mut t, &[0x02]),
+            Operation::TransferInPlace(&mut p),
+            Operation::DelayNs(500),
+        ]).unwrap();
+
+        assert_eq!(r, [0xAA, 0xBB]);
+        assert_eq!(t, [0xCC]);
+        assert_eq!(p, [0xDD, 0xEE]);
+
+        m.finalise();
+    }
+
+    #[test]
+    #[cfg(feature = "hal1")]
+    fn test_spi_bus() {
+        use embedded_hal_1::spi::SpiBus;
+
+        let mut m = Mock::new();
+        let mut s = m.spi();
+
+        m.expect(vec![
+            MockTransaction::spi_device(&s, [MockOp::Write(vec![0x9F])]),
+            MockTransaction::spi_device(&s, [MockOp::Read(vec![0xEF, 0x40])]),
+        ]);
+
+        let mut d = [0u8; 2];
+        SpiBus::write(&mut s, &[0x9F]).unwrap();
+        SpiBus::read(&mut s, &mut d).unwrap();
+        SpiBus::flush(&mut s).unwrap();
+
+        assert_eq!(d, [0xEF, 0x40]);
+
+        m.finalise();
+    }
+
+    #[test]
+    fn test_actual_expected() {
+        let mut m = Mock::new();
+        m.skip_finalise();
+        let mut s = m.spi();
+
+        m.expect(vec![MockTransaction::spi_write(&s, [0x01], [0xAA])]);
+
+        s.spi_write(&[0x01], &[0xAA]).unwrap();
+        s.spi_write(&[0x02], &[0xBB, 0xCC]).unwrap();
+
+        let written: usize = m.actual().iter().map(|t| match t {
+            MockTransaction::SpiWrite(_, p, d) => p.len() + d.len(),
+            _ => 0,
+        }).sum();
+
+        assert_eq!(written, 5);
+        assert_eq!(m.expected(), vec![MockTransaction::spi_write(&s, [0x01], [0xAA])]);
+    }
+
+    #[test]
+    #[should_panic(expected = "expectation mismatch at index 0: expected 1 transactions, got 0")]
+    fn test_finalise_on_drop() {
+        let mut m = Mock::new();
+        let s = m.spi();
+
+        m.expect(vec![MockTransaction::spi_write(&s, [0x01], [0xAA])]);
+    }
+
+    #[test]
+    fn test_skip_finalise() {
+        let mut m = Mock::new();
+        let s = m.spi();
+        m.skip_finalise();
+
+        m.expect(vec![MockTransaction::spi_write(&s, [0x01], [0xAA])]);
+    }
+
+    #[test]
+    #[should_panic(expected = "driver assertion")]
+    fn test_finalise_on_drop_unwinding() {
+        let mut m = Mock::new();
+        let s = m.spi();
+
+        m.expect(vec![MockTransaction::spi_write(&s, [0x01], [0xAA])]);
+
+        // The original panic is reported, rather than aborting on a second panic in drop
+        panic!("driver assertion");
+    }
+
+    #[test]
+    fn test_reset_phases() {
+        let mut m = Mock::new();
+        let mut s = m.spi();
+
+        m.expect(vec![MockTransaction::spi_write(&s, [0x01], [0xAA])]);
+        s.spi_write(&[0x01], &[0xAA]).unwrap();
+        m.finalise();
+
+        m.reset();
+        assert!(m.actual().is_empty());
+        assert!(m.expected().is_empty());
+
+        m.expect(vec![MockTransaction::spi_read(&s, [0x02], [0xBB])]);
+        let mut d = [0u8; 1];
+        s.spi_read(&[0x02], &mut d).unwrap();
+        m.finalise();
+
+        assert_eq!(d, [0xBB]);
+    }
+
+    #[test]
+    fn test_expect_clears_run_state() {
+        use embedded_hal::digital::v2::{OutputPin, ToggleableOutputPin};
+
+        let mut m = Mock::new();
+        let mut p = m.pin();
+
+        m.expect(vec![MockTransaction::set_high(&p)]);
+        p.set_high().unwrap();
+        m.finalise();
+
+        // Pin states from the previous phase are cleared, so the toggle drives high again
+        m.expect(vec![MockTransaction::set_high(&p)]);
+        p.toggle().unwrap();
+        m.finalise();
+
+        assert_eq!(m.final_pin_state(p.id), Some(PinState::High));
+    }
+
+    #[test]
+    fn test_pin_state_conversions() {
+        let mut m = Mock::new();
+        let mut s = m.spi();
+
+        m.expect(vec![MockTransaction::busy(&s, true.into())]);
+
+        let state = s.get_busy().unwrap();
+        assert!(bool::from(state.clone()));
+        assert_eq!(format!("{}", state), "High");
+        assert_eq!(format!("{}", state.toggle()), "Low");
+        assert_eq!(PinState::from(false), PinState::Low);
+
+        m.finalise();
+    }
+
+    #[test]
+    fn test_error_display() {
+        use std::error::Error as _;
+
+        assert_eq!(format!("{}", Error::<MockError, MockError>::Spi(MockError::Bus)), "SPI error: Bus");
+        assert_eq!(format!("{}", Error::<MockError, MockError>::UnexpectedId(0x12)), "unexpected device id: 0x12");
+
+        let e: Box<dyn std::error::Error> = Box::new(Error::<MockError, MockError>::Pin(MockError::Gpio));
+        assert_eq!(e.source().map(|s| s.to_string() ), Some("mock gpio error".to_string()));
+        assert!(Error::<MockError, MockError>::Aborted.source().is_none());
+    }
+
+    #[test]
+    #[should_panic(expected = "unexpected is_high() call at index 1, no expectation set")]
+    fn test_strict_pins() {
+        use embedded_hal::digital::v2::InputPin;
+
+        let mut m = Mock::new();
+        let p = m.pin();
+        m.strict(true);
+
+        m.expect(vec![MockTransaction::is_high(&p, true)]);
+
+        assert_eq!(p.is_high(), Ok(true));
+        let _ = p.is_high();
+    }
+
+    #[test]
+    #[should_panic(expected = "unexpected get_busy() call at index 0, no expectation set")]
+    fn test_strict_busy() {
+        let mut m = Mock::new();
+        let mut s = m.spi();
+        m.strict(true);
+
+        m.expect(vec![MockTransaction::ready(&s, PinState::High)]);
+
+        let _ = s.get_busy();
+    }
+
+    #[test]
+    fn test_lenient_defaults() {
+        use embedded_hal::digital::v2::InputPin;
+
+        let mut m = Mock::new();
+        let mut s = m.spi();
+        let p = m.pin();
+
+        assert_eq!(s.get_busy(), Ok(PinState::Low));
+        assert_eq!(s.get_ready(), Ok(PinState::Low));
+        assert_eq!(p.is_high(), Ok(false));
+        assert_eq!(p.is_low(), Ok(false));
+    }
+
+    #[test]
+    fn test_delay_bounds() {
+        let mut m = Mock::new();
+        let mut s = m.spi();
+
+        m.expect(vec![
+            MockTransaction::delay_ms_at_least(10),
+            MockTransaction::delay_ms_at_most(5),
+            MockTransaction::delay_ms_range(10, 20),
+        ]);
+
+        s.delay_ms(15);
+        s.delay_ms(5);
+        s.delay_ms(10);
+
+        m.finalise();
+    }
+
+    #[test]
+    #[should_panic(expected = "expectation mismatch at index 0")]
+    fn test_delay_at_least_too_short() {
+        let mut m = Mock::new();
+        let mut s = m.spi();
+
+        m.expect(vec![MockTransaction::delay_ms_at_least(10)]);
+
+        s.delay_ms(9);
+
+        m.finalise();
+    }
+
+    #[test]
+    fn test_delay_us() {
+        let mut m = Mock::new();
+        let mut s = m.spi();
+        let mut d = m.delay();
+
+        m.expect(vec![
+            MockTransaction::delay_us(500),
+            MockTransaction::delay_ms(500),
+            MockTransaction::delay_us(10),
+        ]);
+
+        DelayUs::delay_us(&mut d, 500);
+        DelayMs::delay_ms(&mut d, 500);
+        DelayUs::delay_us(&mut s, 10);
+
+        m.finalise();
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_delay_us_distinct_from_ms() {
+        let mut m = Mock::new();
+        let mut d = m.delay();
+
+        m.expect(vec![
+            MockTransaction::delay_us(500),
+        ]);
+
+        DelayMs::delay_ms(&mut d, 500);
+
+        m.finalise();
+    }
+
+    #[test]
+    fn test_exec_records_data() {
+        let mut m = Mock::new();
+        let mut s = m.spi();
+
+        s.spi_exec(&mut [Transaction::Write(&[0xAA, 0xBB])]).unwrap();
+
+        assert_eq!(m.last(), Some(MockTransaction::SpiExec(s.id, vec![MockExec::SpiWrite(vec![0xAA, 0xBB])])));
+    }
+
+    #[test]
+    fn test_exec_delay() {
+        let mut m = Mock::new();
+        let mut s = m.spi();
+
+        m.expect(vec![
+            MockTransaction::spi_exec(&s, [MockExec::write([0x10]), MockExec::delay(5), MockExec::read([0x12, 0x34])]),
+        ]);
+
+        let mut d = [0u8; 2];
+        s.spi_exec(&mut [Transaction::Write(&[0x10]), Transaction::Delay(5), Transaction::Read(&mut d)]).unwrap();
+
+        m.finalise();
+        assert_eq!(d, [0x12, 0x34]);
+    }
+
+    #[test]
+    fn test_spi_write_read() {
+        let mut m = Mock::new();
+        let mut s = m.spi();
+
+        m.expect(vec![
+            MockTransaction::spi_exec(&s, [MockExec::write([0x0F]), MockExec::write([0x01, 0x02]), MockExec::read([0xAB])]),
+        ]);
+
+        let mut d = [0u8; 1];
+        s.spi_write_read(&[0x0F], &[0x01, 0x02], &mut d).unwrap();
+
+        m.finalise();
+        assert_eq!(d, [0xAB]);
+    }
+
+    #[test]
+    fn test_exec_constructors() {
+        let mut m = Mock::new();
+        let s = m.spi();
+
+        assert_eq!(
+            MockTransaction::spi_exec(&s, [MockExec::write([0x01, 0x02]), MockExec::read([0xAA])]),
+            MockTransaction::SpiExec(s.id, vec![MockExec::SpiWrite(vec![0x01, 0x02]), MockExec::SpiRead(vec![0xAA])]),
+        );
+    }
+
+    #[test]
+    fn test_expect_one() {
+        let mut m = Mock::new();
+        let mut s = m.spi();
+
+        m.expect_one(MockTransaction::spi_write(&s, [0xFF], [0xAA, 0xBB]));
+
+        s.spi_write(&[0xFF], &[0xAA, 0xBB]).expect("write failure");
+
+        m.finalise();
+    }
+
+    #[test]
+    fn test_expect_all() {
+        let mut m = Mock::new();
+        let mut s = m.spi();
+
+        m.expect_all([
+            MockTransaction::spi_write(&s, [0xFF], [0xAA]),
+            MockTransaction::delay_ms(1),
+            MockTransaction::spi_write(&s, [0xFF], [0xBB]),
+        ]);
+
+        s.spi_write(&[0xFF], &[0xAA]).expect("write failure");
+        s.delay_ms(1);
+        s.spi_write(&[0xFF], &[0xBB]).expect("write failure");
+
+        m.finalise();
+    }
+
+    enum Command {
+        Reset,
+        Read(u8),
+        Write(u8, u8),
+    }
+
+    impl IntoExpectations for &Command {
+        fn into_expectations(self, spi: Id) -> Vec<MockTransaction> {
+            match self {
+                Command::Reset => vec![
+                    MockTransaction::SpiWrite(spi, vec![0x99], vec![]),
+                    MockTransaction::DelayMs(1),
+                ],
+                Command::Read(r) => vec![MockTransaction::SpiRead(spi, vec![0x80 | r], vec![0x00])],
+                Command::Write(r, v) => vec![MockTransaction::SpiWrite(spi, vec![*r], vec![*v])],
+            }
+        }
+    }
+
+    #[test]
+    fn test_expect_commands() {
+        let mut m = Mock::new();
+        let mut s = m.spi();
+
+        let cmds = [Command::Reset, Command::Write(0x20, 0x07), Command::Read(0x20)];
+        m.expect_commands(s.id, &cmds);
+
+        for c in cmds.iter() {
+            match c {
+                Command::Reset => {
+                    s.spi_write(&[0x99], &[]).unwrap();
+                    s.delay_ms(1);
+                },
+                Command::Read(r) => s.spi_read(&[0x80 | r], &mut [0u8; 1]).unwrap(),
+                Command::Write(r, v) => s.spi_write(&[*r], &[*v]).unwrap(),
+            }
+        }
+
+        m.finalise();
+    }
+
+    #[test]
+    fn test_cloned_handles() {
+        use embedded_hal::digital::v2::OutputPin;
+
+        let mut m = Mock::new();
+        let s = m.spi();
+        let p = m.pin();
+
+        m.expect(vec![
+            MockTransaction::spi_write(&s, [0x01], [0xAA]),
+            MockTransaction::set_high(&p),
+        ]);
+
+        let mut s2 = s.clone();
+        let mut p2 = p.clone();
+        s2.spi_write(&[0x01], &[0xAA]).unwrap();
+        p2.set_high().unwrap();
+
+        m.finalise();
+    }
+
+    #[test]
+    fn test_standard_write() {
+        use embedded_hal::blocking::spi::Write;
+
+        let mut m = Mock::new();
+        let mut s = m.spi();
+
+        let data = vec![0xAA, 0xBB];
+
+        m.expect(vec![MockTransaction::write(&s, data.clone())]);
+
+        s.write(&data).expect("write failure");
+
+        m.finalise();
+    }
+
+    #[test]
+    fn test_standard_transfer() {
+        use embedded_hal::blocking::spi::Transfer;
+
+        let mut m = Mock::new();
+        let mut s = m.spi();
+
+        let outgoing = vec![0xAA, 0xBB];
+        let incoming = vec![0xCC, 0xDD];
+
+        m.expect(vec![MockTransaction::transfer(&s, outgoing.clone(), incoming.clone())]);
+
+        let mut d = outgoing.clone();
+        s.transfer(&mut d).expect("read failure");
+
+        m.finalise();
+        assert_eq!(&incoming, &d);
+    }
+
+    #[test]
+    fn test_word_types() {
+        use embedded_hal::blocking::spi::{Transfer, Write};
+
+        let mut m = Mock::<u16>::default();
+        let mut s = m.spi();
+
+        let outgoing = vec![0x1AA, 0x0BB];
+        let incoming = vec![0x1CC, 0x0DD];
+
+        m.expect(vec![
+            MockTransaction::write(&s, vec![0x1FF, 0x100]),
+            MockTransaction::transfer(&s, outgoing.clone(), incoming.clone()),
+        ]);
+
+        s.write(&[0x1FF, 0x100]).expect("write failure");
+
+        let mut d = outgoing.clone();
+        s.transfer(&mut d).expect("transfer failure");
+
+        m.finalise();
+        assert_eq!(&incoming, &d);
+    }
+
+    #[test]
+    fn test_standard_transfer_u16() {
+        use embedded_hal::blocking::spi::Transfer;
+
+        let mut m = Mock::<u16>::default();
+        let mut s = m.spi();
+
+        let outgoing = vec![0xAA55, 0xBB66];
+        let incoming = vec![0xCC77, 0xDD88];
+
+        m.expect(vec![MockTransaction::transfer(&s, outgoing.clone(), incoming.clone())]);
+
+        let mut d = outgoing.clone();
+        s.transfer(&mut d).expect("read failure");
+
+        m.finalise();
+        assert_eq!(&incoming, &d);
+    }
+
+    #[test]
+    fn test_write_read() {
+        let mut m = Mock::new();
+        let mut s = m.spi();
+
+        m.expect(vec![MockTransaction::write_read(&s, [0x0A], [0xBE, 0xEF])]);
+
+        let mut d = [0u8; 2];
+        s.write_read(&[0x0A], &mut d).expect("write_read failure");
+
+        m.finalise();
+        assert_eq!(d, [0xBE, 0xEF]);
+    }
+
+    #[test]
+    #[should_panic(expected = "write_read expected 2 words but driver provided a 3-word buffer")]
+    fn test_write_read_length_mismatch() {
+        let mut m = Mock::new();
+        let mut s = m.spi();
+
+        m.expect(vec![MockTransaction::write_read(&s, [0x0A], [0xBE, 0xEF])]);
+
+        let mut d = [0u8; 3];
+        let _ = s.write_read(&[0x0A], &mut d);
+    }
+
+    #[test]
+    #[should_panic(expected = "transfer expectation at index 0 has 2 outgoing but 1 incoming words")]
+    fn test_transfer_word_count_mismatch() {
+        use embedded_hal::blocking::spi::Transfer;
+
+        let mut m = Mock::<u16>::default();
+        let mut s = m.spi();
+
+        m.expect(vec![MockTransaction::transfer(&s, vec![0xAA55, 0xBB66], vec![0xCC77])]);
+
+        s.transfer(&mut [0xAA55, 0xBB66]).unwrap();
+    }
+
+    #[test]
+    fn test_u16_pins() {
+        use embedded_hal::digital::v2::OutputPin;
+
+        let mut m = Mock::<u16>::default();
+        let mut p = m.pin();
+
+        m.expect(vec![MockTransaction::set_low(&p), MockTransaction::set_high(&p)]);
+
+        p.set_low().unwrap();
+        p.set_high().unwrap();
+
+        m.finalise();
+    }
+
+    #[test]
+    fn test_byte_mock() {
+        use embedded_hal::blocking::spi::Write;
+
+        let mut m = ByteMock::new();
+        let mut s = m.spi();
+
+        m.expect(vec![MockTransaction::write(&s, vec![0xAA])]);
+        s.write(&[0xAA]).expect("write failure");
+
+        m.finalise();
+    }
+
+     #[test]
+    fn test_pins() {
+        use embedded_hal::digital::v2::{InputPin, OutputPin};
+
+        let mut m = Mock::new();
+        let mut p = m.pin();
+
+        m.expect(vec![
+            MockTransaction::is_high(&p, true),
+            MockTransaction::is_low(&p, false),
+            MockTransaction::set_high(&p),
+            MockTransaction::set_low(&p),
+        ]);
+
+        assert_eq!(true, p.is_high().unwrap());
+        assert_eq!(false, p.is_low().unwrap());
+
+        p.set_high().unwrap();
+        p.set_low().unwrap();
+
+        m.finalise();
+    }
+
+    use crate::Identify;
+
+    impl Identify<MockError, MockError> for Spi {
+        const ID_PREFIX: &'static [u8] = &[0x8F];
+    }
+
+    #[test]
+    fn test_identify() {
+        let mut m = Mock::new();
+        let mut s = m.spi();
+
+        m.expect(vec![
+            MockTransaction::spi_read(&s, vec![0x8F], vec![0x42]),
+            MockTransaction::spi_read(&s, vec![0x8F], vec![0x42]),
+        ]);
+
+        assert_eq!(s.read_id(), Ok(0x42));
+        assert_eq!(s.verify_id(0x42), Ok(()));
+
+        m.finalise();
+    }
+
+    #[test]
+    fn test_identify_mismatch() {
+        let mut m = Mock::new();
+        let mut s = m.spi();
+
+        m.expect(vec![
+            MockTransaction::spi_read(&s, vec![0x8F], vec![0x24]),
+        ]);
+
+        assert_eq!(s.verify_id(0x42), Err(Error::UnexpectedId(0x24)));
+
+        m.finalise();
+    }
+
+    use crate::BurstRead;
+
+    impl BurstRead for Spi {}
+
+    #[test]
+    fn test_burst_read() {
+        let mut m = Mock::new();
+        let mut s = m.spi();
+
+        let data = [0x01, 0x02, 0x03, 0x04, 0x05, 0x06];
+        m.expect(vec![
+            MockTransaction::spi_read(&s, vec![0x80 | 0x28], data),
+        ]);
+
+        let mut d = [0u8; 6];
+        s.burst_read(0x28, &mut d).unwrap();
+
+        m.finalise();
+        assert_eq!(d, data);
+    }
+
+    use crate::PowerMode;
+
+    impl PowerMode for Spi {
+        fn mode_command(mode: Mode) -> u8 {
+            match mode {
+                Mode::Sleep => 0xB9,
+                Mode::Standby => 0xB0,
+                Mode::Active => 0xAB,
+            }
+        }
+    }
+
+    #[test]
+    fn test_power_mode() {
+        let mut m = Mock::new();
+        let mut s = m.spi();
+
+        m.expect(vec![
+            MockTransaction::spi_write(&s, [0xB0], []),
+            MockTransaction::spi_write(&s, [0x10], [0x01]),
+            MockTransaction::spi_write(&s, [0xAB], []),
+        ]);
+
+        s.set_mode(Mode::Standby).unwrap();
+        s.spi_write(&[0x10], &[0x01]).unwrap();
+        s.set_mode(Mode::Active).unwrap();
+
+        m.finalise();
+
+        // Configuration written in standby
+        assert_eq!(m.mode_at::<Spi>(s.id, 0), Option::None);
+        assert_eq!(m.mode_at::<Spi>(s.id, 1), Some(Mode::Standby));
+        assert_eq!(m.mode_at::<Spi>(s.id, 3), Some(Mode::Active));
+    }
+
+    #[test]
+    fn test_register_after_reset() {
+        let mut m = Mock::new();
+        let mut s = m.spi();
+
+        m.register_after_reset(&[0x8F], 0x33);
+
+        assert_eq!(s.read_id(), Ok(0x00));
+        s.set_reset(PinState::Low).unwrap();
+        s.set_reset(PinState::High).unwrap();
+        assert_eq!(s.read_id(), Ok(0x33));
+    }
+
+    #[test]
+    fn test_register_after_soft_reset() {
+        let mut m = Mock::new();
+        let mut s = m.spi();
+
+        m.register_after_reset(&[0x8F], 0x33);
+        m.soft_reset_command(&[0xB6]);
+
+        assert_eq!(s.verify_id(0x33), Err(Error::UnexpectedId(0x00)));
+        s.soft_reset(&[0xB6]).unwrap();
+        assert_eq!(s.verify_id(0x33), Ok(()));
+    }
+
+    #[test]
+    fn test_inject() {
+        use embedded_hal::digital::v2::OutputPin;
+
+        let mut m = Mock::new();
+        let mut s = m.spi();
+        let mut p = m.pin();
+
+        m.expect(vec![
+            MockTransaction::spi_error(&s, MockError::Timeout),
+            MockTransaction::spi_write(&s, [0x01], [0xAA]),
+            MockTransaction::spi_error(&s, MockError::Gpio),
+            MockTransaction::pin_error(&p, MockError::Gpio),
+        ]);
+
+        assert_eq!(s.spi_write(&[0x01], &[0xAA]), Err(Error::Spi(MockError::Timeout)));
+        assert_eq!(s.spi_write(&[0x01], &[0xAA]), Ok(()));
+        assert_eq!(s.get_busy(), Err(Error::Pin(MockError::Gpio)));
+        assert_eq!(p.set_high(), Err(MockError::Gpio));
+
+        m.finalise();
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_inject_other_peripheral() {
+        let mut m = Mock::new();
+        let mut s1 = m.spi();
+        let s2 = m.spi();
+
+        m.expect(vec![
+            MockTransaction::spi_error(&s2, MockError::Bus),
+        ]);
+
+        // Errors are only injected into the matching peripheral
+        assert_eq!(s1.spi_write(&[0x01], &[0xAA]), Ok(()));
+
+        m.finalise();
+    }
+
+    #[test]
+    #[should_panic(expected = "injected error Timeout for peripheral 0 at index 1 was not triggered")]
+    fn test_errors_fired() {
+        let mut m = Mock::new();
+        let mut s = m.spi();
+        m.assert_all_errors_fired(true);
+
+        m.expect(vec![
+            MockTransaction::spi_write(&s, [0x01], [0xAA]),
+            MockTransaction::spi_error(&s, MockError::Timeout),
+        ]);
+
+        s.spi_write(&[0x01], &[0xAA]).unwrap();
+
+        m.finalise();
+    }
+
+    #[test]
+    fn test_unit_error() {
+        let e: UnitError = Error::<MockError, MockError>::Spi(MockError::Bus).into();
+        assert_eq!(e, Error::Spi(()));
+    }
+
+    #[test]
+    fn test_lanes() {
+        let mut m = Mock::new();
+        let mut s = m.spi();
+
+        m.expect(vec![
+            MockTransaction::spi_write(&s, [0x38], []),
+            MockTransaction::set_lanes(&s, Lanes::Quad),
+            MockTransaction::spi_read(&s, [0xEB, 0x00, 0x10, 0x00], [0xAA, 0xBB]),
+            MockTransaction::set_lanes(&s, Lanes::Single),
+        ]);
+
+        s.spi_write(&[0x38], &[]).unwrap();
+        s.set_lanes(Lanes::Quad).unwrap();
+        s.spi_read(&[0xEB, 0x00, 0x10, 0x00], &mut [0u8; 2]).unwrap();
+        s.set_lanes(Lanes::Single).unwrap();
+
+        m.finalise();
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_lanes_missing() {
+        let mut m = Mock::new();
+        let mut s = m.spi();
+
+        m.expect(vec![
+            MockTransaction::set_lanes(&s, Lanes::Quad),
+            MockTransaction::spi_read(&s, [0xEB, 0x00, 0x10, 0x00], [0xAA, 0xBB]),
+        ]);
+
+        // Fast read without switching to quad mode
+        s.spi_read(&[0xEB, 0x00, 0x10, 0x00], &mut [0u8; 2]).unwrap();
+
+        m.finalise();
+    }
+
+    #[test]
+    fn test_begin_end() {
+        let mut m = Mock::new();
+        let mut s = m.spi();
+
+        m.expect(vec![
+            MockTransaction::begin(&s),
+            MockTransaction::spi_write(&s, vec![0x01], vec![0x02]),
+            MockTransaction::spi_read(&s, vec![0x03], vec![0x04]),
+            MockTransaction::end(&s),
+        ]);
+
+        let mut d = [0u8; 1];
+        s.begin().unwrap();
+        s.spi_write(&[0x01], &[0x02]).unwrap();
+        s.spi_read(&[0x03], &mut d).unwrap();
+        s.end().unwrap();
+
+        m.finalise();
+        assert_eq!(d, [0x04]);
+    }
+
+    #[test]
+    #[should_panic(expected = "end without begin")]
+    fn test_end_without_begin() {
+        let mut m = Mock::new();
+        let mut s = m.spi();
+
+        m.expect(vec![
+            MockTransaction::end(&s),
+        ]);
+
+        s.end().unwrap();
+
+        m.finalise();
+    }
+
+    #[test]
+    #[should_panic(expected = "nested begin")]
+    fn test_nested_begin() {
+        let mut m = Mock::new();
+        let mut s = m.spi();
+
+        m.expect(vec![
+            MockTransaction::begin(&s),
+            MockTransaction::begin(&s),
+            MockTransaction::end(&s),
+            MockTransaction::end(&s),
+        ]);
+
+        s.begin().unwrap();
+        s.begin().unwrap();
+        s.end().unwrap();
+        s.end().unwrap();
+
+        m.finalise();
+    }
+
+    #[test]
+    #[should_panic(expected = "begin without end")]
+    fn test_begin_without_end() {
+        let mut m = Mock::new();
+        let mut s = m.spi();
+
+        m.expect(vec![
+            MockTransaction::begin(&s),
+        ]);
+
+        s.begin().unwrap();
+
+        m.finalise();
+    }
+
+    #[test]
+    fn test_finalise_unordered_within_window() {
+        use embedded_hal::digital::v2::OutputPin;
+
+        let mut m = Mock::new();
+        let mut s = m.spi();
+        let mut cs = m.pin();
+
+        m.expect(vec![
+            MockTransaction::set_low(&cs),
+            MockTransaction::spi_write(&s, [0x20], [0x01]),
+            MockTransaction::spi_write(&s, [0x21], [0x02]),
+            MockTransaction::spi_read(&s, [0x0F], [0x42]),
+            MockTransaction::set_high(&cs),
+        ]);
+
+        cs.set_low().unwrap();
+        s.spi_write(&[0x21], &[0x02]).unwrap();
+        s.spi_write(&[0x20], &[0x01]).unwrap();
+        s.spi_read(&[0x0F], &mut [0u8; 1]).unwrap();
+        cs.set_high().unwrap();
+
+        m.finalise_unordered_within_window(cs.id);
+    }
+
+    #[test]
+    #[should_panic(expected = "unordered within windows")]
+    fn test_finalise_unordered_within_window_outside() {
+        use embedded_hal::digital::v2::OutputPin;
+
+        let mut m = Mock::new();
+        let mut s = m.spi();
+        let mut cs = m.pin();
+
+        m.expect(vec![
+            MockTransaction::spi_write(&s, [0x20], [0x01]),
+            MockTransaction::set_low(&cs),
+            MockTransaction::spi_write(&s, [0x21], [0x02]),
+            MockTransaction::set_high(&cs),
+        ]);
+
+        // Writes may not move across window boundaries
+        s.spi_write(&[0x21], &[0x02]).unwrap();
+        cs.set_low().unwrap();
+        s.spi_write(&[0x20], &[0x01]).unwrap();
+        cs.set_high().unwrap();
+
+        m.finalise_unordered_within_window(cs.id);
+    }
+
+    #[test]
+    fn test_finalise_ignoring_delays() {
+        use embedded_hal::blocking::spi::Write;
+
+        let mut m = Mock::new();
+        let mut s = m.spi();
+        let mut d = m.delay();
+
+        m.expect(vec![
+            MockTransaction::write(&s, vec![0xAA]),
+            MockTransaction::delay_ms(10),
+            MockTransaction::write(&s, vec![0xBB]),
+        ]);
+
+        s.write(&[0xAA]).unwrap();
+        d.delay_ms(1);
+        d.delay_ms(2);
+        s.write(&[0xBB]).unwrap();
+        d.delay_ms(3);
+
+        m.finalise_ignoring_delays();
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_finalise_ignoring_delays_mismatch() {
+        use embedded_hal::blocking::spi::Write;
+
+        let mut m = Mock::new();
+        let mut s = m.spi();
+
+        m.expect(vec![
+            MockTransaction::write(&s, vec![0xAA]),
+            MockTransaction::delay_ms(10),
+            MockTransaction::write(&s, vec![0xBB]),
+        ]);
+
+        s.write(&[0xAA]).unwrap();
+        s.delay_ms(10);
+        s.write(&[0xCC]).unwrap();
+
+        m.finalise_ignoring_delays();
+    }
+
+    #[test]
+    fn test_finalise_ignoring_delays_wildcard() {
+        use embedded_hal::blocking::spi::Write;
+
+        let mut m = Mock::new();
+        let mut s = m.spi();
+
+        m.expect(vec![
+            MockTransaction::spi_write_any(&s, [0x80]),
+            MockTransaction::write_any(&s),
+        ]);
+
+        s.spi_write(&[0x80], &[0x01, 0x02]).unwrap();
+        s.delay_ms(5);
+        s.write(&[0xAA]).unwrap();
+
+        m.finalise_ignoring_delays();
+    }
+
+    #[test]
+    fn test_per_peripheral() {
+        use embedded_hal::blocking::spi::Write;
+        use embedded_hal::digital::v2::OutputPin;
+
+        let mut m = Mock::new();
+        let mut s1 = m.spi();
+        let mut s2 = m.spi();
+        let mut cs = m.pin();
+
+        cs.set_low().unwrap();
+        s1.write(&[0xAA]).unwrap();
+        s2.write(&[0xBB]).unwrap();
+        s1.delay_ms(5);
+        s1.spi_write(&[0x01], &[0x02]).unwrap();
+        cs.set_high().unwrap();
+
+        let p = m.per_peripheral();
+        assert_eq!(p.len(), 3);
+
+        assert_eq!(p[&s1.id], vec![
+            MockTransaction::write(&s1, vec![0xAA]),
+            MockTransaction::delay_ms(5),
+            MockTransaction::spi_write(&s1, vec![0x01], vec![0x02]),
+        ]);
+        assert_eq!(p[&s2.id], vec![
+            MockTransaction::write(&s2, vec![0xBB]),
+            MockTransaction::delay_ms(5),
+        ]);
+        assert_eq!(p[&cs.id], vec![
+            MockTransaction::set_low(&cs),
+            MockTransaction::delay_ms(5),
+            MockTransaction::set_high(&cs),
+        ]);
+    }
+
+    #[test]
+    fn test_duplicate() {
+        use embedded_hal::digital::v2::OutputPin;
+
+        let mut m = Mock::new();
+        m.skip_finalise();
+        let s = m.spi();
+        let p = m.pin();
+
+        m.expect(vec![
+            MockTransaction::spi_write(&s, [0x01], [0xAA]),
+            MockTransaction::set_high(&p),
+        ]);
+
+        for _ in 0..2 {
+            let (d, handles) = m.duplicate();
+            let (mut s, mut p) = match &handles[..] {
+                [Handle::Spi(s), Handle::Pin(p)] => (s.clone(), p.clone()),
+                _ => panic!("unexpected handles {:?}", handles),
+            };
+
+            s.spi_write(&[0x01], &[0xAA]).unwrap();
+            p.set_high().unwrap();
+
+            d.finalise();
+        }
+
+        // The original is unaffected
+        assert_eq!(m.unmet().len(), 2);
+    }
+
+    #[test]
+    fn test_write_before_read() {
+        let mut m = Mock::new();
+        let mut s = m.spi();
+
+        s.spi_read(&[0x0F], &mut [0u8; 1]).unwrap();
+        s.spi_write(&[0x20], &[0x07]).unwrap();
+        s.spi_read(&[0x28], &mut [0u8; 6]).unwrap();
+
+        m.assert_write_before_read(s.id, &[0x20], &[0x28]);
+        m.assert_write_before_read(s.id, &[0x20], &[0x29]);
+    }
+
+    #[test]
+    #[should_panic(expected = "before write")]
+    fn test_write_before_read_out_of_order() {
+        let mut m = Mock::new();
+        let mut s = m.spi();
+
+        s.spi_read(&[0x28], &mut [0u8; 6]).unwrap();
+        s.spi_write(&[0x20], &[0x07]).unwrap();
+
+        m.assert_write_before_read(s.id, &[0x20], &[0x28]);
+    }
+
+    #[test]
+    #[should_panic(expected = "without prior write")]
+    fn test_write_before_read_missing() {
+        let mut m = Mock::new();
+        let mut s = m.spi();
+        let mut s2 = m.spi();
+
+        // Writes on other peripherals do not count
+        s2.spi_write(&[0x20], &[0x07]).unwrap();
+        s.spi_read(&[0x28], &mut [0u8; 6]).unwrap();
+
+        m.assert_write_before_read(s.id, &[0x20], &[0x28]);
+    }
+
+    #[test]
+    fn test_opcode_issued() {
+        use embedded_hal::blocking::spi::Write;
+
+        let mut m = Mock::new();
+        let mut s1 = m.spi();
+        let mut s2 = m.spi();
+
+        s1.spi_write(&[], &[0x99, 0x00]).unwrap();
+        s1.spi_read(&[0x9F], &mut [0u8; 3]).unwrap();
+        s1.spi_exec(&mut [Transaction::Write(&[0x06])]).unwrap();
+        s2.write(&[0xB7]).unwrap();
+
+        assert!(m.assert_opcode_issued(s1.id, 0x99));
+        assert!(m.assert_opcode_issued(s1.id, 0x06));
+        assert!(m.assert_opcode_issued(s2.id, 0xB7));
+
+        // Reads and other peripherals are not considered
+        assert!(!m.assert_opcode_issued(s1.id, 0x9F));
+        assert!(!m.assert_opcode_issued(s2.id, 0x99));
+        assert!(!m.assert_opcode_issued(s1.id, 0x00));
+    }
+
+    #[test]
+    fn test_no_cs_release_between() {
+        use embedded_hal::digital::v2::OutputPin;
+
+        let mut m = Mock::new();
+        let mut s = m.spi();
+        let mut cs = m.pin();
+
+        cs.set_low().unwrap();
+        s.spi_write(&[0x02, 0x10], &[]).unwrap();
+        s.spi_read(&[], &mut [0u8; 2]).unwrap();
+        cs.set_high().unwrap();
+        cs.set_low().unwrap();
+        s.spi_read(&[], &mut [0u8; 2]).unwrap();
+
+        m.assert_no_cs_release_between(cs.id, 1, 2);
+    }
+
+    #[test]
+    #[should_panic(expected = "chip select 1 released at index 3 (between 1 and 5)")]
+    fn test_no_cs_release_between_released() {
+        use embedded_hal::digital::v2::OutputPin;
+
+        let mut m = Mock::new();
+        let mut s = m.spi();
+        let mut cs = m.pin();
+
+        cs.set_low().unwrap();
+        s.spi_write(&[0x02, 0x10], &[]).unwrap();
+        s.spi_read(&[], &mut [0u8; 2]).unwrap();
+        cs.set_high().unwrap();
+        cs.set_low().unwrap();
+        s.spi_read(&[], &mut [0u8; 2]).unwrap();
+
+        m.assert_no_cs_release_between(cs.id, 1, 5);
+    }
+
+    #[test]
+    fn test_no_interleave() {
+        use embedded_hal::digital::v2::OutputPin;
+
+        let mut m = Mock::new();
+        let mut a = m.pin();
+        let mut b = m.pin();
+
+        a.set_low().unwrap();
+        a.set_high().unwrap();
+        b.set_low().unwrap();
+        b.set_high().unwrap();
+        a.set_low().unwrap();
+        a.set_high().unwrap();
+
+        m.assert_no_interleave(a.id, b.id);
+    }
+
+    #[test]
+    #[should_panic(expected = "chip select windows for 0 and 1 interleaved at index 2")]
+    fn test_no_interleave_overlap() {
+        use embedded_hal::digital::v2::OutputPin;
+
+        let mut m = Mock::new();
+        let mut a = m.pin();
+        let mut b = m.pin();
+
+        a.set_low().unwrap();
+        a.set_low().unwrap();
+        b.set_low().unwrap();
+        a.set_high().unwrap();
+        b.set_high().unwrap();
+
+        m.assert_no_interleave(a.id, b.id);
+    }
+
+    #[test]
+    fn test_total_read() {
+        use embedded_hal::blocking::spi::Transfer;
+
+        let mut m = Mock::new();
+        let mut s = m.spi();
+        let mut s2 = m.spi();
+
+        s.spi_read(&[0x80], &mut [0u8; 4]).unwrap();
+        s.spi_write(&[0x01], &[0u8; 8]).unwrap();
+        s.transfer(&mut [0u8; 2]).unwrap();
+        s.spi_exec(&mut [Transaction::Write(&[0x80]), Transaction::Read(&mut [0u8; 3])]).unwrap();
+        s2.spi_read(&[0x80], &mut [0u8; 16]).unwrap();
+
+        m.assert_total_read(s.id, 9);
+        m.assert_total_read(s2.id, 16);
+    }
+
+    #[test]
+    #[should_panic(expected = "unexpected total read length for peripheral 0")]
+    fn test_total_read_mismatch() {
+        let mut m = Mock::new();
+        let mut s = m.spi();
+
+        s.spi_read(&[0x80], &mut [0u8; 4]).unwrap();
+
+        m.assert_total_read(s.id, 6);
+    }
+
+    #[test]
+    fn test_kind() {
+        let mut m = Mock::new();
+        let s = m.spi();
+
+        assert_eq!(MockTransaction::spi_write(&s, [0x01], [0x02]).kind(), TxnKind::SpiWrite);
+        assert_eq!(MockTransaction::<u8>::delay_ms(10).kind(), TxnKind::DelayMs);
+    }
+
+    #[test]
+    fn test_min_delay_between() {
+        let mut m = Mock::new();
+        let mut s = m.spi();
+
+        s.spi_write(&[0x01], &[0xAA]).unwrap();
+        s.delay_ms(2);
+        s.spi_read(&[0x02], &mut [0u8; 1]).unwrap();
+        s.delay_ms(3);
+        s.spi_write(&[0x01], &[0xBB]).unwrap();
+        s.delay_ms(5);
+        s.spi_write(&[0x01], &[0xCC]).unwrap();
+
+        m.assert_min_delay_between(TxnKind::SpiWrite, 5);
+    }
+
+    #[test]
+    #[should_panic(expected = "only 4 ms between SpiWrite transactions at index 2 and 4")]
+    fn test_min_delay_between_too_fast() {
+        let mut m = Mock::new();
+        let mut s = m.spi();
+
+        s.spi_write(&[0x01], &[0xAA]).unwrap();
+        s.delay_ms(5);
+        s.spi_write(&[0x01], &[0xBB]).unwrap();
+        s.delay_ms(4);
+        s.spi_write(&[0x01], &[0xCC]).unwrap();
+
+        m.assert_min_delay_between(TxnKind::SpiWrite, 5);
+    }
+
+    #[test]
+    #[should_panic(expected = "[fixture: fast-mode] expectation mismatch at index 1")]
+    fn test_label() {
+        let mut m = Mock::new();
+        let mut s = m.spi();
+        m.label("fixture: fast-mode");
+
+        m.expect(vec![
+            MockTransaction::spi_write(&s, [0x01], [0xAA]),
+            MockTransaction::spi_write(&s, [0x02], [0xBB]),
+        ]);
+
+        s.spi_write(&[0x01], &[0xAA]).unwrap();
+        s.spi_write(&[0x02], &[0xCC]).unwrap();
+
+        m.finalise();
+    }
+
+    #[test]
+    #[should_panic(expected = "[fixture: slow-mode] begin without end")]
+    fn test_label_framing() {
+        let mut m = Mock::new();
+        let mut s = m.spi();
+        m.label("fixture: slow-mode");
+
+        s.begin().unwrap();
+
+        m.finalise();
+    }
+
+    #[test]
+    fn test_unmet() {
+        let mut m = Mock::new();
+        let mut s = m.spi();
+
+        let expectations = vec![
+            MockTransaction::spi_write(&s, [0x01], [0xAA]),
+            MockTransaction::spi_write(&s, [0x02], [0xBB]),
+            MockTransaction::delay_ms(10),
+        ];
+        m.expect(&expectations);
+        assert!(!m.is_done());
+        assert_eq!(m.unmet(), expectations);
+
+        s.spi_write(&[0x01], &[0xAA]).unwrap();
+        assert!(!m.is_done());
+        assert_eq!(m.unmet(), &expectations[1..]);
+
+        s.spi_write(&[0x02], &[0xBB]).unwrap();
+        s.delay_ms(10);
+        assert!(m.is_done());
+        assert_eq!(m.unmet(), vec![]);
+
+        m.finalise();
+    }
+
+    #[test]
+    fn test_rewind() {
+        let mut m = Mock::new();
+        let mut s = m.spi();
+
+        // Rewinding with no transactions is a no-op
+        m.rewind();
+
+        m.expect(vec![
+            MockTransaction::spi_write(&s, [0x01], [0xAA]),
+            MockTransaction::spi_write(&s, [0x02], [0xBB]),
+        ]);
+
+        s.spi_write(&[0x01], &[0xAA]).unwrap();
+        s.spi_write(&[0x03], &[0xCC]).unwrap();
+        m.rewind();
+        assert_eq!(m.unmet().len(), 1);
+
+        s.spi_write(&[0x02], &[0xBB]).unwrap();
+
+        m.finalise();
+    }
+
+    #[test]
+    fn test_trailing_delays() {
+        let mut m = Mock::new();
+        let mut s = m.spi();
+        m.allow_trailing_delays(true);
+
+        m.expect(vec![
+            MockTransaction::delay_ms(1),
+            MockTransaction::spi_write(&s, [0x01], [0xAA]),
+        ]);
+
+        s.delay_ms(1);
+        s.spi_write(&[0x01], &[0xAA]).unwrap();
+        s.delay_ms(10);
+        s.delay_ms(5);
+
+        m.finalise();
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_trailing_delays_disabled() {
+        let mut m = Mock::new();
+        let mut s = m.spi();
+
+        m.expect(vec![
+            MockTransaction::spi_write(&s, [0x01], [0xAA]),
+        ]);
+
+        s.spi_write(&[0x01], &[0xAA]).unwrap();
+        s.delay_ms(10);
+
+        m.finalise();
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_trailing_delays_unmatched() {
+        let mut m = Mock::new();
+        let mut s = m.spi();
+        m.allow_trailing_delays(true);
+
+        m.expect(vec![
+            MockTransaction::spi_write(&s, [0x01], [0xAA]),
+        ]);
+
+        // Only trailing delays are relaxed
+        s.delay_ms(10);
+        s.spi_write(&[0x01], &[0xAA]).unwrap();
+
+        m.finalise();
+    }
+
+    #[test]
+    fn test_deadline() {
+        let mut m = Mock::new();
+        let mut d = m.delay();
+        m.set_deadline_us(10_000);
+
+        d.delay_ms(4);
+        d.delay_ms(6);
+
+        assert_eq!(m.elapsed_us(), 10_000);
+    }
+
+    #[test]
+    #[should_panic(expected = "deadline of 10000 us exceeded (11000 us elapsed) at index 2")]
+    fn test_deadline_exceeded() {
+        let mut m = Mock::new();
+        let mut d = m.delay();
+        m.set_deadline_us(10_000);
+
+        d.delay_ms(4);
+        d.delay_ms(6);
+        d.delay_ms(1);
+
+        unreachable!();
+    }
+
+    #[test]
+    fn test_elapsed_exec_delay() {
+        let mut m = Mock::new();
+        m.skip_finalise();
+        let mut s = m.spi();
+
+        s.spi_exec(&mut [Transaction::Write(&[0x01]), Transaction::Delay(5), Transaction::Write(&[0x02])]).unwrap();
+
+        assert_eq!(m.elapsed_us(), 5000);
+    }
+
+    #[test]
+    fn test_elapsed_between() {
+        let mut m = Mock::new();
+        let mut d = m.delay();
+
+        m.expect(vec![
+            MockTransaction::delay_ms(5),
+            MockTransaction::user("start"),
+            MockTransaction::delay_ms(4),
+            MockTransaction::delay_ms(6),
+            MockTransaction::user("end"),
+        ]);
+
+        d.delay_ms(5);
+        m.mark("start");
+        d.delay_ms(4);
+        d.delay_ms(6);
+        m.mark("end");
+
+        m.finalise();
+        m.assert_elapsed_between("start", "end", 8..=12);
+    }
+
+    #[test]
+    #[should_panic(expected = "10 ms elapsed between markers 'start' and 'end' (expected 2..5)")]
+    fn test_elapsed_between_range() {
+        let mut m = Mock::new();
+        let mut d = m.delay();
+
+        m.mark("start");
+        d.delay_ms(10);
+        m.mark("end");
+
+        m.assert_elapsed_between("start", "end", 2..5);
+    }
+
+    #[test]
+    #[should_panic(expected = "marker 'end' not found after 'start'")]
+    fn test_elapsed_between_missing() {
+        let mut m = Mock::new();
+        let mut d = m.delay();
+
+        m.mark("end");
+        m.mark("start");
+        d.delay_ms(10);
+
+        m.assert_elapsed_between("start", "end", 8..=12);
+    }
+
+    #[test]
+    #[cfg(feature = "async")]
+    fn test_async_transactional() {
+        use crate::AsyncTransactional;
+
+        let mut m = Mock::new();
+        let mut s = m.spi();
+
+        m.expect(vec![
+            MockTransaction::spi_write(&s, [0x01], [0xAA]),
+            MockTransaction::spi_read(&s, [0x02], [0xBB, 0xCC]),
+            MockTransaction::spi_exec(&s, [MockExec::write([0x03]), MockExec::read([0xDD])]),
+        ]);
+
+        let (mut r, mut x) = ([0u8; 2], [0u8; 1]);
+        futures::executor::block_on(async {
+            AsyncTransactional::spi_write(&mut s, &[0x01], &[0xAA]).await?;
+            AsyncTransactional::spi_read(&mut s, &[0x02], &mut r).await?;
+            AsyncTransactional::spi_exec(&mut s, &mut [Transaction::Write(&[0x03]), Transaction::Read(&mut x)]).await
+        }).unwrap();
+
+        assert_eq!(r, [0xBB, 0xCC]);
+        assert_eq!(x, [0xDD]);
+
+        m.finalise();
+    }
+
+    #[test]
+    #[cfg(feature = "async")]
+    fn test_async_delay() {
+        use embedded_hal_async::delay::DelayNs;
+
+        let mut m = Mock::new();
+        let mut s = m.spi();
+        let mut d = m.delay();
+
+        m.expect(vec![
+            MockTransaction::delay_ms(5),
+            MockTransaction::delay_us(20),
+            MockTransaction::delay_us(2),
+        ]);
+
+        futures::executor::block_on(async {
+            DelayNs::delay_ms(&mut s, 5).await;
+            DelayNs::delay_us(&mut d, 20).await;
+            DelayNs::delay_ns(&mut d, 1500).await;
+        });
+
+        m.finalise();
+        assert_eq!(m.elapsed_us(), 5_022);
+    }
+
+    #[test]
+    fn test_lenient_polls() {
+        let mut m = Mock::new();
+        m.skip_finalise();
+        let mut s = m.spi();
+
+        m.expect(vec![
+            MockTransaction::busy(&s, PinState::High),
+        ]);
+
+        assert_eq!(s.get_busy().unwrap(), PinState::High);
+        assert_eq!(s.get_busy().unwrap(), PinState::Low);
+    }
+
+    #[test]
+    #[should_panic(expected = "unexpected busy poll #1")]
+    fn test_strict_polls_busy() {
+        let mut m = Mock::new();
+        let mut s = m.spi();
+        m.strict_polls(true);
+
+        m.expect(vec![
+            MockTransaction::busy(&s, PinState::High),
+        ]);
+
+        assert_eq!(s.get_busy().unwrap(), PinState::High);
+        s.get_busy().unwrap();
+    }
+
+    #[test]
+    #[should_panic(expected = "unexpected ready poll #0")]
+    fn test_strict_polls_ready() {
+        let mut m = Mock::new();
+        let mut s = m.spi();
+        m.strict_polls(true);
+
+        s.get_ready().unwrap();
+    }
+
+    #[test]
+    fn test_actual_with_cs() {
+        use crate::wrapper::Wrapper;
+
+        let mut m = Mock::new();
+        let s = m.spi();
+        let cs_a = m.pin();
+        let cs_b = m.pin();
+        let d = m.delay();
+
+        let (id, a, b) = (s.id, cs_a.id, cs_b.id);
+
+        let mut wa = Wrapper::new(s.clone(), cs_a, (), (), (), d.clone());
+        let mut wb = Wrapper::new(s, cs_b, (), (), (), d);
+
+        wa.spi_write(&[0x01], &[0xAA]).unwrap();
+        wb.spi_write(&[0x02], &[0xBB]).unwrap();
+
+        assert_eq!(m.actual_with_cs(&[a, b]), vec![
+            (MockTransaction::SetLow(a), Some(a)),
+            (MockTransaction::Write(id, vec![0x01]), Some(a)),
+            (MockTransaction::Write(id, vec![0xAA]), Some(a)),
+            (MockTransaction::SetHigh(a), None),
+            (MockTransaction::SetLow(b), Some(b)),
+            (MockTransaction::Write(id, vec![0x02]), Some(b)),
+            (MockTransaction::Write(id, vec![0xBB]), Some(b)),
+            (MockTransaction::SetHigh(b), None),
+        ]);
+    }
+
+    #[test]
+    fn test_final_pin_state() {
+        use embedded_hal::digital::v2::OutputPin;
+
+        let mut m = Mock::new();
+        let mut s = m.spi();
+        let mut cs = m.pin();
+        let p = m.pin();
+
+        s.set_reset(PinState::Low).unwrap();
+        cs.set_low().unwrap();
+        s.set_reset(PinState::High).unwrap();
+        cs.set_high().unwrap();
+        cs.set_low().unwrap();
+        cs.set_high().unwrap();
+
+        assert_eq!(m.final_pin_state(cs.id), Some(PinState::High));
+        assert_eq!(m.final_pin_state(s.id), Some(PinState::High));
+        assert_eq!(m.final_pin_state(p.id), None);
+    }
+
+    #[test]
+    fn test_alternating() {
+        use embedded_hal::digital::v2::OutputPin;
+
+        let mut m = Mock::new();
+        let mut s = m.spi();
+        let mut p = m.pin();
+
+        let mut d = [0u8; 2];
+        s.spi_write(&[0x01], &[]).unwrap();
+        p.set_high().unwrap();
+        s.delay_ms(1);
+        s.spi_read(&[], &mut d).unwrap();
+        s.spi_exec(&mut [Transaction::Write(&[0x02]), Transaction::Read(&mut d)]).unwrap();
+
+        m.assert_alternating(s.id);
+    }
 
-    /// Write data to a specified register address
-    fn spi_write(&mut self, prefix: &[u8], data: &[u8]) -> Result<(), Self::Error> {
-        let mut i = self.inner.lock().unwrap();
-        
-        // Save actual call
-        i.actual.push(MockTransaction::SpiWrite(self.id, prefix.into(), data.into()));
+    #[test]
+    #[should_panic(expected = "consecutive writes on peripheral 0 at index 4")]
+    fn test_alternating_consecutive_writes() {
+        let mut m = Mock::new();
+        let mut s = m.spi();
 
-        // Update expectation index
-        i.index += 1;
+        let mut d = [0u8; 2];
+        s.spi_write(&[0x01], &[]).unwrap();
+        s.spi_read(&[], &mut d).unwrap();
+        s.spi_write(&[0x02], &[]).unwrap();
+        s.delay_ms(1);
+        s.spi_write(&[0x03], &[]).unwrap();
 
-        Ok(())
+        m.assert_alternating(s.id);
     }
 
-    /// Execute the provided transactions
-    fn spi_exec(&mut self, transactions: &mut [Transaction]) -> Result<(), Self::Error> {
-        let mut i = self.inner.lock().unwrap();
-        let index = i.index;
+    #[test]
+    fn test_build_expectations() {
+        use embedded_hal::digital::v2::OutputPin;
 
-        // Save actual calls
-        let t: Vec<MockExec> = transactions.iter().map(|ref v| MockExec::from(*v) ).collect();
-        i.actual.push(MockTransaction::SpiExec(self.id, t));
+        let mut m = Mock::new();
+        let mut s = m.spi();
+        let mut p = m.pin();
 
-        // Load expected reads
-        if let MockTransaction::SpiExec(_id, e) = &i.expected[index] {
-            for i in 0..transactions.len() {
-                let t = &mut transactions[i];
-                let x = e.get(i);
-
-                match (t, x) {
-                    (Transaction::Read(ref mut v), Some(MockExec::SpiRead(d))) => v.copy_from_slice(&d),
-                    _ => ()
-                }
-            }
-        }
-        
-        // Update expectation index
-        i.index += 1;
+        m.build()
+            .set_low(&p)
+            .spi_write(&s, [0x0A], [0xDE, 0xAD])
+            .delay_ms(5)
+            .busy(&s, PinState::Low)
+            .spi_read(&s, [0x0B], [0xBE])
+            .finish();
 
-        Ok(())
+        assert_eq!(m.expected(), vec![
+            MockTransaction::set_low(&p),
+            MockTransaction::spi_write(&s, [0x0A], [0xDE, 0xAD]),
+            MockTransaction::delay_ms(5),
+            MockTransaction::busy(&s, PinState::Low),
+            MockTransaction::spi_read(&s, [0x0B], [0xBE]),
+        ]);
+
+        let mut d = [0u8; 1];
+        p.set_low().unwrap();
+        s.spi_write(&[0x0A], &[0xDE, 0xAD]).unwrap();
+        s.delay_ms(5);
+        assert_eq!(s.get_busy().unwrap(), PinState::Low);
+        s.spi_read(&[0x0B], &mut d).unwrap();
+
+        m.finalise();
+        assert_eq!(d, [0xBE]);
     }
-}
 
-impl Busy for Spi {
-    type Error = Error<(), ()>;
-    /// Check peripheral busy status
-    fn get_busy(&mut self) -> Result<PinState, Self::Error> {
-        let mut i = self.inner.lock().unwrap();
-        let index = i.index;
+    #[test]
+    fn test_build_expectations_u16() {
+        use embedded_hal::blocking::spi::Transfer;
+        use embedded_hal::digital::v2::OutputPin;
 
-        let state = match &i.expected.get(index) {
-            Some(MockTransaction::Busy(_id, state)) => state.clone(),
-            _ => PinState::Low,
-        };
+        let mut m = Mock::<u16>::default();
+        let mut s = m.spi();
+        let mut p = m.pin();
 
-        i.actual.push(MockTransaction::Busy(self.id, state.clone()));
+        m.build()
+            .set_low(&p)
+            .transfer(&s, [0xAA55], [0x1234])
+            .set_high(&p)
+            .finish();
 
-        i.index += 1;
+        p.set_low().unwrap();
+        assert_eq!(s.transfer(&mut [0xAA55]).unwrap(), &[0x1234]);
+        p.set_high().unwrap();
 
-        Ok(state)
+        m.finalise();
     }
-}
 
-impl Ready for Spi {
-    type Error = Error<(), ()>;
-    /// Check peripheral ready status
-    fn get_ready(&mut self) -> Result<PinState, Self::Error> {
-        let mut i = self.inner.lock().unwrap();
-        let index = i.index;
+    #[test]
+    fn test_device() {
+        use embedded_hal::digital::v2::{InputPin, OutputPin};
 
-        let state = match &i.expected.get(index) {
-            Some(MockTransaction::Ready(_id, state)) => state.clone(),
-            _ => PinState::Low,
-        };
+        let mut m = Mock::new();
+        let _p = m.pin();
 
-        i.actual.push(MockTransaction::Ready(self.id, state.clone()));
+        let d = m.device().reset().busy().build();
+        assert_eq!(d.spi.id(), 1);
+        assert!(d.cs.is_none());
+        assert!(d.ready.is_none());
 
-        i.index += 1;
+        let busy = d.busy.unwrap();
+        let mut reset = d.reset.unwrap();
+        assert_eq!(busy.id(), 2);
+        assert_eq!(reset.id(), 3);
+        assert_eq!(m.name(busy.id()), Some("busy2".to_string()));
+        assert_eq!(m.name(reset.id()), Some("reset3".to_string()));
 
-        Ok(state)
+        m.expect(vec![
+            MockTransaction::set_low(&reset),
+            MockTransaction::is_high(&busy, false),
+        ]);
+
+        reset.set_low().unwrap();
+        assert!(!busy.is_high().unwrap());
+
+        assert_eq!(m.final_pin_state(reset.id()), Some(PinState::Low));
+        m.finalise();
     }
-}
 
-impl Reset for Spi {
-    type Error = Error<(), ()>;
-    /// Check peripheral ready status
-    fn set_reset(&mut self, state: PinState) -> Result<(), Self::Error> {
-        let mut i = self.inner.lock().unwrap();
+    #[test]
+    fn test_on_read() {
+        use embedded_hal::blocking::spi::Transfer;
 
-        i.actual.push(MockTransaction::Reset(self.id, state));
+        let mut m = Mock::new();
+        let mut s = m.spi();
 
-        i.index += 1;
+        // Simulate a register file, with writes tracked via `on_each`
+        let regs = Arc::new(Mutex::new(HashMap::new()));
+        let r = regs.clone();
+        m.on_each(move |t| if let MockTransaction::SpiWrite(_, p, d) = t {
+            r.lock().unwrap().insert(p[0] & 0x7F, d.clone());
+        });
+        let r = regs.clone();
+        m.on_read(move |p| match p {
+            [addr] if addr & 0x80 != 0 => r.lock().unwrap().get(&(addr & 0x7F)).cloned().unwrap_or(vec![0x00]),
+            d => d.iter().map(|b| !b ).collect(),
+        });
+
+        // Expected data takes precedence over the callback
+        m.expect(vec![MockTransaction::spi_read(&s, [0x90], [0xAA])]);
+
+        let mut d = [0u8; 1];
+        s.spi_read(&[0x90], &mut d).unwrap();
+        assert_eq!(d, [0xAA]);
+        m.finalise();
 
-        Ok(())
+        // Otherwise reads and transfers are computed from the outgoing data
+        m.reset();
+        s.spi_write(&[0x10], &[0x5A]).unwrap();
+        s.spi_read(&[0x90], &mut d).unwrap();
+        assert_eq!(d, [0x5A]);
+        s.spi_read(&[0x91], &mut d).unwrap();
+        assert_eq!(d, [0x00]);
+        assert_eq!(s.transfer(&mut [0x0F, 0xF0]).unwrap(), &[0xF0, 0x0F]);
+
+        assert_eq!(m.last(), Some(MockTransaction::transfer(&s, [0x0F, 0xF0], [0xF0, 0x0F])));
     }
-}
 
-impl DelayMs<u32> for Spi {
-    fn delay_ms(&mut self, t: u32) {
-        let mut i = self.inner.lock().unwrap();
+    #[test]
+    #[should_panic(expected = "on_read returned 1 words but driver provided a 2-word buffer")]
+    fn test_on_read_length_mismatch() {
+        let mut m = Mock::new();
+        let mut s = m.spi();
 
-        // Save actual call
-        i.actual.push(MockTransaction::DelayMs(t));
+        m.on_read(|_| vec![0x00] );
 
-        // Update expectation index
-        i.index += 1;
+        let mut d = [0u8; 2];
+        let _ = s.spi_read(&[0x80], &mut d);
     }
-}
 
+    #[test]
+    fn test_tx_transform() {
+        use embedded_hal::blocking::spi::Write;
 
-impl spi::Transfer<u8> for Spi 
-{
-    type Error = Error<(), ()>;
+        let mut m = Mock::new();
+        let mut s = m.spi();
 
-    fn transfer<'w>(&mut self, data: &'w mut [u8]) -> Result<&'w [u8], Self::Error> {
-        let mut i = self.inner.lock().unwrap();
-        let index = i.index;
+        // Strip 0x7D escape bytes from recorded frames
+        m.set_tx_transform(|wire: &[u8]| wire.iter().cloned().filter(|b| *b != 0x7D).collect() );
 
-        let incoming: Vec<_> = data.into();
+        m.expect(vec![
+            MockTransaction::spi_write(&s, [0x01], [0x7E, 0x02]),
+            MockTransaction::write(&s, [0x7E]),
+        ]);
 
-        // Copy read data from expectation
-        match &i.expected.get(index) {
-            Some(MockTransaction::Transfer(_id, _outgoing, incoming)) => {
-                if incoming.len() == data.len() {
-                    data.copy_from_slice(&incoming);
-                }
-            },
-            _ => (),
-        };
-                       
-        // Save actual call
-        i.actual.push(MockTransaction::Transfer(self.id, incoming, data.into()));
-        
-        // Update expectation index
-        i.index += 1;
+        s.spi_write(&[0x01], &[0x7D, 0x7E, 0x02]).unwrap();
+        s.write(&[0x7D, 0x7E]).unwrap();
 
-        Ok(data)
+        m.finalise();
     }
-}
 
-impl spi::Write<u8> for Spi  
-{
-    type Error = Error<(), ()>;
-    
-    fn write<'w>(&mut self, data: &[u8]) -> Result<(), Self::Error> {
-        let mut i = self.inner.lock().unwrap();
-        
-        // Save actual call
-        i.actual.push(MockTransaction::Write(self.id, data.into()));
+    #[test]
+    fn test_cs_setup_hold() {
+        use embedded_hal::digital::v2::OutputPin;
 
-        // Update expectation index
-        i.index += 1;
+        let mut m = Mock::new();
+        let mut s = m.spi();
+        let mut cs = m.pin();
 
-        Ok(())
-    }
-}
+        cs.set_low().unwrap();
+        s.delay_ms(1);
+        s.spi_write(&[0x01], &[]).unwrap();
+        s.spi_write(&[0x02], &[]).unwrap();
+        s.delay_ms(2);
+        cs.set_high().unwrap();
 
-impl v2::InputPin for Pin {
-    type Error = ();
+        // Empty windows are ignored
+        cs.set_low().unwrap();
+        cs.set_high().unwrap();
 
-    fn is_high(&self) -> Result<bool, Self::Error> {
-        let mut i = self.inner.lock().unwrap();
-        let index = i.index;
+        m.assert_cs_setup_hold(cs.id, s.id, 1, 2);
+    }
 
-        // Fetch expectation if found
-        let v = match &i.expected.get(index) {
-            Some(MockTransaction::IsHigh(_id, v)) => *v,
-            _ => false,
-        };
+    #[test]
+    #[should_panic(expected = "only 0 ms setup after chip select 1 asserted at index 0 (first data at 1, minimum 1 ms)")]
+    fn test_cs_setup_violation() {
+        use embedded_hal::digital::v2::OutputPin;
 
-        // Save actual call
-        i.actual.push(MockTransaction::IsHigh(self.id, v));
+        let mut m = Mock::new();
+        let mut s = m.spi();
+        let mut cs = m.pin();
 
-        // Update expectation index
-        i.index += 1;
+        cs.set_low().unwrap();
+        s.spi_write(&[0x01], &[]).unwrap();
+        s.delay_ms(1);
+        cs.set_high().unwrap();
 
-        Ok(v)
+        m.assert_cs_setup_hold(cs.id, s.id, 1, 1);
     }
 
-    fn is_low(&self) -> Result<bool, Self::Error> {
-        let mut i = self.inner.lock().unwrap();
-        let index = i.index;
-
-        // Fetch expectation if found
-        let v = match &i.expected.get(index) {
-            Some(MockTransaction::IsLow(_id, v)) => *v,
-            _ => false,
-        };
+    #[test]
+    #[should_panic(expected = "only 1 ms hold before chip select 1 released at index 4 (window from 0, minimum 2 ms)")]
+    fn test_cs_hold_violation() {
+        use embedded_hal::digital::v2::OutputPin;
 
-        // Save actual call
-        i.actual.push(MockTransaction::IsLow(self.id, v));
+        let mut m = Mock::new();
+        let mut s = m.spi();
+        let mut cs = m.pin();
 
-        // Update expectation index
-        i.index += 1;
+        cs.set_low().unwrap();
+        s.delay_ms(1);
+        s.spi_write(&[0x01], &[]).unwrap();
+        s.delay_ms(1);
+        cs.set_high().unwrap();
 
-        Ok(v)
+        m.assert_cs_setup_hold(cs.id, s.id, 1, 2);
     }
-}
-
 
-impl v2::OutputPin for Pin {
-    type Error = ();
+    fn golden_file(name: &str, contents: &str) -> String {
+        let p = std::env::temp_dir().join(format!("embedded-spi-{}-{}.txt", name, std::process::id()));
+        std::fs::write(&p, contents).unwrap();
+        p.to_str().unwrap().to_string()
+    }
 
-    fn set_high(&mut self) -> Result<(), Self::Error> {
-        let mut i = self.inner.lock().unwrap();
+    #[test]
+    fn test_golden() {
+        let mut m = Mock::new();
+        let mut s = m.spi();
 
-        // Save actual call
-        i.actual.push(MockTransaction::SetHigh(self.id));
+        let path = golden_file("golden", "
+            # Device init
+            w 0: 01 00
+            delay_ms 5
+        ");
 
-        // Update expectation index
-        i.index += 1;
+        s.spi_write(&[0x01], &[0x00]).unwrap();
+        s.delay_ms(5);
 
-        Ok(())
+        m.assert_matches_golden(&path);
     }
 
-    fn set_low(&mut self) -> Result<(), Self::Error> {
-        let mut i = self.inner.lock().unwrap();
+    #[test]
+    #[should_panic(expected = "@@ golden line 3 @@\n-    3  w 0: 01 00\n+       w 0: 01 FF")]
+    fn test_golden_mismatch() {
+        let mut m = Mock::new();
+        let mut s = m.spi();
 
-        // Save actual call
-        i.actual.push(MockTransaction::SetLow(self.id));
+        let path = golden_file("golden-mismatch", "
+            # Device init
+            w 0: 01 00
+            delay_ms 5
+        ");
 
-        // Update expectation index
-        i.index += 1;
+        s.spi_write(&[0x01], &[0xFF]).unwrap();
+        s.delay_ms(5);
 
-        Ok(())
+        m.assert_matches_golden(&path);
     }
-}
 
-impl DelayMs<u32> for Delay {
-    fn delay_ms(&mut self, t: u32) {
-        let mut i = self.inner.lock().unwrap();
+    #[test]
+    fn test_bless() {
+        let mut m = Mock::new();
+        let mut s = m.spi();
 
-        // Save actual call
-        i.actual.push(MockTransaction::DelayMs(t));
+        let path = golden_file("bless", "");
 
-        // Update expectation index
-        i.index += 1;
-    }
-}
+        s.spi_write(&[0x01], &[0x00]).unwrap();
+        s.delay_ms(5);
 
-#[cfg(test)]
-mod test {
-    use std::*;
-    use std::{vec, panic};
+        m.bless(&path);
+        m.check_against(&path);
 
-    use super::*;
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "w 0: 01 00\ndelay_ms 5\n");
+    }
 
     #[test]
-    fn test_transactional_read() {
+    #[should_panic(expected = "actual transactions do not match golden file")]
+    fn test_bless_divergence() {
         let mut m = Mock::new();
         let mut s = m.spi();
 
-        let prefix = vec![0xFF];
-        let data = vec![0xAA, 0xBB];
+        let path = golden_file("bless-divergence", "");
 
-        m.expect(vec![MockTransaction::spi_read(&s, prefix.clone(), data.clone())]);
+        s.spi_write(&[0x01], &[0x00]).unwrap();
+        m.bless(&path);
 
-        let mut d = [0u8; 2];
-        s.spi_read(&prefix, &mut d).expect("read failure");
+        s.spi_write(&[0x02], &[0x00]).unwrap();
+        m.check_against(&path);
+    }
+
+    #[test]
+    fn test_toggle_pin() {
+        use embedded_hal::digital::v2::{OutputPin, ToggleableOutputPin};
+
+        assert_eq!(PinState::Low.toggle(), PinState::High);
+        assert_eq!(PinState::High.toggle(), PinState::Low);
+
+        let mut m = Mock::new();
+        let mut p = m.pin();
+
+        m.expect(vec![
+            MockTransaction::set_high(&p),
+            MockTransaction::set_low(&p),
+            MockTransaction::set_high(&p),
+        ]);
+
+        p.set_high().unwrap();
+        p.toggle().unwrap();
+        assert_eq!(m.final_pin_state(p.id), Some(PinState::Low));
+        p.toggle().unwrap();
+        assert_eq!(m.final_pin_state(p.id), Some(PinState::High));
 
         m.finalise();
-        assert_eq!(&data, &d);
     }
 
     #[test]
-    #[should_panic]
-    fn test_transactional_read_expect_write() {
+    fn test_readback() {
         let mut m = Mock::new();
         let mut s = m.spi();
 
-        let prefix = vec![0xFF];
-        let data = vec![0xAA, 0xBB];
-
-        m.expect(vec![MockTransaction::spi_write(&s, prefix.clone(), data.clone())]);
+        let data = [0x11, 0x22, 0x33];
+        m.expect(MockTransaction::readback(&s, [0x02, 0x10], [0x03, 0x10], data));
 
-        let mut d = [0u8; 2];
-        s.spi_read(&prefix, &mut d).expect("read failure");
+        s.spi_write(&[0x02, 0x10], &data).unwrap();
+        let mut d = [0u8; 3];
+        s.spi_read(&[0x03, 0x10], &mut d).unwrap();
 
         m.finalise();
-        assert_eq!(&data, &d);
+        m.assert_readback_matches(s.id, s.id);
     }
 
     #[test]
-    fn test_transactional_write() {
+    #[should_panic(expected = "readback mismatch")]
+    fn test_readback_mismatch() {
         let mut m = Mock::new();
         let mut s = m.spi();
 
-        let prefix = vec![0xFF];
-        let data = vec![0xAA, 0xBB];
+        m.expect(vec![
+            MockTransaction::spi_write(&s, [0x02, 0x10], [0x11, 0x22]),
+            MockTransaction::spi_write(&s, [0x02, 0x20], [0x33, 0x44]),
+            MockTransaction::spi_read(&s, [0x03, 0x10], [0x33, 0x44]),
+        ]);
 
-        m.expect(vec![MockTransaction::spi_write(&s, prefix.clone(), data.clone())]);
+        s.spi_write(&[0x02, 0x10], &[0x11, 0x22]).unwrap();
+        s.spi_write(&[0x02, 0x20], &[0x33, 0x44]).unwrap();
+        let mut d = [0u8; 2];
+        s.spi_read(&[0x03, 0x10], &mut d).unwrap();
 
-        s.spi_write(&prefix, &data).expect("write failure");
+        m.assert_readback_matches(s.id, s.id);
+    }
 
-        m.finalise();
+    #[test]
+    #[cfg(feature = "fuzz")]
+    fn test_read_seed() {
+        let run = |seed| {
+            let mut m = Mock::with_read_seed(seed);
+            let mut s = m.spi();
+
+            let mut a = [0u8; 8];
+            let mut b = [0u8; 8];
+            s.spi_read(&[0x01], &mut a).unwrap();
+            s.spi_read(&[0x02], &mut b).unwrap();
+
+            // Generated data is recorded
+            assert_eq!(m.per_peripheral()[&s.id], vec![
+                MockTransaction::spi_read(&s, [0x01], a),
+                MockTransaction::spi_read(&s, [0x02], b),
+            ]);
+
+            (a, b)
+        };
+
+        let (a, b) = run(0x1234);
+        assert_ne!(a, b);
+        assert_eq!(run(0x1234), (a, b));
+        assert_ne!(run(0x4321), (a, b));
     }
 
     #[test]
-    #[should_panic]
-    fn test_transactional_write_expect_read() {
-        let mut m = Mock::new();
+    #[cfg(feature = "fuzz")]
+    fn test_read_seed_expected() {
+        let mut m = Mock::with_read_seed(0x1234);
         let mut s = m.spi();
 
-        let prefix = vec![0xFF];
-        let data = vec![0xAA, 0xBB];
-
-        m.expect(vec![MockTransaction::spi_read(&s, prefix.clone(), data.clone())]);
+        m.expect(vec![
+            MockTransaction::spi_read(&s, vec![0x01], vec![0xAA, 0xBB]),
+        ]);
 
-        s.spi_write(&prefix, &data).expect("write failure");
+        let mut d = [0u8; 2];
+        s.spi_read(&[0x01], &mut d).unwrap();
 
         m.finalise();
+        assert_eq!(d, [0xAA, 0xBB]);
     }
 
     #[test]
-    fn test_standard_write() {
+    fn test_on_each() {
         use embedded_hal::blocking::spi::Write;
 
         let mut m = Mock::new();
         let mut s = m.spi();
 
-        let data = vec![0xAA, 0xBB];
+        let writes = Arc::new(Mutex::new(0));
+        let w = writes.clone();
+        m.on_each(move |t| {
+            if let MockTransaction::Write(..) = t {
+                *w.lock().unwrap() += 1;
+            }
+        });
 
-        m.expect(vec![MockTransaction::write(&s, data.clone())]);
+        m.expect(vec![
+            MockTransaction::write(&s, vec![0xAA]),
+            MockTransaction::delay_ms(10),
+            MockTransaction::write(&s, vec![0xBB]),
+        ]);
 
-        s.write(&data).expect("write failure");
+        s.write(&[0xAA]).unwrap();
+        assert_eq!(*writes.lock().unwrap(), 1);
+
+        s.delay_ms(10);
+        s.write(&[0xBB]).unwrap();
+        assert_eq!(*writes.lock().unwrap(), 2);
 
         m.finalise();
     }
 
     #[test]
-    fn test_standard_transfer() {
-        use embedded_hal::blocking::spi::Transfer;
+    #[should_panic]
+    fn test_on_each_panic() {
+        use embedded_hal::blocking::spi::Write;
 
         let mut m = Mock::new();
         let mut s = m.spi();
 
-        let outgoing = vec![0xAA, 0xBB];
-        let incoming = vec![0xCC, 0xDD];
-
-        m.expect(vec![MockTransaction::transfer(&s, outgoing.clone(), incoming.clone())]);
-
-        let mut d = outgoing.clone();
-        s.transfer(&mut d).expect("read failure");
+        let mut writes = 0;
+        m.on_each(move |_t| {
+            writes += 1;
+            assert!(writes <= 2, "runaway writes");
+        });
 
-        m.finalise();
-        assert_eq!(&incoming, &d);
+        for _i in 0..3 {
+            s.write(&[0xAA]).unwrap();
+        }
     }
 
-     #[test]
-    fn test_pins() {
-        use embedded_hal::digital::v2::{InputPin, OutputPin};
+    #[test]
+    #[should_panic(expected = "recorded 2 actuals but index is 1")]
+    fn test_index_desync() {
+        use embedded_hal::blocking::spi::Write;
 
         let mut m = Mock::new();
-        let mut p = m.pin();
+        let mut s = m.spi();
 
         m.expect(vec![
-            MockTransaction::is_high(&p, true),
-            MockTransaction::is_low(&p, false),
-            MockTransaction::set_high(&p),
-            MockTransaction::set_low(&p),
+            MockTransaction::write(&s, [0xAA]),
+            MockTransaction::write(&s, [0xBB]),
         ]);
 
-        assert_eq!(true, p.is_high().unwrap());
-        assert_eq!(false, p.is_low().unwrap());
+        m.on_each(|t| {
+            assert_ne!(t, &MockTransaction::Write(0, vec![0xBB]), "driver failure");
+        });
 
-        p.set_high().unwrap();
-        p.set_low().unwrap();
+        s.write(&[0xAA]).unwrap();
+
+        // Panic part way through recording a transaction
+        let mut s1 = s.clone();
+        assert!(panic::catch_unwind(move || s1.write(&[0xBB]) ).is_err());
 
         m.finalise();
     }
@@ -0,0 +1,161 @@
+//! Rust source generation for recorded transactions
+//! This maps each recorded `MockTransaction` back to its constructor call, so a permissive
+//! run of an existing driver can be turned into a starting expectation set for a test.
+//!
+//! Handles are referred to by Id, with SPI peripherals named `s<id>` and pins `p<id>`.
+
+use std::format;
+use std::vec::Vec;
+use std::string::{String, ToString};
+
+use crate::{PinState, Lanes, Error};
+use super::{MockTransaction, MockExec, MockOp, MockError};
+
+/// Format a sequence of transactions as a `vec![...]` expression of constructor calls
+pub(crate) fn emit_expectations(txns: &[MockTransaction]) -> String {
+    let mut s = "vec![\n".to_string();
+
+    for t in txns {
+        s.push_str(&format!("    {},\n", emit_transaction(t)));
+    }
+
+    s.push(']');
+    s
+}
+
+fn emit_transaction(t: &MockTransaction) -> String {
+    use MockTransaction::*;
+
+    match t {
+        None => "MockTransaction::None".to_string(),
+        SpiWrite(id, prefix, data) => format!("MockTransaction::spi_write(&s{}, {}, {})", id, words(prefix), words(data)),
+        SpiWriteAny(id, prefix) => format!("MockTransaction::spi_write_any(&s{}, {})", id, words(prefix)),
+        SpiWriteStatus(id, prefix, data, status) => {
+            format!("MockTransaction::spi_write_status(&s{}, {}, {}, {})", id, words(prefix), words(data), words(status))
+        },
+        SpiRead(id, prefix, data) => format!("MockTransaction::spi_read(&s{}, {}, {})", id, words(prefix), words(data)),
+        SpiReadDummy(id, prefix, dummy, data) => {
+            format!("MockTransaction::spi_read_with_dummy(&s{}, {}, {}, {})", id, words(prefix), dummy, words(data))
+        },
+        SpiExec(id, ops) => {
+            let ops: Vec<_> = ops.iter().map(|o| match o {
+                MockExec::SpiWrite(d) => format!("MockExec::SpiWrite(vec!{})", words(d)),
+                MockExec::SpiRead(d) => format!("MockExec::SpiRead(vec!{})", words(d)),
+                MockExec::Delay(ms) => format!("MockExec::Delay({})", ms),
+            }).collect();
+            format!("MockTransaction::spi_exec(&s{}, [{}])", id, ops.join(", "))
+        },
+        SpiDevice(id, ops) => {
+            let ops: Vec<_> = ops.iter().map(|o| match o {
+                MockOp::Read(d) => format!("MockOp::Read(vec!{})", words(d)),
+                MockOp::Write(d) => format!("MockOp::Write(vec!{})", words(d)),
+                MockOp::Transfer(w, r) => format!("MockOp::Transfer(vec!{}, vec!{})", words(w), words(r)),
+                MockOp::TransferInPlace(w, r) => format!("MockOp::TransferInPlace(vec!{}, vec!{})", words(w), words(r)),
+                MockOp::DelayNs(ns) => format!("MockOp::DelayNs({})", ns),
+            }).collect();
+            format!("MockTransaction::spi_device(&s{}, [{}])", id, ops.join(", "))
+        },
+        BeginTransaction(id) => format!("MockTransaction::begin(&s{})", id),
+        EndTransaction(id) => format!("MockTransaction::end(&s{})", id),
+        SetLanes(id, lanes) => format!("MockTransaction::set_lanes(&s{}, Lanes::{})", id, match lanes {
+            Lanes::Single => "Single",
+            Lanes::Dual => "Dual",
+            Lanes::Quad => "Quad",
+        }),
+        Busy(id, state) => format!("MockTransaction::busy(&s{}, {})", id, pin_state(state)),
+        Ready(id, state) => format!("MockTransaction::ready(&s{}, {})", id, pin_state(state)),
+        Reset(id, state) => format!("MockTransaction::reset(&s{}, {})", id, pin_state(state)),
+        Write(id, data) => format!("MockTransaction::write(&s{}, {})", id, words(data)),
+        WriteAny(id) => format!("MockTransaction::write_any(&s{})", id),
+        Transfer(id, outgoing, incoming) => {
+            format!("MockTransaction::transfer(&s{}, {}, {})", id, words(outgoing), words(incoming))
+        },
+        TransferTx(id, outgoing) => format!("MockTransaction::transfer_tx_only(&s{}, {})", id, words(outgoing)),
+        TransferAbort(id, outgoing, partial, e) => {
+            format!("MockTransaction::transfer_abort(&s{}, {}, {}, {})", id, words(outgoing), words(partial), error(e))
+        },
+        WriteRead(id, outgoing, incoming) => {
+            format!("MockTransaction::write_read(&s{}, {}, {})", id, words(outgoing), words(incoming))
+        },
+        IsHigh(id, v) => format!("MockTransaction::is_high(&p{}, {})", id, v),
+        IsLow(id, v) => format!("MockTransaction::is_low(&p{}, {})", id, v),
+        SetHigh(id) => format!("MockTransaction::set_high(&p{})", id),
+        SetLow(id) => format!("MockTransaction::set_low(&p{})", id),
+        DelayMs(v) => format!("MockTransaction::delay_ms({})", v),
+        DelayUs(v) => format!("MockTransaction::delay_us({})", v),
+        DelayMsAtLeast(v) => format!("MockTransaction::delay_ms_at_least({})", v),
+        DelayMsAtMost(v) => format!("MockTransaction::delay_ms_at_most({})", v),
+        DelayMsRange(min, max) => format!("MockTransaction::delay_ms_range({}, {})", min, max),
+        // Injections may target either SPI peripherals or pins, so are emitted by Id
+        Inject(id, Option::None, e) => format!("MockTransaction::Inject({}, None, {})", id, failure(e)),
+        Inject(_, Some(call), e) => format!("MockTransaction::fail({}, {})", emit_transaction(call), failure(e)),
+        User(name) => format!("MockTransaction::user({:?})", name),
+    }
+}
+
+fn words(d: &[u8]) -> String {
+    let w: Vec<_> = d.iter().map(|b| format!("0x{:02X}", b) ).collect();
+    format!("[{}]", w.join(", "))
+}
+
+fn pin_state(s: &PinState) -> &'static str {
+    match s {
+        PinState::High => "PinState::High",
+        PinState::Low => "PinState::Low",
+    }
+}
+
+fn failure(e: &Error<MockError, MockError>) -> String {
+    match e {
+        Error::Spi(e) => format!("Error::Spi({})", error(e)),
+        Error::Pin(e) => format!("Error::Pin({})", error(e)),
+        Error::Aborted => "Error::Aborted".to_string(),
+        Error::UnexpectedId(i) => format!("Error::UnexpectedId({})", i),
+    }
+}
+
+fn error(e: &MockError) -> &'static str {
+    match e {
+        MockError::Bus => "MockError::Bus",
+        MockError::Gpio => "MockError::Gpio",
+        MockError::Timeout => "MockError::Timeout",
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use std::vec;
+
+    #[test]
+    fn test_emit_expectations() {
+        let txns = vec![
+            MockTransaction::BeginTransaction(0),
+            MockTransaction::SpiWrite(0, vec![0x0A], vec![0xDE, 0xAD]),
+            MockTransaction::SpiRead(0, vec![], vec![0xBE]),
+            MockTransaction::SpiExec(0, vec![MockExec::SpiWrite(vec![0x01]), MockExec::SpiRead(vec![0x02])]),
+            MockTransaction::EndTransaction(0),
+            MockTransaction::Busy(0, PinState::Low),
+            MockTransaction::SetHigh(1),
+            MockTransaction::DelayMs(10),
+            MockTransaction::Inject(1, Option::None, Error::Pin(MockError::Gpio)),
+            MockTransaction::fail(MockTransaction::Write(0, vec![0xAA]), Error::Aborted),
+            MockTransaction::User("done".to_string()),
+        ];
+
+        assert_eq!(emit_expectations(&txns), "vec![
+    MockTransaction::begin(&s0),
+    MockTransaction::spi_write(&s0, [0x0A], [0xDE, 0xAD]),
+    MockTransaction::spi_read(&s0, [], [0xBE]),
+    MockTransaction::spi_exec(&s0, [MockExec::SpiWrite(vec![0x01]), MockExec::SpiRead(vec![0x02])]),
+    MockTransaction::end(&s0),
+    MockTransaction::busy(&s0, PinState::Low),
+    MockTransaction::set_high(&p1),
+    MockTransaction::delay_ms(10),
+    MockTransaction::Inject(1, None, Error::Pin(MockError::Gpio)),
+    MockTransaction::fail(MockTransaction::write(&s0, [0xAA]), Error::Aborted),
+    MockTransaction::user(\"done\"),
+]");
+    }
+}
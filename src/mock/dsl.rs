@@ -0,0 +1,735 @@
+//! Text format for mock expectations
+//! This provides a simple line-based format for describing sequences of `MockTransaction`s,
+//! allowing long hardware sequences to be kept in readable fixture files.
+//!
+//! Each line describes a single transaction, with `#` at the start of a line or following
+//! whitespace starting a comment (so marker names may contain `#`).
+//! Buffers are hex encoded, with `-` used for an empty prefix.
+//!
+//! ```text
+//! w 0: 0A DEAD          # spi_write(prefix = [0x0A], data = [0xDE, 0xAD]) on peripheral 0
+//! w_any 0: 0A           # spi_write(prefix = [0x0A]) with any data
+//! ws 0: 0A DEAD -> 80   # spi_write_status returning status [0x80]
+//! r 0: 0A -> BEEF       # spi_read(prefix = [0x0A]) returning [0xBE, 0xEF]
+//! rd 0: 0A 2 -> BEEF    # spi_read_with_dummy with 2 dummy bytes
+//! x 0: w 0A, r BEEF     # spi_exec with a write then a read
+//! x 0: w 0A, d 5, r BE  # spi_exec with a 5 ms delay between operations
+//! # SpiDevice transaction with write, read, transfer, in-place transfer and delay (ns) operations
+//! dev 0: w 0A, r BEEF, t 01 -> 02, ti 03 -> 04, d 500
+//! begin 0               # also end
+//! lanes 0 quad          # also single / dual
+//! write 0: AABB         # spi::Write
+//! write_any 0           # spi::Write with any data
+//! transfer 0: AA -> BB  # spi::Transfer
+//! transfer_tx 0: AA     # spi::Transfer, ignoring read-back
+//! # spi::Transfer interrupted with a bus error after a partial read-back
+//! transfer_abort 0: AABB -> 11 bus
+//! # write then read on a single transaction, returning [0xBE, 0xEF]
+//! write_read 0: 0A -> BEEF
+//! busy 0 high           # also ready / reset
+//! is_high 1 true        # also is_low
+//! set_high 1            # also set_low
+//! delay_ms 5            # also delay_us
+//! delay_ms_at_least 10  # any delay_ms of 10 or more, also delay_ms_at_most
+//! delay_ms_range 10 20  # any delay_ms from 10 to 20 inclusive
+//! # error injected into the next operation (also `pin <error>`, `aborted` or `unexpected_id <id>`)
+//! inject 0 spi timeout
+//! # spi_read of prefix 0A failing with an SPI bus error, with the error as for `inject`
+//! fail spi bus = r 0: 0A ->
+//! user start            # named marker
+//! none
+//! ```
+
+use std::{fmt, format, vec};
+use std::vec::Vec;
+use std::string::{String, ToString};
+
+use crate::{PinState, Lanes};
+use super::{Id, MockTransaction, MockExec, MockOp, MockError, wildcard_matches};
+use crate::Error;
+
+/// Error parsing a text expectation
+#[derive(Debug, Clone, PartialEq)]
+pub struct ParseError {
+    /// Line number (1-indexed) at which the error occurred
+    pub line: usize,
+    /// Description of the error
+    pub reason: String,
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "line {}: {}", self.line, self.reason)
+    }
+}
+
+impl std::error::Error for ParseError {}
+
+/// Parse a sequence of expectations from the text format
+pub fn parse_expectations(s: &str) -> Result<Vec<MockTransaction>, ParseError> {
+    Ok(parse_with_lines(s)?.into_iter().map(|(_, t)| t ).collect())
+}
+
+/// Parse a sequence of expectations, with the (1-indexed) source line of each
+pub(crate) fn parse_with_lines(s: &str) -> Result<Vec<(usize, MockTransaction)>, ParseError> {
+    let mut transactions = vec![];
+
+    for (n, l) in s.lines().enumerate() {
+        // Strip comments and whitespace
+        let l = match l.match_indices('#').map(|(i, _)| i ).find(|i| *i == 0 || l[..*i].ends_with(char::is_whitespace) ) {
+            Some(i) => &l[..i],
+            None => l,
+        }.trim();
+
+        if l.is_empty() {
+            continue;
+        }
+
+        let t = parse_line(l).map_err(|reason| ParseError{ line: n + 1, reason })?;
+        transactions.push((n + 1, t));
+    }
+
+    Ok(transactions)
+}
+
+/// Number of unchanged lines shown around each change in a golden diff
+const DIFF_CONTEXT: usize = 2;
+
+/// Build a unified diff between golden (line-numbered) and actual transactions
+/// Lines are prefixed with the golden file line number where available,
+/// returning `None` if the sequences match. Wildcard golden lines match as in `Mock::finalise`.
+pub(crate) fn golden_diff(golden: &[(usize, MockTransaction)], actual: &[MockTransaction]) -> Option<String> {
+    enum Op { Same(usize), Del(usize), Add(usize) }
+
+    let same = |e: &MockTransaction, a: &MockTransaction| e == a || wildcard_matches(e, a);
+
+    let (n, m) = (golden.len(), actual.len());
+
+    // Longest common subsequence table, from the end of each sequence
+    let mut lcs = vec![vec![0usize; m + 1]; n + 1];
+    for i in (0..n).rev() {
+        for j in (0..m).rev() {
+            lcs[i][j] = if same(&golden[i].1, &actual[j]) {
+                lcs[i + 1][j + 1] + 1
+            } else {
+                lcs[i + 1][j].max(lcs[i][j + 1])
+            };
+        }
+    }
+
+    let (mut i, mut j) = (0, 0);
+    let mut ops = vec![];
+    while i < n || j < m {
+        if i < n && j < m && same(&golden[i].1, &actual[j]) {
+            ops.push(Op::Same(i));
+            i += 1;
+            j += 1;
+        } else if i < n && (j == m || lcs[i + 1][j] >= lcs[i][j + 1]) {
+            ops.push(Op::Del(i));
+            i += 1;
+        } else {
+            ops.push(Op::Add(j));
+            j += 1;
+        }
+    }
+
+    if ops.iter().all(|o| matches!(o, Op::Same(..)) ) {
+        return Option::None;
+    }
+
+    // Show changes along with nearby context
+    let shown: Vec<bool> = (0..ops.len()).map(|k| {
+        let lo = k.saturating_sub(DIFF_CONTEXT);
+        let hi = (k + DIFF_CONTEXT + 1).min(ops.len());
+        ops[lo..hi].iter().any(|o| !matches!(o, Op::Same(..)) )
+    }).collect();
+
+    let mut s = String::new();
+    for (k, o) in ops.iter().enumerate() {
+        if !shown[k] {
+            continue;
+        }
+
+        if k == 0 || !shown[k - 1] {
+            let line = ops[k..].iter().find_map(|o| match o {
+                Op::Same(i) | Op::Del(i) => Some(golden[*i].0),
+                Op::Add(_) => Option::None,
+            });
+            match line {
+                Some(l) => s.push_str(&format!("@@ golden line {} @@\n", l)),
+                Option::None => s.push_str("@@ end of golden @@\n"),
+            }
+        }
+
+        match o {
+            Op::Same(i) => s.push_str(&format!("  {:4}  {}\n", golden[*i].0, format_transaction(&golden[*i].1))),
+            Op::Del(i) => s.push_str(&format!("- {:4}  {}\n", golden[*i].0, format_transaction(&golden[*i].1))),
+            Op::Add(j) => s.push_str(&format!("+       {}\n", format_transaction(&actual[*j]))),
+        }
+    }
+
+    Some(s)
+}
+
+/// Format a sequence of expectations in the text format
+/// The output of this can be read back using `parse_expectations`
+pub fn format_expectations(transactions: &[MockTransaction]) -> String {
+    let mut s = String::new();
+
+    for t in transactions {
+        s.push_str(&format_transaction(t));
+        s.push('\n');
+    }
+
+    s
+}
+
+/// Format a single transaction in the text format
+pub(crate) fn format_transaction(t: &MockTransaction) -> String {
+    use MockTransaction::*;
+
+    match t {
+        None => "none".to_string(),
+        SpiWrite(id, prefix, data) => format!("w {}: {} {}", id, prefix_hex(prefix), hex(data)).trim_end().to_string(),
+        SpiWriteAny(id, prefix) => format!("w_any {}: {}", id, prefix_hex(prefix)),
+        SpiWriteStatus(id, prefix, data, status) => {
+            format!("ws {}: {} {} -> {}", id, prefix_hex(prefix), hex(data), hex(status)).trim_end().to_string()
+        },
+        SpiRead(id, prefix, data) => format!("r {}: {} -> {}", id, prefix_hex(prefix), hex(data)).trim_end().to_string(),
+        SpiReadDummy(id, prefix, dummy, data) => format!("rd {}: {} {} -> {}", id, prefix_hex(prefix), dummy, hex(data)).trim_end().to_string(),
+        SpiExec(id, ops) => {
+            let ops: Vec<_> = ops.iter().map(|o| match o {
+                MockExec::SpiWrite(d) => format!("w {}", hex(d)),
+                MockExec::SpiRead(d) => format!("r {}", hex(d)),
+                MockExec::Delay(ms) => format!("d {}", ms),
+            }.trim_end().to_string()).collect();
+            format!("x {}: {}", id, ops.join(", ")).trim_end().to_string()
+        },
+        SpiDevice(id, ops) => {
+            let ops: Vec<_> = ops.iter().map(|o| match o {
+                MockOp::Read(d) => format!("r {}", hex(d)),
+                MockOp::Write(d) => format!("w {}", hex(d)),
+                MockOp::Transfer(w, r) => format!("t {} -> {}", hex(w), hex(r)),
+                MockOp::TransferInPlace(w, r) => format!("ti {} -> {}", hex(w), hex(r)),
+                MockOp::DelayNs(ns) => format!("d {}", ns),
+            }.trim_end().to_string()).collect();
+            format!("dev {}: {}", id, ops.join(", ")).trim_end().to_string()
+        },
+        BeginTransaction(id) => format!("begin {}", id),
+        EndTransaction(id) => format!("end {}", id),
+        SetLanes(id, lanes) => format!("lanes {} {}", id, match lanes {
+            Lanes::Single => "single",
+            Lanes::Dual => "dual",
+            Lanes::Quad => "quad",
+        }),
+        Busy(id, state) => format!("busy {} {}", id, pin_state(state)),
+        Ready(id, state) => format!("ready {} {}", id, pin_state(state)),
+        Reset(id, state) => format!("reset {} {}", id, pin_state(state)),
+        Write(id, data) => format!("write {}: {}", id, hex(data)).trim_end().to_string(),
+        WriteAny(id) => format!("write_any {}", id),
+        Transfer(id, outgoing, incoming) => format!("transfer {}: {} -> {}", id, hex(outgoing), hex(incoming)).trim_end().to_string(),
+        TransferTx(id, outgoing) => format!("transfer_tx {}: {}", id, hex(outgoing)).trim_end().to_string(),
+        TransferAbort(id, outgoing, partial, e) => {
+            format!("transfer_abort {}: {} -> {}", id, hex(outgoing), format!("{} {}", hex(partial), error_name(e)).trim_start())
+        },
+        WriteRead(id, outgoing, incoming) => format!("write_read {}: {} -> {}", id, hex(outgoing), hex(incoming)).trim_end().to_string(),
+        IsHigh(id, v) => format!("is_high {} {}", id, v),
+        IsLow(id, v) => format!("is_low {} {}", id, v),
+        SetHigh(id) => format!("set_high {}", id),
+        SetLow(id) => format!("set_low {}", id),
+        DelayMs(v) => format!("delay_ms {}", v),
+        DelayMsAtLeast(v) => format!("delay_ms_at_least {}", v),
+        DelayMsAtMost(v) => format!("delay_ms_at_most {}", v),
+        DelayMsRange(min, max) => format!("delay_ms_range {} {}", min, max),
+        DelayUs(v) => format!("delay_us {}", v),
+        User(name) => format!("user {}", name),
+        Inject(id, Option::None, e) => format!("inject {} {}", id, failure(e)),
+        Inject(_, Some(call), e) => format!("fail {} = {}", failure(e), format_transaction(call)),
+    }
+}
+
+fn parse_line(l: &str) -> Result<MockTransaction, String> {
+    // Split the command from the remainder of the line
+    let (cmd, rest) = match l.find(char::is_whitespace) {
+        Some(i) => (&l[..i], l[i..].trim()),
+        None => (l, ""),
+    };
+
+    let t = match cmd {
+        "none" => MockTransaction::None,
+        "w" => {
+            let (id, body) = parse_buffers(rest)?;
+            let (prefix, data) = parse_prefixed(body)?;
+            MockTransaction::SpiWrite(id, prefix, data)
+        },
+        "w_any" => {
+            let (id, body) = parse_buffers(rest)?;
+            match parse_prefixed(body)? {
+                (prefix, ref data) if data.is_empty() => MockTransaction::SpiWriteAny(id, prefix),
+                _ => return Err("unexpected data after wildcard write prefix".to_string()),
+            }
+        },
+        "ws" => {
+            let (id, body) = parse_buffers(rest)?;
+            let (outgoing, status) = split_arrow(body)?;
+            let (prefix, data) = parse_prefixed(outgoing)?;
+            MockTransaction::SpiWriteStatus(id, prefix, data, parse_hex(status)?)
+        },
+        "r" => {
+            let (id, body) = parse_buffers(rest)?;
+            let (outgoing, incoming) = split_arrow(body)?;
+            let (prefix, data) = parse_prefixed(outgoing)?;
+            if !data.is_empty() {
+                return Err("unexpected data before '->' in read".to_string());
+            }
+            MockTransaction::SpiRead(id, prefix, parse_hex(incoming)?)
+        },
+        "rd" => {
+            let (id, body) = parse_buffers(rest)?;
+            let (outgoing, incoming) = split_arrow(body)?;
+            let mut args = outgoing.split_whitespace();
+            let (prefix, dummy) = match (args.next(), args.next(), args.next()) {
+                (Some(p), Some(d), Option::None) => (p, d),
+                _ => return Err("expected '<prefix> <dummy count>' before '->' in dummy read".to_string()),
+            };
+            let prefix = match prefix {
+                "-" => vec![],
+                _ => parse_hex(prefix)?,
+            };
+            let dummy = dummy.parse().map_err(|_| format!("invalid dummy count '{}'", dummy) )?;
+            MockTransaction::SpiReadDummy(id, prefix, dummy, parse_hex(incoming)?)
+        },
+        "x" => {
+            let (id, body) = parse_buffers(rest)?;
+            let mut ops = vec![];
+            for o in body.split(',').map(|o| o.trim() ).filter(|o| !o.is_empty() ) {
+                let (kind, data) = match o.find(char::is_whitespace) {
+                    Some(i) => (&o[..i], &o[i..]),
+                    None => (o, ""),
+                };
+                ops.push(match kind {
+                    "w" => MockExec::SpiWrite(parse_hex(data)?),
+                    "r" => MockExec::SpiRead(parse_hex(data)?),
+                    "d" => MockExec::Delay(data.trim().parse().map_err(|_| format!("invalid delay '{}'", data.trim()) )?),
+                    _ => return Err(format!("unrecognised exec operation '{}'", kind)),
+                });
+            }
+            MockTransaction::SpiExec(id, ops)
+        },
+        "dev" => {
+            let (id, body) = parse_buffers(rest)?;
+            let mut ops = vec![];
+            for o in body.split(',').map(|o| o.trim() ).filter(|o| !o.is_empty() ) {
+                let (kind, data) = match o.find(char::is_whitespace) {
+                    Some(i) => (&o[..i], &o[i..]),
+                    None => (o, ""),
+                };
+                ops.push(match kind {
+                    "w" => MockOp::Write(parse_hex(data)?),
+                    "r" => MockOp::Read(parse_hex(data)?),
+                    "t" | "ti" => {
+                        let (outgoing, incoming) = split_arrow(data)?;
+                        let (outgoing, incoming) = (parse_hex(outgoing)?, parse_hex(incoming)?);
+                        if kind == "t" { MockOp::Transfer(outgoing, incoming) } else { MockOp::TransferInPlace(outgoing, incoming) }
+                    },
+                    "d" => MockOp::DelayNs(data.trim().parse().map_err(|_| format!("invalid delay '{}'", data.trim()) )?),
+                    _ => return Err(format!("unrecognised device operation '{}'", kind)),
+                });
+            }
+            MockTransaction::SpiDevice(id, ops)
+        },
+        "write" => {
+            let (id, body) = parse_buffers(rest)?;
+            MockTransaction::Write(id, parse_hex(body)?)
+        },
+        "transfer" => {
+            let (id, body) = parse_buffers(rest)?;
+            let (outgoing, incoming) = split_arrow(body)?;
+            MockTransaction::Transfer(id, parse_hex(outgoing)?, parse_hex(incoming)?)
+        },
+        "write_read" => {
+            let (id, body) = parse_buffers(rest)?;
+            let (outgoing, incoming) = split_arrow(body)?;
+            MockTransaction::WriteRead(id, parse_hex(outgoing)?, parse_hex(incoming)?)
+        },
+        "transfer_tx" => {
+            let (id, body) = parse_buffers(rest)?;
+            MockTransaction::TransferTx(id, parse_hex(body)?)
+        },
+        "transfer_abort" => {
+            let (id, body) = parse_buffers(rest)?;
+            let (outgoing, incoming) = split_arrow(body)?;
+            let (partial, e) = match incoming.trim().rsplit_once(char::is_whitespace) {
+                Some((partial, e)) => (partial, e),
+                None => ("", incoming.trim()),
+            };
+            MockTransaction::TransferAbort(id, parse_hex(outgoing)?, parse_hex(partial)?, parse_error(e)?)
+        },
+        "busy" | "ready" | "reset" => {
+            let (id, v) = parse_args(rest)?;
+            let state = match v {
+                "high" => PinState::High,
+                "low" => PinState::Low,
+                _ => return Err(format!("invalid pin state '{}' (expected high or low)", v)),
+            };
+            match cmd {
+                "busy" => MockTransaction::Busy(id, state),
+                "ready" => MockTransaction::Ready(id, state),
+                _ => MockTransaction::Reset(id, state),
+            }
+        },
+        "is_high" | "is_low" => {
+            let (id, v) = parse_args(rest)?;
+            let v = v.parse::<bool>().map_err(|_| format!("invalid value '{}' (expected true or false)", v) )?;
+            match cmd {
+                "is_high" => MockTransaction::IsHigh(id, v),
+                _ => MockTransaction::IsLow(id, v),
+            }
+        },
+        "lanes" => {
+            let (id, v) = parse_args(rest)?;
+            let lanes = match v {
+                "single" => Lanes::Single,
+                "dual" => Lanes::Dual,
+                "quad" => Lanes::Quad,
+                _ => return Err(format!("invalid lanes '{}' (expected single, dual or quad)", v)),
+            };
+            MockTransaction::SetLanes(id, lanes)
+        },
+        "user" if !rest.is_empty() => MockTransaction::User(rest.to_string()),
+        "inject" => {
+            let (id, e) = match rest.find(char::is_whitespace) {
+                Some(i) => (parse_id(&rest[..i])?, &rest[i..]),
+                None => return Err(format!("expected '<id> <error>', found '{}'", rest)),
+            };
+            MockTransaction::Inject(id, Option::None, parse_failure(e)?)
+        },
+        "fail" => {
+            let (e, call) = match rest.find('=') {
+                Some(i) => (rest[..i].trim(), rest[i+1..].trim()),
+                None => return Err("missing '=' between failure and call".to_string()),
+            };
+            let call = parse_line(call)?;
+            if call.id().is_none() {
+                return Err(format!("failing call '{}' has no peripheral", format_transaction(&call)));
+            }
+            MockTransaction::fail(call, parse_failure(e)?)
+        },
+        "write_any" => MockTransaction::WriteAny(parse_id(rest)?),
+        "begin" => MockTransaction::BeginTransaction(parse_id(rest)?),
+        "end" => MockTransaction::EndTransaction(parse_id(rest)?),
+        "set_high" => MockTransaction::SetHigh(parse_id(rest)?),
+        "set_low" => MockTransaction::SetLow(parse_id(rest)?),
+        "delay_ms" => MockTransaction::DelayMs(rest.parse().map_err(|_| format!("invalid delay '{}'", rest) )?),
+        "delay_us" => MockTransaction::DelayUs(rest.parse().map_err(|_| format!("invalid delay '{}'", rest) )?),
+        "delay_ms_at_least" => MockTransaction::DelayMsAtLeast(rest.parse().map_err(|_| format!("invalid delay '{}'", rest) )?),
+        "delay_ms_at_most" => MockTransaction::DelayMsAtMost(rest.parse().map_err(|_| format!("invalid delay '{}'", rest) )?),
+        "delay_ms_range" => {
+            let mut v = rest.split_whitespace().map(|v| v.parse().map_err(|_| format!("invalid delay '{}'", v) ));
+            match (v.next(), v.next(), v.next()) {
+                (Some(min), Some(max), None) => MockTransaction::DelayMsRange(min?, max?),
+                _ => return Err(format!("invalid delay range '{}' (expected <min> <max>)", rest)),
+            }
+        },
+        _ => return Err(format!("unrecognised transaction '{}'", cmd)),
+    };
+
+    Ok(t)
+}
+
+/// Parse an `<id>: <body>` pair
+fn parse_buffers(s: &str) -> Result<(Id, &str), String> {
+    match s.find(':') {
+        Some(i) => Ok((parse_id(&s[..i])?, s[i+1..].trim())),
+        None => Err("missing ':' after peripheral id".to_string()),
+    }
+}
+
+/// Parse an `<id> <value>` pair
+fn parse_args(s: &str) -> Result<(Id, &str), String> {
+    let mut args = s.split_whitespace();
+    match (args.next(), args.next(), args.next()) {
+        (Some(id), Some(v), None) => Ok((parse_id(id)?, v)),
+        _ => Err(format!("expected '<id> <value>', found '{}'", s)),
+    }
+}
+
+fn parse_id(s: &str) -> Result<Id, String> {
+    let s = s.trim();
+    s.parse().map_err(|_| format!("invalid peripheral id '{}'", s) )
+}
+
+/// Split a buffer pair on `->`
+fn split_arrow(s: &str) -> Result<(&str, &str), String> {
+    match s.find("->") {
+        Some(i) => Ok((&s[..i], &s[i+2..])),
+        None => Err("missing '->' between outgoing and incoming data".to_string()),
+    }
+}
+
+/// Parse a prefix token followed by optional data tokens
+fn parse_prefixed(s: &str) -> Result<(Vec<u8>, Vec<u8>), String> {
+    let s = s.trim();
+    let (prefix, data) = match s.find(char::is_whitespace) {
+        Some(i) => (&s[..i], &s[i..]),
+        None => (s, ""),
+    };
+
+    if prefix.is_empty() {
+        return Err("missing prefix (use '-' for an empty prefix)".to_string());
+    }
+
+    let prefix = match prefix {
+        "-" => vec![],
+        _ => parse_hex(prefix)?,
+    };
+
+    Ok((prefix, parse_hex(data)?))
+}
+
+/// Parse whitespace separated hex tokens into a single buffer
+fn parse_hex(s: &str) -> Result<Vec<u8>, String> {
+    let mut v = vec![];
+
+    for t in s.split_whitespace() {
+        if t.len() % 2 != 0 {
+            return Err(format!("odd number of hex digits in '{}'", t));
+        }
+        for i in (0..t.len()).step_by(2) {
+            let b = t.get(i..i+2)
+                .and_then(|b| u8::from_str_radix(b, 16).ok() )
+                .ok_or_else(|| format!("invalid hex '{}'", t) )?;
+            v.push(b);
+        }
+    }
+
+    Ok(v)
+}
+
+fn parse_error(v: &str) -> Result<MockError, String> {
+    match v {
+        "bus" => Ok(MockError::Bus),
+        "gpio" => Ok(MockError::Gpio),
+        "timeout" => Ok(MockError::Timeout),
+        _ => Err(format!("invalid error '{}' (expected bus, gpio or timeout)", v)),
+    }
+}
+
+/// Parse an injected error, with a bare error name taken as an SPI error
+fn parse_failure(e: &str) -> Result<Error<MockError, MockError>, String> {
+    let mut args = e.split_whitespace();
+    let e = match (args.next(), args.next(), args.next()) {
+        (Some("spi"), Some(v), None) => Error::Spi(parse_error(v)?),
+        (Some("pin"), Some(v), None) => Error::Pin(parse_error(v)?),
+        (Some("aborted"), None, None) => Error::Aborted,
+        (Some("unexpected_id"), Some(v), None) => Error::UnexpectedId(v.parse().map_err(|_| format!("invalid id '{}'", v) )?),
+        (Some(v), None, None) => Error::Spi(parse_error(v)?),
+        _ => return Err(format!("invalid failure '{}' (expected spi, pin, aborted or unexpected_id)", e)),
+    };
+    Ok(e)
+}
+
+fn failure(e: &Error<MockError, MockError>) -> String {
+    match e {
+        Error::Spi(e) => format!("spi {}", error_name(e)),
+        Error::Pin(e) => format!("pin {}", error_name(e)),
+        Error::Aborted => "aborted".to_string(),
+        Error::UnexpectedId(i) => format!("unexpected_id {}", i),
+    }
+}
+
+fn error_name(e: &MockError) -> &'static str {
+    match e {
+        MockError::Bus => "bus",
+        MockError::Gpio => "gpio",
+        MockError::Timeout => "timeout",
+    }
+}
+
+fn hex(d: &[u8]) -> String {
+    d.iter().map(|b| format!("{:02X}", b) ).collect()
+}
+
+fn prefix_hex(d: &[u8]) -> String {
+    match d.len() {
+        0 => "-".to_string(),
+        _ => hex(d),
+    }
+}
+
+fn pin_state(s: &PinState) -> &'static str {
+    match s {
+        PinState::High => "high",
+        PinState::Low => "low",
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        let s = "
+            # Example sequence
+            w 0: 0A DEAD
+            r 0: 0A -> BEEF
+            set_high 1      # release CS
+            delay_ms 5
+        ";
+
+        let t = parse_expectations(s).unwrap();
+
+        assert_eq!(t, vec![
+            MockTransaction::SpiWrite(0, vec![0x0A], vec![0xDE, 0xAD]),
+            MockTransaction::SpiRead(0, vec![0x0A], vec![0xBE, 0xEF]),
+            MockTransaction::SetHigh(1),
+            MockTransaction::DelayMs(5),
+        ]);
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let t = vec![
+            MockTransaction::None,
+            MockTransaction::SpiWrite(0, vec![0x0A, 0x0B], vec![0xDE, 0xAD]),
+            MockTransaction::SpiWrite(0, vec![], vec![0x01]),
+            MockTransaction::SpiWrite(0, vec![0x99], vec![]),
+            MockTransaction::SpiWriteStatus(0, vec![0x0A], vec![0xDE, 0xAD], vec![0x80]),
+            MockTransaction::SpiWriteStatus(0, vec![], vec![], vec![]),
+            MockTransaction::SpiRead(0, vec![0x0A], vec![0xBE, 0xEF]),
+            MockTransaction::SpiRead(0, vec![0x0A], vec![]),
+            MockTransaction::SpiReadDummy(0, vec![0x0B, 0x10], 2, vec![0xBE, 0xEF]),
+            MockTransaction::SpiReadDummy(0, vec![], 0, vec![]),
+            MockTransaction::SpiExec(0, vec![MockExec::SpiWrite(vec![0x0A]), MockExec::SpiRead(vec![0xBE, 0xEF])]),
+            MockTransaction::SpiExec(0, vec![MockExec::SpiWrite(vec![0x0A]), MockExec::Delay(5), MockExec::SpiRead(vec![0xBE])]),
+            MockTransaction::SpiExec(0, vec![]),
+            MockTransaction::BeginTransaction(0),
+            MockTransaction::EndTransaction(0),
+            MockTransaction::SetLanes(0, Lanes::Single),
+            MockTransaction::SetLanes(0, Lanes::Dual),
+            MockTransaction::SetLanes(0, Lanes::Quad),
+            MockTransaction::Busy(0, PinState::High),
+            MockTransaction::Ready(0, PinState::Low),
+            MockTransaction::Reset(0, PinState::High),
+            MockTransaction::SpiDevice(0, vec![
+                MockOp::Write(vec![0x0A]), MockOp::Read(vec![0xBE, 0xEF]), MockOp::Transfer(vec![0x01], vec![]),
+                MockOp::TransferInPlace(vec![0x03], vec![0x04]), MockOp::DelayNs(500), MockOp::Write(vec![]),
+            ]),
+            MockTransaction::Write(0, vec![0xAA, 0xBB]),
+            MockTransaction::WriteAny(0),
+            MockTransaction::SpiWriteAny(0, vec![0x80]),
+            MockTransaction::SpiWriteAny(1, vec![]),
+            MockTransaction::Transfer(0, vec![0xAA], vec![0xBB]),
+            MockTransaction::TransferTx(0, vec![0xAA]),
+            MockTransaction::TransferAbort(0, vec![0xAA, 0xBB], vec![0x11], MockError::Bus),
+            MockTransaction::TransferAbort(0, vec![0xAA], vec![], MockError::Timeout),
+            MockTransaction::WriteRead(0, vec![0x0A], vec![0xBE, 0xEF]),
+            MockTransaction::WriteRead(0, vec![0x0A], vec![]),
+            MockTransaction::IsHigh(1, true),
+            MockTransaction::IsLow(1, false),
+            MockTransaction::SetHigh(1),
+            MockTransaction::SetLow(2),
+            MockTransaction::DelayMs(10),
+            MockTransaction::DelayUs(250),
+            MockTransaction::DelayMsAtLeast(10),
+            MockTransaction::DelayMsAtMost(20),
+            MockTransaction::DelayMsRange(10, 20),
+            MockTransaction::Inject(0, Option::None, Error::Spi(MockError::Bus)),
+            MockTransaction::Inject(1, Option::None, Error::Pin(MockError::Gpio)),
+            MockTransaction::Inject(2, Option::None, Error::Spi(MockError::Timeout)),
+            MockTransaction::Inject(2, Option::None, Error::Aborted),
+            MockTransaction::fail(MockTransaction::SpiRead(0, vec![0x0A], vec![]), Error::Spi(MockError::Bus)),
+            MockTransaction::fail(MockTransaction::Busy(0, PinState::Low), Error::Pin(MockError::Gpio)),
+            MockTransaction::fail(MockTransaction::Write(0, vec![0xAA, 0xBB]), Error::Aborted),
+            MockTransaction::fail(MockTransaction::Transfer(0, vec![0xAA], vec![]), Error::UnexpectedId(3)),
+            MockTransaction::User("start".to_string()),
+            MockTransaction::User("end of init".to_string()),
+            MockTransaction::User("step#2".to_string()),
+        ];
+
+        let s = format_expectations(&t);
+        let p = parse_expectations(&s).unwrap();
+
+        assert_eq!(t, p);
+    }
+
+    #[test]
+    fn test_golden_diff() {
+        let golden = parse_with_lines("
+            # Init sequence
+            w 0: 01 00
+            w 0: 02 00
+            delay_ms 5
+            r 0: 03 -> AA
+        ").unwrap();
+
+        let actual = parse_expectations("
+            w 0: 01 00
+            w 0: 02 FF
+            delay_ms 5
+            r 0: 03 -> AA
+        ").unwrap();
+
+        assert_eq!(golden_diff(&golden, &golden.iter().map(|(_, t)| t.clone()).collect::<Vec<_>>()), Option::None);
+
+        let d = golden_diff(&golden, &actual).unwrap();
+        assert_eq!(d, "\
+@@ golden line 3 @@
+     3  w 0: 01 00
+-    4  w 0: 02 00
++       w 0: 02 FF
+     5  delay_ms 5
+     6  r 0: 03 -> AA
+");
+    }
+
+    #[test]
+    fn test_marker_comments() {
+        let t = parse_expectations("
+            # comment
+            user step#2   # trailing comment
+        ").unwrap();
+
+        assert_eq!(t, vec![MockTransaction::User("step#2".to_string())]);
+    }
+
+    #[test]
+    fn test_golden_diff_wildcard() {
+        let golden = parse_with_lines("
+            w_any 0: 80
+            delay_ms_at_least 5
+            write_any 0
+        ").unwrap();
+
+        let actual = vec![
+            MockTransaction::SpiWrite(0, vec![0x80], vec![0x01, 0x02]),
+            MockTransaction::DelayMs(10),
+            MockTransaction::Write(0, vec![0xAA]),
+        ];
+
+        assert_eq!(golden_diff(&golden, &actual), Option::None);
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        let cases = [
+            ("w 0 0A DEAD", 1, "missing ':'"),
+            ("\nw x: 0A DEAD", 2, "invalid peripheral id 'x'"),
+            ("r 0: 0A BEEF", 1, "missing '->'"),
+            ("w 0: 0A DEA", 1, "odd number of hex digits"),
+            ("write 0: ZZ", 1, "invalid hex 'ZZ'"),
+            ("busy 0 maybe", 1, "invalid pin state 'maybe'"),
+            ("is_high 0", 1, "expected '<id> <value>'"),
+            ("delay_ms soon", 1, "invalid delay 'soon'"),
+            ("x 0: q 00", 1, "unrecognised exec operation 'q'"),
+            ("frobnicate 0", 1, "unrecognised transaction 'frobnicate'"),
+        ];
+
+        for (s, line, reason) in cases.iter() {
+            let e = parse_expectations(s).unwrap_err();
+            assert_eq!(e.line, *line, "input: {}", s);
+            assert!(e.reason.starts_with(reason), "input: {} error: {}", s, e);
+        }
+    }
+}
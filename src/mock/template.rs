@@ -0,0 +1,130 @@
+//! Expectation templates
+//! A template is a sequence of `MockTransaction`s containing named placeholder words,
+//! which may be instantiated with concrete values for families of similar tests
+//! (for example, the same register access at different addresses).
+//!
+//! Placeholders are marked with a sentinel word, so the sentinel must be chosen so as
+//! not to collide with literal data in the template.
+
+use std::vec;
+use std::vec::Vec;
+
+use super::{MockTransaction, MockExec, MockOp, Word};
+
+/// Template expectation sequence with named placeholders
+#[derive(Clone, Debug, PartialEq)]
+pub struct Template<W = u8> {
+    txns: Vec<MockTransaction<W>>,
+    placeholders: Vec<(&'static str, W)>,
+}
+
+impl <W: Word> Template<W> {
+    /// Create a new template from a sequence of transactions
+    pub fn new(txns: Vec<MockTransaction<W>>) -> Self {
+        Template{ txns, placeholders: Vec::new() }
+    }
+
+    /// Declare a named placeholder, marked in the template by the provided sentinel word
+    pub fn placeholder(mut self, name: &'static str, sentinel: W) -> Self {
+        self.placeholders.push((name, sentinel));
+        self
+    }
+
+    /// Instantiate the template, replacing each placeholder sentinel with the provided value
+    /// This panics if a placeholder is not bound or an unknown placeholder is provided
+    pub fn instantiate(&self, values: &[(&str, W)]) -> Vec<MockTransaction<W>> {
+        for (name, _) in values {
+            if !self.placeholders.iter().any(|(n, _)| n == name) {
+                panic!("unknown template placeholder '{}'", name);
+            }
+        }
+
+        let subs: Vec<(W, W)> = self.placeholders.iter().map(|(name, sentinel)| {
+            match values.iter().find(|(n, _)| n == name) {
+                Some((_, v)) => (*sentinel, *v),
+                None => panic!("unbound template placeholder '{}'", name),
+            }
+        }).collect();
+
+        let mut txns = self.txns.clone();
+        for t in txns.iter_mut() {
+            for_each_word(t, |w| {
+                if let Some((_, v)) = subs.iter().find(|(s, _)| s == w) {
+                    *w = *v;
+                }
+            });
+        }
+
+        txns
+    }
+}
+
+/// Apply a function to every word contained in a transaction
+fn for_each_word<W, F: FnMut(&mut W)>(t: &mut MockTransaction<W>, mut f: F) {
+    let bufs: Vec<&mut Vec<W>> = match t {
+        MockTransaction::SpiWrite(_, a, b)
+            | MockTransaction::SpiRead(_, a, b)
+            | MockTransaction::SpiReadDummy(_, a, _, b)
+            | MockTransaction::Transfer(_, a, b)
+            | MockTransaction::TransferAbort(_, a, b, _)
+            | MockTransaction::WriteRead(_, a, b) => vec![a, b],
+        MockTransaction::SpiWriteStatus(_, a, b, c) => vec![a, b, c],
+        MockTransaction::Write(_, a) | MockTransaction::TransferTx(_, a) | MockTransaction::SpiWriteAny(_, a) => vec![a],
+        MockTransaction::SpiExec(_, ops) => ops.iter_mut().filter_map(|o| match o {
+            MockExec::SpiWrite(d) | MockExec::SpiRead(d) => Some(d),
+            MockExec::Delay(_) => None,
+        }).collect(),
+        MockTransaction::SpiDevice(_, ops) => ops.iter_mut().flat_map(|o| match o {
+            MockOp::Read(d) | MockOp::Write(d) => vec![d],
+            MockOp::Transfer(a, b) | MockOp::TransferInPlace(a, b) => vec![a, b],
+            MockOp::DelayNs(_) => vec![],
+        }).collect(),
+        MockTransaction::Inject(_, Some(call), _) => return for_each_word(call, f),
+        _ => Vec::new(),
+    };
+
+    for b in bufs {
+        b.iter_mut().for_each(&mut f);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use super::super::Mock;
+
+    #[test]
+    fn test_instantiate() {
+        let mut m = Mock::new();
+        let s = m.spi();
+
+        let t = Template::new(vec![
+            MockTransaction::spi_write(&s, [0xFF], [0xAA, 0xBB]),
+            MockTransaction::spi_read(&s, [0x80], [0xFF]),
+        ]).placeholder("ADDR", 0xFF);
+
+        assert_eq!(t.instantiate(&[("ADDR", 0x0A)]), vec![
+            MockTransaction::spi_write(&s, [0x0A], [0xAA, 0xBB]),
+            MockTransaction::spi_read(&s, [0x80], [0x0A]),
+        ]);
+
+        assert_eq!(t.instantiate(&[("ADDR", 0x1B)]), vec![
+            MockTransaction::spi_write(&s, [0x1B], [0xAA, 0xBB]),
+            MockTransaction::spi_read(&s, [0x80], [0x1B]),
+        ]);
+    }
+
+    #[test]
+    #[should_panic(expected = "unbound template placeholder 'ADDR'")]
+    fn test_unbound_placeholder() {
+        let mut m = Mock::new();
+        let s = m.spi();
+
+        let t = Template::new(vec![
+            MockTransaction::spi_write(&s, [0xFF], []),
+        ]).placeholder("ADDR", 0xFF);
+
+        t.instantiate(&[]);
+    }
+}
@@ -165,6 +165,10 @@ where
             res = match &mut t {
                 Transaction::Write(d) => self.spi.write(d),
                 Transaction::Read(d) =>  self.spi.transfer(d).map(|_r| () ),
+                Transaction::Delay(ms) => {
+                    self.delay.delay_ms(*ms);
+                    Ok(())
+                },
             }.map_err(|e| Error::Spi(e) );
 
             if res.is_err() {